@@ -0,0 +1,278 @@
+//! `--action`: file operations applied to the listed entries.
+
+use std::fs::{self, File, Metadata};
+use std::io::{self, BufRead, IsTerminal, Write};
+use std::path::{Path, PathBuf};
+
+use crate::trash;
+
+/// Operation given with `--action KIND[:DEST]`.
+pub enum Action {
+    Copy(PathBuf),
+    Move(PathBuf),
+    Trash,
+    Delete,
+}
+
+impl Action {
+    pub fn from_string(s: &str) -> Result<Action, String> {
+        match s.split_once(':') {
+            Some(("copy", dest)) if !dest.is_empty() => Ok(Action::Copy(PathBuf::from(dest))),
+            Some(("move", dest)) if !dest.is_empty() => Ok(Action::Move(PathBuf::from(dest))),
+            None if s == "trash" => Ok(Action::Trash),
+            None if s == "delete" => Ok(Action::Delete),
+            _ => Err(format!("'{}' is not a valid action (copy:DEST, move:DEST, trash, delete)", s)),
+        }
+    }
+
+    fn verb(&self) -> &'static str {
+        match self {
+            Action::Copy(_) => "copy",
+            Action::Move(_) => "move",
+            Action::Trash => "trash",
+            Action::Delete => "delete",
+        }
+    }
+
+    /// Whether the action can't be undone and so needs `--force` on top of confirmation.
+    pub fn is_destructive(&self) -> bool {
+        matches!(self, Action::Delete)
+    }
+
+    /// Applies the action to `paths`, keeping their layout below the walk root each
+    /// came from in the destination. Failures are reported as they happen; returns
+    /// whether every entry succeeded.
+    pub fn run(&self, paths: &[&Path], roots: &[PathBuf], dry_run: bool) -> bool {
+        let mut ok = true;
+        // Copied directories get their timestamps back once their contents are in place
+        let mut copied_dirs = Vec::new();
+        // Directories are never acted on as a whole: a moved one is recreated at the
+        // destination, and the source goes once the listed entries in it have, so
+        // nothing unlisted is moved, trashed or deleted along with it
+        let mut emptied_dirs: Vec<&Path> = Vec::new();
+        for &path in paths {
+            let target = match self {
+                Action::Copy(dest) | Action::Move(dest) => match relative_to_roots(path, roots) {
+                    Some(relative) => Some(dest.join(relative)),
+                    None => {
+                        eprintln!("Error: {}: not below a walk root, so it has no place in {}", path.display(), dest.display());
+                        ok = false;
+                        continue;
+                    }
+                },
+                Action::Trash | Action::Delete => None,
+            };
+            if target.as_ref().is_some_and(|target| is_same_file(path, target)) {
+                eprintln!("Error: {}: would be its own destination", path.display());
+                ok = false;
+                continue;
+            }
+            if dry_run {
+                match &target {
+                    Some(target) => eprintln!("would {} {} -> {}", self.verb(), path.display(), target.display()),
+                    None => eprintln!("would {} {}", self.verb(), path.display()),
+                }
+                continue;
+            }
+            let result = fs::symlink_metadata(path).and_then(|meta| {
+                if let Some(parent) = target.as_ref().and_then(|target| target.parent()) {
+                    fs::create_dir_all(parent)?;
+                }
+                match (self, &target) {
+                    (Action::Copy(_), Some(target)) => copy_entry(path, target, &meta)?,
+                    (Action::Move(_), Some(target)) if meta.is_dir() => {
+                        copy_entry(path, target, &meta)?;
+                        emptied_dirs.push(path);
+                    }
+                    (Action::Move(_), Some(target)) => move_entry(path, target, &meta)?,
+                    (Action::Trash | Action::Delete, _) if meta.is_dir() => emptied_dirs.push(path),
+                    (Action::Trash, _) => trash::trash(path)?,
+                    (_, _) => remove_file(path, &meta)?,
+                }
+                if let (Some(target), true) = (&target, meta.is_dir()) {
+                    copied_dirs.push((target.clone(), meta.clone()));
+                }
+                Ok(())
+            });
+            if let Err(e) = result {
+                eprintln!("Error: {}: {}", path.display(), e);
+                ok = false;
+            }
+        }
+        // Deepest first, so that a directory left empty by its subdirectories goes too
+        emptied_dirs.sort_by_key(|dir| std::cmp::Reverse(dir.components().count()));
+        for dir in emptied_dirs {
+            if let Err(e) = remove_emptied_dir(dir, matches!(self, Action::Trash)) {
+                eprintln!("Error: {}: {}", dir.display(), e);
+                ok = false;
+            }
+        }
+        for (dir, meta) in copied_dirs.iter().rev() {
+            if let Err(e) = preserve_attributes(dir, meta) {
+                eprintln!("Error: {}: {}", dir.display(), e);
+                ok = false;
+            }
+        }
+        ok
+    }
+
+    pub fn prompt(&self, count: usize) -> String {
+        match self {
+            Action::Copy(dest) | Action::Move(dest) => {
+                format!("{} {} entries to {}?", capitalize(self.verb()), count, dest.display())
+            }
+            Action::Trash | Action::Delete => format!("{} {} entries?", capitalize(self.verb()), count),
+        }
+    }
+}
+
+fn capitalize(word: &str) -> String {
+    let mut chars = word.chars();
+    chars.next().map(|first| first.to_uppercase().chain(chars).collect()).unwrap_or_default()
+}
+
+/// Asks `question` on the terminal and waits for a yes. Without a terminal to ask on, refuses.
+pub fn confirm(question: &str) -> bool {
+    if !io::stdin().is_terminal() {
+        eprintln!("Error: can't ask for confirmation without a terminal (use --yes)");
+        return false;
+    }
+    eprint!("{} [y/N] ", question);
+    let _ = io::stderr().flush();
+    let mut answer = String::new();
+    if io::stdin().lock().read_line(&mut answer).is_err() {
+        return false;
+    }
+    matches!(answer.trim(), "y" | "Y" | "yes" | "Yes")
+}
+
+/// Where `path` lies below the deepest walk root holding it, going by name alone as
+/// `--stdin` paths needn't exist below any.
+fn relative_to_roots(path: &Path, roots: &[PathBuf]) -> Option<PathBuf> {
+    let absolute = crate::lexically_absolute(path).ok()?;
+    roots.iter()
+        .filter_map(|root| crate::lexically_absolute(root).ok())
+        .filter_map(|root| absolute.strip_prefix(root).ok().map(Path::to_path_buf))
+        .min_by_key(|relative| relative.components().count())
+}
+
+/// Whether `target` names `source` itself, or a link to it.
+fn is_same_file(source: &Path, target: &Path) -> bool {
+    let canonical = |path: &Path| fs::canonicalize(path).ok();
+    let absolute = |path: &Path| crate::lexically_absolute(path).ok();
+    absolute(target).is_some_and(|target| absolute(source) == Some(target))
+        || canonical(target).is_some_and(|target| canonical(source) == Some(target))
+}
+
+/// Removes `dir`, or moves it to the trash alongside its files, if nothing is left in
+/// it. Otherwise warns that it holds unlisted entries.
+fn remove_emptied_dir(dir: &Path, to_trash: bool) -> io::Result<()> {
+    if fs::read_dir(dir)?.next().is_some() {
+        eprintln!("sortfs: warning: {}: left in place, it holds entries that weren't listed", dir.display());
+        return Ok(());
+    }
+    if to_trash {
+        trash::trash(dir)
+    } else {
+        fs::remove_dir(dir)
+    }
+}
+
+fn copy_entry(source: &Path, target: &Path, meta: &Metadata) -> io::Result<()> {
+    if meta.file_type().is_symlink() {
+        copy_symlink(source, target)?;
+    } else if meta.is_dir() {
+        match fs::create_dir(target) {
+            Err(e) if e.kind() != io::ErrorKind::AlreadyExists => return Err(e),
+            _ => {}
+        }
+        return Ok(());
+    } else {
+        fs::copy(source, target)?;
+    }
+    preserve_attributes(target, meta)
+}
+
+fn move_entry(source: &Path, target: &Path, meta: &Metadata) -> io::Result<()> {
+    match fs::rename(source, target) {
+        Err(e) if e.kind() == io::ErrorKind::CrossesDevices => {
+            copy_entry(source, target, meta)?;
+            remove_file(source, meta)
+        }
+        result => result,
+    }
+}
+
+#[cfg(unix)]
+fn copy_symlink(source: &Path, target: &Path) -> io::Result<()> {
+    std::os::unix::fs::symlink(fs::read_link(source)?, target)
+}
+
+// Junctions are recreated as directory symlinks, the only kind std can make
+#[cfg(windows)]
+fn copy_symlink(source: &Path, target: &Path) -> io::Result<()> {
+    use std::os::windows::fs::{symlink_dir, symlink_file, FileTypeExt};
+    let link = fs::read_link(source)?;
+    if fs::symlink_metadata(source)?.file_type().is_symlink_dir() {
+        symlink_dir(link, target)
+    } else {
+        symlink_file(link, target)
+    }
+}
+
+#[cfg(not(any(unix, windows)))]
+fn copy_symlink(source: &Path, target: &Path) -> io::Result<()> {
+    fs::copy(source, target).map(|_| ())
+}
+
+/// Removes a file or link. On Windows, directory symlinks and junctions are
+/// removed like directories, without touching what they point to.
+#[cfg(windows)]
+fn remove_file(path: &Path, meta: &Metadata) -> io::Result<()> {
+    use std::os::windows::fs::FileTypeExt;
+    if meta.file_type().is_symlink_dir() {
+        fs::remove_dir(path)
+    } else {
+        fs::remove_file(path)
+    }
+}
+
+#[cfg(not(windows))]
+fn remove_file(path: &Path, _meta: &Metadata) -> io::Result<()> {
+    fs::remove_file(path)
+}
+
+/// Carries the mode, mtime and, where permitted, the owner over to `target`.
+fn preserve_attributes(target: &Path, meta: &Metadata) -> io::Result<()> {
+    if meta.file_type().is_symlink() {
+        return preserve_owner(target, meta);
+    }
+    fs::set_permissions(target, meta.permissions())?;
+    if let Ok(modified) = meta.modified() {
+        // Directories can't be opened for writing, but a read handle may set times
+        File::open(target)?.set_modified(modified)?;
+    }
+    preserve_owner(target, meta)
+}
+
+#[cfg(unix)]
+fn preserve_owner(target: &Path, meta: &Metadata) -> io::Result<()> {
+    use std::os::unix::ffi::OsStrExt;
+    use std::os::unix::fs::MetadataExt;
+    let path = std::ffi::CString::new(target.as_os_str().as_bytes())
+        .map_err(|_| io::Error::from(io::ErrorKind::InvalidInput))?;
+    // SAFETY: path is a valid NUL-terminated string for the duration of the call
+    if unsafe { libc::lchown(path.as_ptr(), meta.uid(), meta.gid()) } != 0 {
+        let err = io::Error::last_os_error();
+        // Only root may give files away; the copy keeps the current user's ownership then
+        if err.raw_os_error() != Some(libc::EPERM) {
+            return Err(err);
+        }
+    }
+    Ok(())
+}
+
+#[cfg(not(unix))]
+fn preserve_owner(_target: &Path, _meta: &Metadata) -> io::Result<()> {
+    Ok(())
+}
@@ -0,0 +1,45 @@
+//! Labels overlaid on the listing from a `--annotate` TSV file.
+
+use std::collections::HashMap;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+
+/// Path -> label map read from lines of `PATH<TAB>LABEL`.
+///
+/// Relative paths are taken relative to the walk root; `#` starts a comment line.
+pub struct Annotations {
+    labels: HashMap<PathBuf, String>,
+}
+
+impl Annotations {
+    pub fn load(file: &Path) -> io::Result<Annotations> {
+        let content = fs::read_to_string(file)?;
+        let mut labels = HashMap::new();
+        for (number, line) in content.lines().enumerate() {
+            if line.trim().is_empty() || line.starts_with('#') {
+                continue;
+            }
+            match line.split_once('\t') {
+                Some((path, label)) => {
+                    let path = path.trim_end_matches('/');
+                    labels.insert(PathBuf::from(path), label.to_string());
+                }
+                None => {
+                    return Err(io::Error::new(
+                        io::ErrorKind::InvalidData,
+                        format!("{}:{}: expected PATH<TAB>LABEL", file.display(), number + 1),
+                    ));
+                }
+            }
+        }
+        Ok(Annotations { labels })
+    }
+
+    /// Looks up an entry by its walked path, then by its path relative to `root`.
+    pub fn label(&self, path: &Path, root: &Path) -> Option<&str> {
+        self.labels.get(path)
+            .or_else(|| path.strip_prefix(root).ok().and_then(|relative| self.labels.get(relative)))
+            .map(String::as_str)
+    }
+}
@@ -0,0 +1,429 @@
+//! `--archives`: listing the members of zip and tar files as if they were entries of
+//! the walk, as `ARCHIVE!/MEMBER`.
+//!
+//! Only the archive indexes are read: a zip's central directory, and the headers of a
+//! tar. Gzipped tars are decompressed by running `gzip -dc`.
+
+use std::fs::File;
+use std::io::{self, BufReader, Read, Seek, SeekFrom};
+use std::path::Path;
+use std::process::{Command, Stdio};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use crate::entry::Entry;
+use crate::filter::Filters;
+use crate::time;
+
+/// A regular file inside an archive.
+struct Member {
+    name: String,
+    modified: SystemTime,
+}
+
+enum Kind {
+    Zip,
+    Tar,
+    TarGz,
+}
+
+impl Kind {
+    fn of(path: &Path) -> Option<Kind> {
+        let name = path.file_name()?.to_str()?.to_ascii_lowercase();
+        if name.ends_with(".zip") {
+            Some(Kind::Zip)
+        } else if name.ends_with(".tar") {
+            Some(Kind::Tar)
+        } else if name.ends_with(".tar.gz") || name.ends_with(".tgz") {
+            Some(Kind::TarGz)
+        } else {
+            None
+        }
+    }
+}
+
+/// The files inside `archive` that pass `filters`, or none if it isn't a zip or tar
+/// file. Archives that can't be read are reported.
+pub fn members(archive: &Entry, filters: &Filters) -> Vec<Entry> {
+    let members = match Kind::of(archive.path()) {
+        Some(Kind::Zip) => zip_members(archive.path()),
+        Some(Kind::Tar) => File::open(archive.path()).and_then(|file| tar_members(BufReader::new(file))),
+        Some(Kind::TarGz) => gunzipped_tar_members(archive.path()),
+        None => return Vec::new(),
+    };
+    let members = members.unwrap_or_else(|e| {
+        eprintln!("sortfs: warning: {}: {}, skipped", archive.path().display(), e);
+        Vec::new()
+    });
+    members.into_iter()
+        .map(|member| Entry::from_archive_member(archive, &member.name, member.modified))
+        .filter(|member| filters.matches(member))
+        .collect()
+}
+
+fn invalid(message: &str) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, message.to_string())
+}
+
+fn u16_at(bytes: &[u8], offset: usize) -> u16 {
+    u16::from_le_bytes([bytes[offset], bytes[offset + 1]])
+}
+
+fn u32_at(bytes: &[u8], offset: usize) -> u32 {
+    u32::from_le_bytes([bytes[offset], bytes[offset + 1], bytes[offset + 2], bytes[offset + 3]])
+}
+
+fn zip_members(path: &Path) -> io::Result<Vec<Member>> {
+    const END_SIGNATURE: u32 = 0x0605_4b50;
+    const HEADER_SIGNATURE: u32 = 0x0201_4b50;
+    // The end record is 22 bytes, followed by a comment of up to 64 KiB
+    const END_SEARCH: u64 = 22 + 0xffff;
+
+    let mut file = File::open(path)?;
+    let len = file.metadata()?.len();
+    let tail_start = len.saturating_sub(END_SEARCH);
+    file.seek(SeekFrom::Start(tail_start))?;
+    let mut tail = Vec::new();
+    file.read_to_end(&mut tail)?;
+    let end = (0..tail.len().saturating_sub(21)).rev()
+        .find(|&offset| u32_at(&tail, offset) == END_SIGNATURE)
+        .ok_or_else(|| invalid("not a zip file"))?;
+    let count = u16_at(&tail, end + 10);
+    let directory_len = u32_at(&tail, end + 12);
+    let directory_start = u32_at(&tail, end + 16);
+    if count == 0xffff || directory_start == 0xffff_ffff {
+        return Err(invalid("zip64 archives aren't supported"));
+    }
+    // Both come from the file itself, so check them before allocating
+    if u64::from(directory_start) + u64::from(directory_len) > len {
+        return Err(invalid("zip central directory runs past the end of the file"));
+    }
+
+    let mut directory = vec![0; directory_len as usize];
+    file.seek(SeekFrom::Start(directory_start.into()))?;
+    file.read_exact(&mut directory)?;
+    let mut members = Vec::new();
+    let mut offset = 0;
+    for _ in 0..count {
+        if directory.len() < offset + 46 || u32_at(&directory, offset) != HEADER_SIGNATURE {
+            return Err(invalid("corrupt zip central directory"));
+        }
+        let header = &directory[offset..];
+        let name_len = u16_at(header, 28) as usize;
+        let extra_len = u16_at(header, 30) as usize;
+        let comment_len = u16_at(header, 32) as usize;
+        if header.len() < 46 + name_len + extra_len {
+            return Err(invalid("corrupt zip central directory"));
+        }
+        let name = String::from_utf8_lossy(&header[46..46 + name_len]).into_owned();
+        let extra = &header[46 + name_len..46 + name_len + extra_len];
+        let modified = unix_time_field(extra).or_else(|| dos_time(u16_at(header, 14), u16_at(header, 12)));
+        if !name.ends_with('/') {
+            members.push(Member { name, modified: modified.unwrap_or(UNIX_EPOCH) });
+        }
+        offset += 46 + name_len + extra_len + comment_len;
+    }
+    Ok(members)
+}
+
+/// The UTC mtime of the "extended timestamp" extra field that Info-ZIP writes.
+fn unix_time_field(mut extra: &[u8]) -> Option<SystemTime> {
+    while extra.len() >= 4 {
+        let (id, len) = (u16_at(extra, 0), u16_at(extra, 2) as usize);
+        let data = extra.get(4..4 + len)?;
+        if id == 0x5455 && data.len() >= 5 && data[0] & 1 != 0 {
+            return Some(UNIX_EPOCH + Duration::from_secs(u32_at(data, 1).into()));
+        }
+        extra = &extra[4 + len..];
+    }
+    None
+}
+
+/// MS-DOS date and time fields, which hold local time with two-second precision.
+fn dos_time(date: u16, time: u16) -> Option<SystemTime> {
+    let (year, month, day) = (1980 + i64::from(date >> 9), u32::from((date >> 5) & 0xf), u32::from(date & 0x1f));
+    let (hour, minute, second) = (u32::from(time >> 11), u32::from((time >> 5) & 0x3f), u32::from(time & 0x1f) * 2);
+    if !(1..=12).contains(&month) || day == 0 {
+        return None;
+    }
+    time::local_to_system_time(year, month, day, hour, minute, second)
+}
+
+fn gunzipped_tar_members(path: &Path) -> io::Result<Vec<Member>> {
+    let mut child = Command::new("gzip")
+        .arg("-dc")
+        .arg(path)
+        .stdout(Stdio::piped())
+        .stderr(Stdio::null())
+        .spawn()
+        .map_err(|e| io::Error::new(e.kind(), format!("failed to run gzip: {}", e)))?;
+    let members = tar_members(BufReader::new(child.stdout.take().unwrap()));
+    // Stop gzip early if the tar ended before the compressed data did
+    let _ = child.kill();
+    let status = child.wait()?;
+    match members {
+        Ok(members) if members.is_empty() && !status.success() => Err(invalid("not a gzip file")),
+        result => result,
+    }
+}
+
+/// Octal numeric field of a tar header, NUL or space terminated.
+fn octal(field: &[u8]) -> Option<u64> {
+    let digits = std::str::from_utf8(field).ok()?.trim_matches(|c: char| c == '\0' || c == ' ');
+    u64::from_str_radix(digits, 8).ok()
+}
+
+fn text(field: &[u8]) -> String {
+    let len = field.iter().position(|&byte| byte == 0).unwrap_or(field.len());
+    String::from_utf8_lossy(&field[..len]).into_owned()
+}
+
+/// The most a GNU long-name or pax header may hold; real ones are a few hundred bytes.
+const MAX_EXTENDED_HEADER: u64 = 1 << 20;
+
+fn tar_members(mut reader: impl Read) -> io::Result<Vec<Member>> {
+    let mut members = Vec::new();
+    let mut header = [0u8; 512];
+    // Set by GNU long-name ('L') and pax ('x') headers for the header that follows
+    let mut long_name = None;
+    let mut pax_mtime = None;
+    loop {
+        if reader.read_exact(&mut header).is_err() || header.iter().all(|&byte| byte == 0) {
+            break;
+        }
+        let size = octal(&header[124..136]).ok_or_else(|| invalid("corrupt tar header"))?;
+        let padded = size.div_ceil(512) * 512;
+        match header[156] {
+            b'L' | b'x' => {
+                // The size comes from the file itself, so cap it and let the buffer
+                // grow only as far as the data really goes
+                if size > MAX_EXTENDED_HEADER {
+                    return Err(invalid("tar extended header is too large"));
+                }
+                let mut data = Vec::new();
+                (&mut reader).take(padded).read_to_end(&mut data)?;
+                if (data.len() as u64) < padded {
+                    return Err(invalid("tar extended header runs past the end of the file"));
+                }
+                let data = &data[..size as usize];
+                if header[156] == b'L' {
+                    long_name = Some(text(data));
+                } else {
+                    for (key, value) in pax_records(data) {
+                        match key {
+                            "path" => long_name = Some(value.to_string()),
+                            "mtime" => pax_mtime = value.split('.').next().and_then(|secs| secs.parse::<u64>().ok()),
+                            _ => {}
+                        }
+                    }
+                }
+                continue;
+            }
+            b'0' | b'\0' | b'7' => {
+                let name = long_name.take().unwrap_or_else(|| {
+                    let name = text(&header[0..100]);
+                    match text(&header[345..500]) {
+                        prefix if &header[257..262] == b"ustar" && !prefix.is_empty() => format!("{}/{}", prefix, name),
+                        _ => name,
+                    }
+                });
+                let mtime = pax_mtime.take().or_else(|| octal(&header[136..148])).unwrap_or(0);
+                let modified = UNIX_EPOCH.checked_add(Duration::from_secs(mtime)).unwrap_or(UNIX_EPOCH);
+                members.push(Member { name, modified });
+            }
+            _ => {
+                long_name = None;
+                pax_mtime = None;
+            }
+        }
+        io::copy(&mut (&mut reader).take(padded), &mut io::sink())?;
+    }
+    Ok(members)
+}
+
+/// `LEN KEY=VALUE\n` records of a pax extended header.
+fn pax_records(data: &[u8]) -> Vec<(&str, &str)> {
+    let mut records = Vec::new();
+    let mut rest = data;
+    while let Some(space) = rest.iter().position(|&byte| byte == b' ') {
+        let len: usize = match std::str::from_utf8(&rest[..space]).ok().and_then(|len| len.parse().ok()) {
+            Some(len) if len > space && len <= rest.len() => len,
+            _ => break,
+        };
+        if let Ok(record) = std::str::from_utf8(&rest[space + 1..len]) {
+            if let Some((key, value)) = record.trim_end_matches('\n').split_once('=') {
+                records.push((key, value));
+            }
+        }
+        rest = &rest[len..];
+    }
+    records
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::{env, fs};
+
+    fn tar_header(name: &str, kind: u8, size: u64, mtime: u64) -> Vec<u8> {
+        let mut header = vec![0u8; 512];
+        header[..name.len()].copy_from_slice(name.as_bytes());
+        header[124..136].copy_from_slice(format!("{:011o}\0", size).as_bytes());
+        header[136..148].copy_from_slice(format!("{:011o}\0", mtime).as_bytes());
+        header[156] = kind;
+        header[257..263].copy_from_slice(b"ustar\0");
+        header
+    }
+
+    fn tar_data(data: &[u8]) -> Vec<u8> {
+        let mut block = data.to_vec();
+        block.resize(data.len().div_ceil(512) * 512, 0);
+        block
+    }
+
+    fn names(members: &[Member]) -> Vec<&str> {
+        members.iter().map(|member| member.name.as_str()).collect()
+    }
+
+    #[test]
+    fn tar_names_and_times() {
+        let long = format!("{}/long.txt", "d".repeat(120));
+        let pax = "23 path=pax/member.txt\n20 mtime=2000000000\n";
+        let tar = [
+            tar_header("a.txt", b'0', 3, 1000),
+            tar_data(b"abc"),
+            tar_header("dir/", b'5', 0, 0),
+            tar_header("././@LongLink", b'L', long.len() as u64, 0),
+            tar_data(long.as_bytes()),
+            tar_header("truncated", b'0', 0, 0),
+            tar_header("PaxHeaders/x", b'x', pax.len() as u64, 0),
+            tar_data(pax.as_bytes()),
+            tar_header("ignored.txt", b'0', 0, 5),
+            vec![0; 1024],
+        ].concat();
+        let members = tar_members(&tar[..]).unwrap();
+        assert_eq!(names(&members), ["a.txt", long.as_str(), "pax/member.txt"]);
+        assert_eq!(members[0].modified, UNIX_EPOCH + Duration::from_secs(1000));
+        assert_eq!(members[2].modified, UNIX_EPOCH + Duration::from_secs(2_000_000_000));
+    }
+
+    #[test]
+    fn tar_without_end_blocks() {
+        assert!(tar_members(&[][..]).unwrap().is_empty());
+        let tar = [tar_header("a", b'0', 1, 0), tar_data(b"a")].concat();
+        assert_eq!(names(&tar_members(&tar[..]).unwrap()), ["a"]);
+    }
+
+    #[test]
+    fn tar_rejects_malformed_headers() {
+        let is_invalid = |tar: &[u8]| matches!(tar_members(tar), Err(e) if e.kind() == io::ErrorKind::InvalidData);
+
+        let mut corrupt = tar_header("a", b'0', 0, 0);
+        corrupt[124..136].copy_from_slice(b"zzzzzzzzzzz\0");
+        assert!(is_invalid(&corrupt));
+
+        // Neither may allocate what the header claims
+        assert!(is_invalid(&tar_header("huge", b'L', 0o77_777_777_777, 0)));
+        assert!(is_invalid(&[tar_header("huge", b'x', MAX_EXTENDED_HEADER + 1, 0), vec![b'a'; 512]].concat()));
+
+        let truncated = [tar_header("short", b'x', 1000, 0), vec![b'a'; 600]].concat();
+        assert!(is_invalid(&truncated));
+        assert!(is_invalid(&tar_header("empty", b'L', 1, 0)));
+    }
+
+    #[test]
+    fn pax_record_lengths() {
+        assert_eq!(pax_records(b"11 path=ab\n12 mtime=1.5\n"), [("path", "ab"), ("mtime", "1.5")]);
+        assert!(pax_records(b"99 path=ab\n").is_empty());
+        assert!(pax_records(b"2 path=ab\n").is_empty());
+        assert!(pax_records(b"x path=ab\n").is_empty());
+        assert_eq!(pax_records(b"11 path=ab\n5 tail"), [("path", "ab")]);
+    }
+
+    struct ZipEntry<'a> {
+        name: &'a str,
+        date: u16,
+        time: u16,
+        extra: &'a [u8],
+    }
+
+    fn zip(entries: &[ZipEntry], count: u16, start_offset: u32, len_offset: u32) -> Vec<u8> {
+        let mut directory = Vec::new();
+        for entry in entries {
+            let mut header = vec![0u8; 46];
+            header[0..4].copy_from_slice(&0x0201_4b50u32.to_le_bytes());
+            header[12..14].copy_from_slice(&entry.time.to_le_bytes());
+            header[14..16].copy_from_slice(&entry.date.to_le_bytes());
+            header[28..30].copy_from_slice(&(entry.name.len() as u16).to_le_bytes());
+            header[30..32].copy_from_slice(&(entry.extra.len() as u16).to_le_bytes());
+            directory.extend(header);
+            directory.extend(entry.name.as_bytes());
+            directory.extend(entry.extra);
+        }
+        // Local file data isn't read, so some filler stands in for it
+        let mut file = vec![0u8; 30];
+        let start = file.len() as u32;
+        let len = directory.len() as u32;
+        file.extend(directory);
+        let mut end = vec![0u8; 22];
+        end[0..4].copy_from_slice(&0x0605_4b50u32.to_le_bytes());
+        end[10..12].copy_from_slice(&count.to_le_bytes());
+        end[12..16].copy_from_slice(&len.wrapping_add(len_offset).to_le_bytes());
+        end[16..20].copy_from_slice(&start.wrapping_add(start_offset).to_le_bytes());
+        file.extend(end);
+        file
+    }
+
+    fn read_zip(bytes: &[u8]) -> io::Result<Vec<Member>> {
+        use std::sync::atomic::{AtomicUsize, Ordering};
+        static READ: AtomicUsize = AtomicUsize::new(0);
+        let number = READ.fetch_add(1, Ordering::Relaxed);
+        let file = env::temp_dir().join(format!("sortfs-zip-{}-{}.zip", std::process::id(), number));
+        fs::write(&file, bytes).unwrap();
+        let members = zip_members(&file);
+        fs::remove_file(&file).unwrap();
+        members
+    }
+
+    #[test]
+    fn zip_names_and_times() {
+        let mut extended = vec![0x55, 0x54, 5, 0, 1];
+        extended.extend(1_600_000_000u32.to_le_bytes());
+        let entries = [
+            ZipEntry { name: "a/", date: 0, time: 0, extra: &[] },
+            ZipEntry { name: "a/b.txt", date: 0, time: 0, extra: &extended },
+            // 2020-01-02; invalid DOS dates fall back to the epoch
+            ZipEntry { name: "c.txt", date: (40 << 9) | (1 << 5) | 2, time: 0, extra: &[] },
+            ZipEntry { name: "d.txt", date: 13 << 5, time: 0, extra: &[] },
+        ];
+        let members = read_zip(&zip(&entries, 4, 0, 0)).unwrap();
+        assert_eq!(names(&members), ["a/b.txt", "c.txt", "d.txt"]);
+        assert_eq!(members[0].modified, UNIX_EPOCH + Duration::from_secs(1_600_000_000));
+        assert_eq!(members[1].modified, time::local_to_system_time(2020, 1, 2, 0, 0, 0).unwrap());
+        assert_eq!(members[2].modified, UNIX_EPOCH);
+        assert!(read_zip(&zip(&[], 0, 0, 0)).unwrap().is_empty());
+    }
+
+    #[test]
+    fn zip_rejects_malformed_directories() {
+        let is_invalid = |bytes: &[u8]| matches!(read_zip(bytes), Err(e) if e.kind() == io::ErrorKind::InvalidData);
+        let entry = [ZipEntry { name: "a", date: 0, time: 0, extra: &[] }];
+
+        assert!(is_invalid(b""));
+        assert!(is_invalid(&[0u8; 21]));
+        assert!(is_invalid(b"PK\x03\x04 not really a zip"));
+        assert!(is_invalid(&zip(&entry, 0xffff, 0, 0)));
+        assert!(is_invalid(&zip(&entry, 2, 0, 0)));
+        // The central directory can't claim more than the file holds
+        assert!(is_invalid(&zip(&entry, 1, 0, 0x7fff_ffff)));
+        assert!(is_invalid(&zip(&entry, 1, 1000, 0)));
+        let broken_extra = [ZipEntry { name: "a", date: 0, time: 0, extra: &[0x55, 0x54, 200, 0] }];
+        assert_eq!(names(&read_zip(&zip(&broken_extra, 1, 0, 0)).unwrap()), ["a"]);
+    }
+
+    #[test]
+    fn dos_dates() {
+        assert!(dos_time(0, 0).is_none());
+        assert!(dos_time((1 << 5) | 31, 0).is_some());
+        assert!(dos_time(13 << 5 | 1, 0).is_none());
+    }
+}
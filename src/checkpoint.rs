@@ -0,0 +1,271 @@
+//! `--checkpoint` and `--resume`: walk progress saved along the way, so an interrupted
+//! walk of a big tree can pick up where it stopped.
+//!
+//! The walker doesn't say when it is done with a directory, so that is worked out
+//! here: a directory is complete once it has been read and every child it queued
+//! (counted in `filter_entry`) is complete too. A checkpoint holds the complete
+//! directories and the entries listed inside them; a resumed walk lists those
+//! entries again after a stat() and doesn't enter the directories.
+
+use std::collections::{HashMap, HashSet};
+use std::env;
+use std::ffi::OsString;
+use std::fs::{self, File};
+use std::io::{self, BufWriter, Write};
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+use crate::entry::Entry;
+use crate::filter::Filters;
+use crate::json::{self, Value};
+use crate::snapshot;
+
+const FORMAT: &str = "sortfs-checkpoint";
+const VERSION: u64 = 1;
+
+/// How often a walk writes its progress.
+const INTERVAL: Duration = Duration::from_secs(15);
+
+/// Where a walk keeps its progress, and the query it belongs to.
+pub struct Checkpoint {
+    pub file: PathBuf,
+    // The command line, less the options that don't change the listing
+    pub query: String,
+    pub resume: bool,
+}
+
+impl Checkpoint {
+    /// This run's query: the working directory and arguments, without the ones
+    /// that only say how long to walk or whether to resume.
+    pub fn query() -> String {
+        let mut query = env::current_dir().unwrap_or_default().display().to_string();
+        let mut args = env::args().skip(1);
+        while let Some(arg) = args.next() {
+            match arg.as_str() {
+                "--checkpoint" | "--resume" => continue,
+                "--timeout" => {
+                    args.next();
+                    continue;
+                }
+                arg if arg.starts_with("--timeout=") => continue,
+                _ => {}
+            }
+            query.push('\0');
+            query.push_str(&arg);
+        }
+        query
+    }
+
+    /// The checkpoint of walks of `root`, next to the `--since-last` state.
+    pub fn file(root: &Path) -> Option<PathBuf> {
+        let mut file = OsString::from(snapshot::state_file(root)?);
+        file.push(".checkpoint");
+        Some(PathBuf::from(file))
+    }
+
+    /// The complete directories and listed paths of an earlier walk of the same
+    /// query, or nothing when there is none to resume.
+    pub fn load(&self) -> Option<(HashSet<PathBuf>, Vec<PathBuf>)> {
+        let content = match fs::read_to_string(&self.file) {
+            Ok(content) => content,
+            Err(e) if e.kind() == io::ErrorKind::NotFound => return None,
+            Err(e) => {
+                eprintln!("sortfs: warning: {}: {}", self.file.display(), e);
+                return None;
+            }
+        };
+        let mut lines = content.lines();
+        let header = lines.next().and_then(|line| json::parse(line).ok());
+        let header = header.as_ref().filter(|header| {
+            header.get("format").and_then(Value::as_str) == Some(FORMAT)
+                && header.get("version").and_then(Value::as_u64) == Some(VERSION)
+        });
+        if header.and_then(|header| header.get("query")?.as_str()) != Some(&self.query) {
+            eprintln!("sortfs: warning: {} was written for another query, starting over", self.file.display());
+            return None;
+        }
+        let mut complete = HashSet::new();
+        let mut listed = Vec::new();
+        for line in lines {
+            let record = match json::parse(line) {
+                Ok(record) => record,
+                // A checkpoint cut short by a crash ends in a partial line
+                Err(_) => break,
+            };
+            let path = match snapshot::read_path(&record) {
+                Some(path) => path,
+                None => continue,
+            };
+            match record.get("complete") {
+                Some(Value::Bool(true)) => {
+                    complete.insert(path);
+                }
+                _ => listed.push(path),
+            }
+        }
+        Some((complete, listed))
+    }
+
+    /// Writes the entries of `results` inside complete directories, replacing the
+    /// previous checkpoint only once the new one is whole.
+    pub fn save(&self, progress: &Progress, results: &[Entry]) -> io::Result<()> {
+        let complete = progress.complete();
+        let in_complete = |path: &Path| path.ancestors().any(|dir| complete.contains(dir));
+        if let Some(dir) = self.file.parent() {
+            fs::create_dir_all(dir)?;
+        }
+        let mut partial = OsString::from(&self.file);
+        partial.push(".partial");
+        let mut writer = BufWriter::new(File::create(&partial)?);
+        writeln!(writer, "{{\"format\":\"{}\",\"version\":{},\"query\":{}}}", FORMAT, VERSION, json::string(&self.query))?;
+        for dir in &complete {
+            write!(writer, "{{")?;
+            snapshot::write_path(&mut writer, dir)?;
+            writeln!(writer, ",\"complete\":true}}")?;
+        }
+        for entry in results.iter().filter(|entry| in_complete(entry.path())) {
+            write!(writer, "{{")?;
+            snapshot::write_path(&mut writer, entry.path())?;
+            writeln!(writer, "}}")?;
+        }
+        writer.into_inner()?.sync_all()?;
+        fs::rename(&partial, &self.file)
+    }
+
+    pub fn remove(&self) {
+        if let Err(e) = fs::remove_file(&self.file) {
+            if e.kind() != io::ErrorKind::NotFound {
+                eprintln!("sortfs: warning: {}: {}", self.file.display(), e);
+            }
+        }
+    }
+}
+
+/// Entries for the paths listed in a checkpoint, stat()ed again, as the walker
+/// would have described them.
+pub fn restat(paths: &[PathBuf], filters: &Filters, follow_links: bool) -> Vec<Entry> {
+    paths.iter()
+        .filter_map(|path| {
+            let entry = if follow_links { Entry::from_path(path) } else { Entry::from_unfollowed_path(path) };
+            entry.or_else(|| Entry::from_broken_symlink(path))
+        })
+        .filter(|entry| filters.matches(entry))
+        .collect()
+}
+
+#[derive(Default)]
+struct OpenDir {
+    read: bool,
+    // Queued children that aren't complete yet
+    pending: usize,
+    complete_children: Vec<PathBuf>,
+}
+
+#[derive(Default)]
+struct State {
+    open: HashMap<PathBuf, OpenDir>,
+    // Complete directories whose parent isn't
+    complete: HashSet<PathBuf>,
+}
+
+impl State {
+    fn finish(&mut self, path: &Path, is_dir: bool) {
+        if is_dir {
+            if let Some(dir) = self.open.remove(path) {
+                for child in dir.complete_children {
+                    self.complete.remove(&child);
+                }
+            }
+            self.complete.insert(path.to_path_buf());
+        }
+        let parent = match path.parent().and_then(|parent| self.open.get_mut(parent)) {
+            Some(parent) => parent,
+            None => return,
+        };
+        parent.pending = parent.pending.saturating_sub(1);
+        if is_dir {
+            parent.complete_children.push(path.to_path_buf());
+        }
+        if parent.read && parent.pending == 0 {
+            let parent = path.parent().unwrap().to_path_buf();
+            self.finish(&parent, true);
+        }
+    }
+}
+
+/// Which directories of a running walk are complete.
+pub struct Progress {
+    state: Mutex<State>,
+    last_save: Mutex<Instant>,
+}
+
+impl Progress {
+    /// Starts from the directories a resumed checkpoint found complete.
+    pub fn new(complete: HashSet<PathBuf>) -> Progress {
+        Progress {
+            state: Mutex::new(State { open: HashMap::new(), complete }),
+            last_save: Mutex::new(Instant::now()),
+        }
+    }
+
+    pub fn complete(&self) -> HashSet<PathBuf> {
+        self.state.lock().unwrap().complete.clone()
+    }
+
+    pub fn is_complete(&self, path: &Path) -> bool {
+        self.state.lock().unwrap().complete.contains(path)
+    }
+
+    /// `filter_entry` let `path` through: its parent waits for it.
+    pub fn queued(&self, path: &Path) {
+        let mut state = self.state.lock().unwrap();
+        if let Some(parent) = path.parent().and_then(|parent| state.open.get_mut(parent)) {
+            parent.pending += 1;
+        }
+    }
+
+    /// `filter_entry` left out a directory already complete in the checkpoint.
+    pub fn skipped_complete(&self, path: &Path) {
+        let mut state = self.state.lock().unwrap();
+        if let Some(parent) = path.parent().and_then(|parent| state.open.get_mut(parent)) {
+            parent.complete_children.push(path.to_path_buf());
+        }
+    }
+
+    /// The walker handed over a directory it is about to read.
+    pub fn entered(&self, dir: &Path) {
+        self.state.lock().unwrap().open.insert(dir.to_path_buf(), OpenDir::default());
+    }
+
+    /// The walker has queued every child of `dir`.
+    pub fn read(&self, dir: &Path) {
+        let mut state = self.state.lock().unwrap();
+        let done = match state.open.get_mut(dir) {
+            Some(open) => {
+                open.read = true;
+                open.pending == 0
+            }
+            None => false,
+        };
+        if done {
+            state.finish(dir, true);
+        }
+    }
+
+    /// A non-directory has been listed (or not) for good.
+    pub fn listed(&self, path: &Path) {
+        self.state.lock().unwrap().finish(path, false);
+    }
+
+    /// Whether a checkpoint is due, claiming it for the caller if so.
+    pub fn save_due(&self) -> bool {
+        match self.last_save.try_lock() {
+            Ok(mut last_save) if last_save.elapsed() >= INTERVAL => {
+                *last_save = Instant::now();
+                true
+            }
+            _ => false,
+        }
+    }
+}
@@ -0,0 +1,70 @@
+//! `--exclude-device` and `--include-device`: walks limited by the filesystem that
+//! entries live on, named by device number or by what is mounted.
+
+#[cfg(target_os = "linux")]
+use std::fs;
+use std::fs::Metadata;
+
+/// The device a stat()ed entry lives on.
+#[cfg(unix)]
+pub fn of(meta: &Metadata) -> Option<u64> {
+    use std::os::unix::fs::MetadataExt;
+    Some(meta.dev())
+}
+
+#[cfg(not(unix))]
+pub fn of(_meta: &Metadata) -> Option<u64> {
+    None
+}
+
+/// Device ids (st_dev) for `spec`: a raw device number, `MAJOR:MINOR`, or a mount
+/// source such as `/dev/sdb1` or `server:/export`, which may be mounted several times.
+pub fn resolve(spec: &str) -> Result<Vec<u64>, String> {
+    if let Ok(id) = spec.parse::<u64>() {
+        return Ok(vec![id]);
+    }
+    if let Some(id) = major_minor(spec) {
+        return Ok(vec![id]);
+    }
+    let ids = mount_source_devices(spec);
+    if ids.is_empty() {
+        return Err(format!("'{}' is not a device number and no mounted filesystem comes from it", spec));
+    }
+    Ok(ids)
+}
+
+#[cfg(target_os = "linux")]
+fn major_minor(spec: &str) -> Option<u64> {
+    let (major, minor) = spec.split_once(':')?;
+    Some(libc::makedev(major.parse().ok()?, minor.parse().ok()?))
+}
+
+#[cfg(not(target_os = "linux"))]
+fn major_minor(_spec: &str) -> Option<u64> {
+    None
+}
+
+#[cfg(target_os = "linux")]
+fn mount_source_devices(source: &str) -> Vec<u64> {
+    // Fields: id, parent id, MAJOR:MINOR, root, mount point, options, optional
+    // fields, "-", type, source (with octal escapes), super options
+    let mountinfo = fs::read_to_string("/proc/self/mountinfo").unwrap_or_default();
+    let mut ids: Vec<u64> = mountinfo.lines()
+        .filter_map(|line| {
+            let (mount, filesystem) = line.split_once(" - ")?;
+            let mount_source = filesystem.split(' ').nth(1)?;
+            if crate::remote::unescape(mount_source) != source {
+                return None;
+            }
+            major_minor(mount.split(' ').nth(2)?)
+        })
+        .collect();
+    ids.sort_unstable();
+    ids.dedup();
+    ids
+}
+
+#[cfg(not(target_os = "linux"))]
+fn mount_source_devices(_source: &str) -> Vec<u64> {
+    Vec::new()
+}
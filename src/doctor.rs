@@ -0,0 +1,182 @@
+use std::env;
+use std::fs::{self, File};
+use std::io::{self, Write};
+use std::path::{Path, PathBuf};
+use std::process;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use lscolors::LsColors;
+
+use crate::filter::{FileTypes, Filters};
+use crate::{build_entries, print_lscolor_path, time, WalkOptions};
+
+/// Outcome of a single `sortfs doctor` check.
+type Check = Result<String, String>;
+
+/// Runs `sortfs doctor`: builds a scratch tree, exercises the walk/sort/filter
+/// pipeline against it and reports what works on this system.
+pub fn run() -> io::Result<()> {
+    let root = env::temp_dir().join(format!("sortfs-doctor-{}", process::id()));
+    let result = create_tree(&root).and_then(|_| report(&root));
+    let _ = fs::remove_dir_all(&root);
+    match result {
+        Ok(true) => Ok(()),
+        Ok(false) => process::exit(1),
+        Err(e) => {
+            eprintln!("Error: could not set up {}: {}", root.display(), e);
+            process::exit(1);
+        }
+    }
+}
+
+fn report(root: &Path) -> io::Result<bool> {
+    let checks: Vec<(&str, Check)> = vec![
+        ("walk", check_walk(root)),
+        ("sort", check_sort(root)),
+        ("ignore", check_ignore(root)),
+        ("type filter", check_types(root)),
+        ("color", check_color()),
+        ("symlinks", check_symlinks(root)),
+        ("local time", check_local_time()),
+        ("threads", Ok(format!("{} walker threads", num_cpus::get()))),
+    ];
+
+    let stdout = io::stdout();
+    let mut handle = stdout.lock();
+    writeln!(handle, "sortfs doctor ({} {})", env::consts::OS, env::consts::ARCH)?;
+    let mut healthy = true;
+    for (name, check) in checks {
+        match check {
+            Ok(detail) => writeln!(handle, "  ok    {:<12} {}", name, detail)?,
+            Err(detail) => {
+                healthy = false;
+                writeln!(handle, "  FAIL  {:<12} {}", name, detail)?;
+            }
+        }
+    }
+    Ok(healthy)
+}
+
+fn create_tree(root: &Path) -> io::Result<()> {
+    fs::create_dir_all(root.join("sub"))?;
+    // A .git directory makes .gitignore rules apply, as in a real repository
+    fs::create_dir_all(root.join(".git"))?;
+    fs::write(root.join(".git/HEAD"), "ref: refs/heads/main\n")?;
+    fs::write(root.join(".gitignore"), "*.log\n")?;
+    fs::write(root.join(".fdignore"), "*.tmp\n")?;
+    fs::write(root.join("ignored.log"), "")?;
+    fs::write(root.join("ignored.tmp"), "")?;
+
+    let day = 24 * 60 * 60;
+    for (name, age) in [("new.txt", 0), ("sub/mid.txt", 10 * day), ("old.txt", 1000 * day)] {
+        let file = File::create(root.join(name))?;
+        file.set_modified(SystemTime::now() - Duration::from_secs(age))?;
+    }
+    Ok(())
+}
+
+fn walk(root: &Path, filters: &Filters) -> Vec<PathBuf> {
+    walk_with(root, filters, &WalkOptions::default())
+}
+
+fn walk_with(root: &Path, filters: &Filters, options: &WalkOptions) -> Vec<PathBuf> {
+    build_entries(filters, options, &root.to_path_buf(), String::new(), None)
+        .into_iter()
+        .map(|entry| entry.path)
+        .filter(|path| path != root)
+        .collect()
+}
+
+fn relative(root: &Path, paths: &[PathBuf]) -> Vec<String> {
+    paths.iter()
+        .filter_map(|path| path.strip_prefix(root).ok())
+        .map(|path| path.display().to_string())
+        .collect()
+}
+
+fn check_walk(root: &Path) -> Check {
+    let found = relative(root, &walk(root, &Filters::default()));
+    for expected in ["new.txt", "old.txt", "sub", "sub/mid.txt"] {
+        if !found.iter().any(|path| path == expected) {
+            return Err(format!("'{}' missing from {:?}", expected, found));
+        }
+    }
+    Ok(format!("{} entries found", found.len()))
+}
+
+fn check_sort(root: &Path) -> Check {
+    let found = relative(root, &walk(root, &Filters::default()));
+    let position = |name: &str| found.iter().position(|path| path == name);
+    match (position("new.txt"), position("sub/mid.txt"), position("old.txt")) {
+        (Some(new), Some(mid), Some(old)) if new < mid && mid < old => Ok("newest first".to_string()),
+        _ => Err(format!("unexpected order {:?}", found)),
+    }
+}
+
+fn check_ignore(root: &Path) -> Check {
+    let found = relative(root, &walk(root, &Filters::default()));
+    let leaked: Vec<&String> = found.iter()
+        .filter(|path| path.ends_with(".log") || path.ends_with(".tmp") || path.starts_with(".git/"))
+        .collect();
+    if leaked.is_empty() {
+        Ok(".gitignore, .fdignore and .git/ respected".to_string())
+    } else {
+        Err(format!("ignored entries listed: {:?}", leaked))
+    }
+}
+
+fn check_types(root: &Path) -> Check {
+    let filters = Filters {
+        file_types: FileTypes::from_values(["d"].iter().copied()),
+        ..Filters::default()
+    };
+    let found = relative(root, &walk(root, &filters));
+    if found.iter().all(|path| path == "sub" || path == ".git") && !found.is_empty() {
+        Ok("directory filter".to_string())
+    } else {
+        Err(format!("--type d returned {:?}", found))
+    }
+}
+
+fn check_color() -> Check {
+    let ls_colors = LsColors::from_string("*.txt=01;32");
+    let mut buffer = Vec::new();
+    print_lscolor_path(&mut buffer, &ls_colors, "sub/mid.txt", false)
+        .map_err(|e| e.to_string())?;
+    if String::from_utf8_lossy(&buffer).contains("\u{1b}[1;32m") {
+        let source = if env::var_os("LS_COLORS").is_some() { "LS_COLORS set" } else { "LS_COLORS unset, defaults used" };
+        Ok(format!("ANSI styling works ({})", source))
+    } else {
+        Err(format!("no escape sequence in {:?}", String::from_utf8_lossy(&buffer)))
+    }
+}
+
+#[cfg(unix)]
+fn check_symlinks(root: &Path) -> Check {
+    std::os::unix::fs::symlink(root.join("sub"), root.join("link"))
+        .map_err(|e| format!("cannot create symlinks: {}", e))?;
+    let options = WalkOptions { follow_links: true, ..WalkOptions::default() };
+    let found = relative(root, &walk_with(root, &Filters::default(), &options));
+    let _ = fs::remove_file(root.join("link"));
+    if found.iter().any(|path| path == "link/mid.txt") {
+        Ok("followed".to_string())
+    } else {
+        Err(format!("link/mid.txt missing from {:?}", found))
+    }
+}
+
+#[cfg(not(unix))]
+fn check_symlinks(_root: &Path) -> Check {
+    Ok("skipped on this platform".to_string())
+}
+
+fn check_local_time() -> Check {
+    match time::parse_datetime("2000-01-01 12:00", SystemTime::now()) {
+        Some(parsed) => {
+            let epoch = parsed.duration_since(UNIX_EPOCH).map_err(|e| e.to_string())?.as_secs();
+            let offset = (epoch as i64 - 946_728_000) / 60;
+            Ok(format!("date parsing works (UTC offset {:+} min)", -offset))
+        }
+        None => Err("could not convert a local date".to_string()),
+    }
+}
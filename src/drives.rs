@@ -0,0 +1,37 @@
+//! `--all-drives`: every fixed drive of a Windows machine, walked together.
+
+use std::io;
+
+/// Roots of the fixed (not removable, network or optical) drives, like `C:\`.
+#[cfg(windows)]
+pub fn fixed_drives() -> io::Result<Vec<String>> {
+    const DRIVE_FIXED: u32 = 3;
+
+    #[link(name = "kernel32")]
+    extern "system" {
+        fn GetLogicalDrives() -> u32;
+        fn GetDriveTypeW(root_path_name: *const u16) -> u32;
+    }
+
+    // SAFETY: takes no arguments and only reports a bitmask
+    let mask = unsafe { GetLogicalDrives() };
+    if mask == 0 {
+        return Err(io::Error::last_os_error());
+    }
+    let drives = (b'A'..=b'Z')
+        .enumerate()
+        .filter(|(bit, _)| mask & (1u32 << bit) != 0)
+        .map(|(_, letter)| format!("{}:\\", letter as char))
+        .filter(|root| {
+            let wide: Vec<u16> = root.encode_utf16().chain(Some(0)).collect();
+            // SAFETY: `wide` is a NUL-terminated UTF-16 string that outlives the call
+            unsafe { GetDriveTypeW(wide.as_ptr()) == DRIVE_FIXED }
+        })
+        .collect();
+    Ok(drives)
+}
+
+#[cfg(not(windows))]
+pub fn fixed_drives() -> io::Result<Vec<String>> {
+    Err(io::Error::new(io::ErrorKind::Unsupported, "--all-drives is only available on Windows"))
+}
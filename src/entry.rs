@@ -0,0 +1,189 @@
+use std::fs::{self, FileType, Metadata};
+use std::path::{Path, PathBuf};
+use std::time::SystemTime;
+
+use ignore::DirEntry;
+
+/// A walk result, detached from the walker so that entries it can't describe
+/// (such as dangling symlinks) can be listed too.
+#[derive(Clone)]
+pub struct Entry {
+    pub path: PathBuf,
+    pub file_type: FileType,
+    pub is_symlink: bool,
+    pub is_broken: bool,
+    pub metadata: Option<Metadata>,
+    pub modified: SystemTime,
+}
+
+impl Entry {
+    /// Wraps a walker entry; `metadata` is None when stat() was skipped or failed.
+    pub fn from_dir_entry(entry: DirEntry, metadata: Option<Metadata>) -> Option<Entry> {
+        let file_type = entry.file_type()?;
+        let modified = metadata.as_ref()
+            .and_then(|meta| meta.modified().ok())
+            .unwrap_or(SystemTime::UNIX_EPOCH); // default to UNIX_EPOCH if error
+        // Unfollowed links keep the symlink type; whether they dangle takes another look
+        let is_broken = file_type.is_symlink() && fs::metadata(entry.path()).is_err();
+        Some(Entry {
+            is_symlink: entry.path_is_symlink(),
+            path: entry.into_path(),
+            file_type,
+            is_broken,
+            metadata,
+            modified,
+        })
+    }
+
+    /// Describes a path found outside the walk, following symlinks like the walker does.
+    pub fn from_path(path: &Path) -> Option<Entry> {
+        let is_symlink = fs::symlink_metadata(path).ok()?.file_type().is_symlink();
+        let metadata = fs::metadata(path).ok()?;
+        Some(Entry {
+            path: path.to_path_buf(),
+            file_type: metadata.file_type(),
+            is_symlink,
+            is_broken: false,
+            modified: metadata.modified().unwrap_or(SystemTime::UNIX_EPOCH),
+            metadata: Some(metadata),
+        })
+    }
+
+    /// Describes a path found outside the walk like the walker does without following
+    /// symlinks: a link is described by itself.
+    pub fn from_unfollowed_path(path: &Path) -> Option<Entry> {
+        let metadata = fs::symlink_metadata(path).ok()?;
+        let is_symlink = metadata.file_type().is_symlink();
+        Some(Entry {
+            path: path.to_path_buf(),
+            file_type: metadata.file_type(),
+            is_symlink,
+            is_broken: is_symlink && fs::metadata(path).is_err(),
+            modified: metadata.modified().unwrap_or(SystemTime::UNIX_EPOCH),
+            metadata: Some(metadata),
+        })
+    }
+
+    /// Describes a symlink whose target doesn't exist, from the link itself.
+    pub fn from_broken_symlink(path: &Path) -> Option<Entry> {
+        let metadata = fs::symlink_metadata(path).ok()?;
+        if !metadata.file_type().is_symlink() || fs::metadata(path).is_ok() {
+            return None;
+        }
+        Some(Entry {
+            path: path.to_path_buf(),
+            file_type: metadata.file_type(),
+            is_symlink: true,
+            is_broken: true,
+            modified: metadata.modified().unwrap_or(SystemTime::UNIX_EPOCH),
+            metadata: Some(metadata),
+        })
+    }
+
+    /// Describes a file inside an archive (`--archives`) as `ARCHIVE!/NAME`. Members
+    /// have no metadata of their own; the archive's file type stands in for theirs.
+    pub fn from_archive_member(archive: &Entry, name: &str, modified: SystemTime) -> Entry {
+        Entry {
+            path: PathBuf::from(format!("{}!/{}", archive.path.display(), name.trim_start_matches('/'))),
+            file_type: archive.file_type,
+            is_symlink: false,
+            is_broken: false,
+            metadata: None,
+            modified,
+        }
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    pub fn is_dir(&self) -> bool {
+        self.file_type.is_dir()
+    }
+
+    /// Name of the entry's type as used in machine-readable output.
+    pub fn kind(&self) -> &'static str {
+        if self.is_symlink {
+            "symlink"
+        } else if self.file_type.is_dir() {
+            "directory"
+        } else if self.file_type.is_file() {
+            "file"
+        } else {
+            "special"
+        }
+    }
+
+    /// Size in bytes for regular files, when the entry was stat()ed.
+    pub fn size(&self) -> Option<u64> {
+        self.metadata.as_ref().filter(|meta| meta.is_file()).map(|meta| meta.len())
+    }
+
+    /// Size in bytes, or with `disk_usage` the space allocated on disk, which differs
+    /// for sparse and compressed files. Unlike `size()`, also measures directories.
+    pub fn measured_size(&self, disk_usage: bool) -> Option<u64> {
+        let meta = self.metadata.as_ref()?;
+        Some(if disk_usage { allocated_size(meta) } else { meta.len() })
+    }
+
+    #[cfg(unix)]
+    pub fn uid(&self) -> Option<u32> {
+        use std::os::unix::fs::MetadataExt;
+        self.metadata.as_ref().map(|meta| meta.uid())
+    }
+
+    #[cfg(not(unix))]
+    pub fn uid(&self) -> Option<u32> {
+        None
+    }
+
+    #[cfg(unix)]
+    pub fn inode(&self) -> Option<u64> {
+        use std::os::unix::fs::MetadataExt;
+        self.metadata.as_ref().map(|meta| meta.ino())
+    }
+
+    #[cfg(not(unix))]
+    pub fn inode(&self) -> Option<u64> {
+        None
+    }
+
+    /// Device and inode, which identify the file whatever path it was reached by.
+    #[cfg(unix)]
+    pub fn file_id(&self) -> Option<(u64, u64)> {
+        use std::os::unix::fs::MetadataExt;
+        self.metadata.as_ref().map(|meta| (meta.dev(), meta.ino()))
+    }
+
+    #[cfg(not(unix))]
+    pub fn file_id(&self) -> Option<(u64, u64)> {
+        None
+    }
+
+    /// Inode change time (ctime), which unlike mtime can't be set by the user.
+    #[cfg(unix)]
+    pub fn changed(&self) -> Option<SystemTime> {
+        use std::convert::TryFrom;
+        use std::os::unix::fs::MetadataExt;
+        let meta = self.metadata.as_ref()?;
+        let secs = u64::try_from(meta.ctime()).ok()?;
+        Some(SystemTime::UNIX_EPOCH + std::time::Duration::new(secs, meta.ctime_nsec() as u32))
+    }
+
+    #[cfg(not(unix))]
+    pub fn changed(&self) -> Option<SystemTime> {
+        None
+    }
+}
+
+#[cfg(unix)]
+pub fn allocated_size(meta: &Metadata) -> u64 {
+    use std::os::unix::fs::MetadataExt;
+    // st_blocks is always counted in 512-byte units, whatever the filesystem's block size
+    meta.blocks() * 512
+}
+
+#[cfg(not(unix))]
+pub fn allocated_size(meta: &Metadata) -> u64 {
+    meta.len()
+}
@@ -0,0 +1,675 @@
+use std::fs::{self, Metadata};
+use std::path::{Path, PathBuf};
+use std::time::SystemTime;
+
+use globset::{GlobBuilder, GlobSet, GlobSetBuilder};
+
+use crate::device;
+use crate::entry::{self, Entry};
+use crate::git::GitPaths;
+use crate::mime;
+use crate::users;
+use crate::xattr;
+
+/// Entry kinds selected with `-t/--type`, modeled after fd's file-type filter.
+#[derive(Clone, Copy, Default)]
+pub struct FileTypes {
+    pub files: bool,
+    pub directories: bool,
+    pub symlinks: bool,
+    pub specials: bool,
+    pub executables_only: bool,
+    pub empty_only: bool,
+}
+
+impl FileTypes {
+    pub fn from_values<'a>(values: impl Iterator<Item = &'a str>) -> FileTypes {
+        let mut file_types = FileTypes::default();
+        for value in values {
+            match value {
+                "f" | "file" => file_types.files = true,
+                "d" | "dir" | "directory" => file_types.directories = true,
+                "l" | "symlink" => file_types.symlinks = true,
+                "s" | "special" => file_types.specials = true,
+                "x" | "executable" => {
+                    file_types.executables_only = true;
+                    file_types.files = true;
+                }
+                "e" | "empty" => file_types.empty_only = true,
+                _ => unreachable!(),
+            }
+        }
+        // "-t e" alone means empty files and empty directories
+        if file_types.empty_only && !file_types.files && !file_types.directories {
+            file_types.files = true;
+            file_types.directories = true;
+        }
+        file_types
+    }
+
+    pub fn all() -> FileTypes {
+        FileTypes {
+            files: true,
+            directories: true,
+            symlinks: true,
+            specials: true,
+            ..FileTypes::default()
+        }
+    }
+
+    // Non-directories can be pruned in filter_entry() without losing any descent
+    pub fn is_dirs_only(&self) -> bool {
+        self.directories && !self.files && !self.symlinks && !self.specials
+    }
+
+    fn should_ignore(&self, entry: &Entry, meta: Option<&Metadata>) -> bool {
+        let file_type = entry.file_type;
+        if entry.is_symlink && !file_type.is_dir() && !file_type.is_file() {
+            return !self.symlinks;
+        }
+        let type_selected = (file_type.is_file() && self.files)
+            || (file_type.is_dir() && self.directories)
+            || (entry.is_symlink && self.symlinks)
+            || (is_special(&file_type) && self.specials);
+        !type_selected
+            || (self.executables_only && !is_executable(&file_type, meta))
+            || (self.empty_only && !is_empty(entry, &file_type, meta))
+    }
+}
+
+#[cfg(unix)]
+fn is_special(file_type: &fs::FileType) -> bool {
+    use std::os::unix::fs::FileTypeExt;
+    file_type.is_socket() || file_type.is_fifo() || file_type.is_char_device() || file_type.is_block_device()
+}
+
+#[cfg(not(unix))]
+fn is_special(_file_type: &fs::FileType) -> bool {
+    false
+}
+
+#[cfg(unix)]
+fn is_executable(file_type: &fs::FileType, meta: Option<&Metadata>) -> bool {
+    use std::os::unix::fs::PermissionsExt;
+    file_type.is_file() && meta.is_some_and(|meta| meta.permissions().mode() & 0o111 != 0)
+}
+
+#[cfg(not(unix))]
+fn is_executable(_file_type: &fs::FileType, _meta: Option<&Metadata>) -> bool {
+    false
+}
+
+fn is_empty(entry: &Entry, file_type: &fs::FileType, meta: Option<&Metadata>) -> bool {
+    if file_type.is_dir() {
+        fs::read_dir(entry.path())
+            .map(|mut dir| dir.next().is_none())
+            .unwrap_or(false)
+    } else {
+        file_type.is_file() && meta.is_some_and(|meta| meta.len() == 0)
+    }
+}
+
+/// Byte-size bound given with `--size`, using fd's `[+-]NUM[UNIT]` syntax.
+#[derive(Clone, Copy)]
+pub enum SizeFilter {
+    Min(u64),
+    Max(u64),
+    Equals(u64),
+}
+
+impl SizeFilter {
+    pub fn from_string(s: &str) -> Result<SizeFilter, String> {
+        let invalid = || format!("'{}' is not a valid size constraint (e.g. +10M, -4k)", s);
+        let lower = s.to_ascii_lowercase();
+        let (sign, rest) = match lower.chars().next() {
+            Some(c @ '+') | Some(c @ '-') => (Some(c), &lower[1..]),
+            _ => (None, &lower[..]),
+        };
+        let split = rest.find(|c: char| !c.is_ascii_digit()).unwrap_or(rest.len());
+        let quantity: u64 = rest[..split].parse().map_err(|_| invalid())?;
+        let multiplier: u64 = match &rest[split..] {
+            "" | "b" => 1,
+            "k" | "kb" => 1000,
+            "ki" | "kib" => 1024,
+            "m" | "mb" => 1000 * 1000,
+            "mi" | "mib" => 1024 * 1024,
+            "g" | "gb" => 1000 * 1000 * 1000,
+            "gi" | "gib" => 1024 * 1024 * 1024,
+            "t" | "tb" => 1000 * 1000 * 1000 * 1000,
+            "ti" | "tib" => 1024 * 1024 * 1024 * 1024,
+            _ => return Err(invalid()),
+        };
+        let bytes = quantity.checked_mul(multiplier).ok_or_else(invalid)?;
+        Ok(match sign {
+            Some('+') => SizeFilter::Min(bytes),
+            Some(_) => SizeFilter::Max(bytes),
+            None => SizeFilter::Equals(bytes),
+        })
+    }
+
+    fn is_within(&self, size: u64) -> bool {
+        match *self {
+            SizeFilter::Min(limit) => size >= limit,
+            SizeFilter::Max(limit) => size <= limit,
+            SizeFilter::Equals(limit) => size == limit,
+        }
+    }
+}
+
+/// Ownership constraint given with `--owner [!]user[:[!]group]`.
+#[derive(Clone, Copy, Default)]
+pub struct OwnerFilter {
+    uid: Option<(u32, bool)>,
+    gid: Option<(u32, bool)>,
+}
+
+impl OwnerFilter {
+    pub fn from_string(s: &str) -> Result<OwnerFilter, String> {
+        let (user, group) = match s.split_once(':') {
+            Some((user, group)) => (user, group),
+            None => (s, ""),
+        };
+        let filter = OwnerFilter {
+            uid: parse_id(user, users::uid_by_name)?,
+            gid: parse_id(group, users::gid_by_name)?,
+        };
+        if filter.uid.is_none() && filter.gid.is_none() {
+            return Err(format!("'{}' names neither a user nor a group", s));
+        }
+        Ok(filter)
+    }
+
+    #[cfg(unix)]
+    fn matches(&self, meta: &Metadata) -> bool {
+        use std::os::unix::fs::MetadataExt;
+        let check = |id: Option<(u32, bool)>, actual: u32| {
+            id.is_none_or(|(id, negated)| (id == actual) != negated)
+        };
+        check(self.uid, meta.uid()) && check(self.gid, meta.gid())
+    }
+
+    #[cfg(not(unix))]
+    fn matches(&self, _meta: &Metadata) -> bool {
+        true
+    }
+}
+
+/// Parses a user or group given by name or numeric id, optionally negated with `!`.
+fn parse_id(s: &str, lookup: fn(&str) -> Option<u32>) -> Result<Option<(u32, bool)>, String> {
+    let (name, negated) = match s.strip_prefix('!') {
+        Some(name) => (name, true),
+        None => (s, false),
+    };
+    if name.is_empty() {
+        return Ok(None);
+    }
+    let id = name.parse().ok()
+        .or_else(|| lookup(name))
+        .ok_or_else(|| format!("unknown user or group '{}'", name))?;
+    Ok(Some((id, negated)))
+}
+
+/// Mode-bit match given with `--perm`, following find(1): `0644` matches exactly,
+/// `-0644` requires all of the bits and `/0222` any of them.
+#[derive(Clone, Copy)]
+pub enum PermFilter {
+    Exact(u32),
+    AllOf(u32),
+    AnyOf(u32),
+}
+
+impl PermFilter {
+    pub fn from_string(s: &str) -> Result<PermFilter, String> {
+        let (constructor, digits): (fn(u32) -> PermFilter, &str) = match s.chars().next() {
+            Some('-') => (PermFilter::AllOf, &s[1..]),
+            Some('/') => (PermFilter::AnyOf, &s[1..]),
+            _ => (PermFilter::Exact, s),
+        };
+        match u32::from_str_radix(digits, 8) {
+            // from_str_radix also takes a leading sign
+            Ok(mode) if mode <= 0o7777 && digits.bytes().all(|b| b.is_ascii_digit()) => Ok(constructor(mode)),
+            _ => Err(format!("'{}' is not an octal permission mode (e.g. 0644, -0200, /0111)", s)),
+        }
+    }
+
+    #[cfg(unix)]
+    fn matches(&self, meta: &Metadata) -> bool {
+        use std::os::unix::fs::PermissionsExt;
+        let mode = meta.permissions().mode() & 0o7777;
+        match *self {
+            PermFilter::Exact(bits) => mode == bits,
+            PermFilter::AllOf(bits) => mode & bits == bits,
+            PermFilter::AnyOf(bits) => bits == 0 || mode & bits != 0,
+        }
+    }
+
+    #[cfg(not(unix))]
+    fn matches(&self, _meta: &Metadata) -> bool {
+        true
+    }
+}
+
+/// Hard link count given with `--links`, following find(1): `+1` matches more
+/// than one link, `-2` fewer than two and `1` exactly one.
+#[derive(Clone, Copy)]
+pub enum LinksFilter {
+    MoreThan(u64),
+    FewerThan(u64),
+    Exactly(u64),
+}
+
+impl LinksFilter {
+    pub fn from_string(s: &str) -> Result<LinksFilter, String> {
+        let (constructor, digits): (fn(u64) -> LinksFilter, &str) = match s.chars().next() {
+            Some('+') => (LinksFilter::MoreThan, &s[1..]),
+            Some('-') => (LinksFilter::FewerThan, &s[1..]),
+            _ => (LinksFilter::Exactly, s),
+        };
+        match digits.parse() {
+            Ok(count) if digits.bytes().all(|b| b.is_ascii_digit()) => Ok(constructor(count)),
+            _ => Err(format!("'{}' is not a link count (e.g. +1, -2, 1)", s)),
+        }
+    }
+
+    #[cfg(unix)]
+    fn matches(&self, meta: &Metadata) -> bool {
+        use std::os::unix::fs::MetadataExt;
+        let links = meta.nlink();
+        match *self {
+            LinksFilter::MoreThan(count) => links > count,
+            LinksFilter::FewerThan(count) => links < count,
+            LinksFilter::Exactly(count) => links == count,
+        }
+    }
+
+    #[cfg(not(unix))]
+    fn matches(&self, _meta: &Metadata) -> bool {
+        true
+    }
+}
+
+/// Checks access for the effective user, the way open(2) would decide.
+#[cfg(unix)]
+fn is_accessible(path: &Path, mode: libc::c_int) -> bool {
+    use std::os::unix::ffi::OsStrExt;
+    let path = match std::ffi::CString::new(path.as_os_str().as_bytes()) {
+        Ok(path) => path,
+        Err(_) => return false,
+    };
+    // SAFETY: path is a valid NUL-terminated string for the duration of the call
+    unsafe { libc::faccessat(libc::AT_FDCWD, path.as_ptr(), mode, libc::AT_EACCESS) == 0 }
+}
+
+#[cfg(unix)]
+fn is_readable(path: &Path, _meta: &Metadata) -> bool {
+    is_accessible(path, libc::R_OK)
+}
+
+#[cfg(unix)]
+fn is_writable(path: &Path, _meta: &Metadata) -> bool {
+    is_accessible(path, libc::W_OK)
+}
+
+#[cfg(not(unix))]
+fn is_readable(_path: &Path, _meta: &Metadata) -> bool {
+    true
+}
+
+#[cfg(not(unix))]
+fn is_writable(_path: &Path, meta: &Metadata) -> bool {
+    !meta.permissions().readonly()
+}
+
+/// Extended attribute condition given with `--has-xattr NAME[=VALUE]`.
+pub struct XattrFilter {
+    name: String,
+    value: Option<Vec<u8>>,
+}
+
+impl XattrFilter {
+    pub fn from_string(s: &str) -> Result<XattrFilter, String> {
+        let (name, value) = match s.split_once('=') {
+            Some((name, value)) => (name, Some(value.as_bytes().to_vec())),
+            None => (s, None),
+        };
+        if name.is_empty() {
+            return Err(format!("'{}' doesn't name an extended attribute", s));
+        }
+        Ok(XattrFilter { name: name.to_string(), value })
+    }
+
+    fn matches(&self, path: &Path) -> bool {
+        match xattr::get(path, &self.name) {
+            Ok(Some(value)) => self.value.as_ref().is_none_or(|expected| *expected == value),
+            _ => false,
+        }
+    }
+}
+
+/// Patterns given with `--glob`, matched against the path relative to the entry's
+/// walk root. They are anchored there, so `*.rs` only matches at the top level and
+/// `**/*.rs` at any depth, unless `--unanchored` prefixes every pattern with `**/`.
+pub struct GlobFilter {
+    roots: Vec<PathBuf>,
+    globs: GlobSet,
+}
+
+impl GlobFilter {
+    pub fn new(roots: &[PathBuf], patterns: &[&str], anchored: bool) -> Result<GlobFilter, String> {
+        let mut globs = GlobSetBuilder::new();
+        for pattern in patterns {
+            let pattern = pattern.trim_start_matches('/');
+            let pattern = if anchored || pattern.starts_with("**/") {
+                pattern.to_string()
+            } else {
+                format!("**/{}", pattern)
+            };
+            let glob = GlobBuilder::new(&pattern)
+                .literal_separator(true)
+                .build()
+                .map_err(|e| format!("invalid --glob pattern: {}", e))?;
+            globs.add(glob);
+        }
+        let globs = globs.build().map_err(|e| e.to_string())?;
+        Ok(GlobFilter { roots: roots.to_vec(), globs })
+    }
+
+    fn matches(&self, path: &Path) -> bool {
+        // Paths outside every root (such as --stdin ones) are matched as given
+        let relative = self.roots.iter().find_map(|root| path.strip_prefix(root).ok()).unwrap_or(path);
+        self.globs.is_match(relative)
+    }
+}
+
+/// Directories given with `--prune`: neither listed nor descended into. Like .gitignore
+/// patterns, a glob without `/` matches the name at any depth, one with `/` the path
+/// relative to the root.
+#[derive(Clone)]
+pub struct PruneGlobs {
+    root: PathBuf,
+    names: GlobSet,
+    paths: GlobSet,
+}
+
+impl PruneGlobs {
+    pub fn new(root: &Path, patterns: &[&str]) -> Result<PruneGlobs, String> {
+        let mut names = GlobSetBuilder::new();
+        let mut paths = GlobSetBuilder::new();
+        for pattern in patterns {
+            let pattern = pattern.trim_end_matches('/');
+            let glob = GlobBuilder::new(pattern.trim_start_matches('/'))
+                .literal_separator(true)
+                .build()
+                .map_err(|e| format!("invalid --prune pattern: {}", e))?;
+            if pattern.contains('/') {
+                paths.add(glob);
+            } else {
+                names.add(glob);
+            }
+        }
+        let build = |set: GlobSetBuilder| set.build().map_err(|e| e.to_string());
+        Ok(PruneGlobs { root: root.to_path_buf(), names: build(names)?, paths: build(paths)? })
+    }
+
+    pub fn prunes(&self, dir: &Path) -> bool {
+        dir.file_name().is_some_and(|name| self.names.is_match(name))
+            || dir.strip_prefix(&self.root).is_ok_and(|relative| self.paths.is_match(relative))
+    }
+}
+
+/// How dangling symlinks are treated (`--broken-symlinks`, `--no-broken-symlinks`).
+#[derive(Clone, Copy, PartialEq, Eq)]
+pub enum BrokenSymlinks {
+    Include,
+    Only,
+    Exclude,
+}
+
+/// Content class selected with `--text` or `--binary`.
+#[derive(Clone, Copy, PartialEq, Eq)]
+pub enum ContentKind {
+    Text,
+    Binary,
+}
+
+impl ContentKind {
+    /// Samples the head of a regular file; `None` for anything else or an unreadable file.
+    pub fn detect(entry: &Entry) -> Option<ContentKind> {
+        if !entry.file_type.is_file() {
+            return None;
+        }
+        match mime::looks_binary(entry.path()) {
+            Ok(true) => Some(ContentKind::Binary),
+            Ok(false) => Some(ContentKind::Text),
+            Err(_) => None,
+        }
+    }
+
+    pub fn name(self) -> &'static str {
+        match self {
+            ContentKind::Text => "text",
+            ContentKind::Binary => "binary",
+        }
+    }
+}
+
+/// All per-entry conditions evaluated in the walker's visitor.
+pub struct Filters {
+    pub file_types: FileTypes,
+    pub glob: Option<GlobFilter>,
+    pub size_limits: Vec<SizeFilter>,
+    // Compare sizes by allocated blocks rather than length (--disk-usage)
+    pub disk_usage: bool,
+    pub changed_after: Option<SystemTime>,
+    pub changed_before: Option<SystemTime>,
+    pub newer_than: Option<SystemTime>,
+    pub owner: Option<OwnerFilter>,
+    pub readable: bool,
+    pub writable: bool,
+    pub perm: Option<PermFilter>,
+    pub links: Option<LinksFilter>,
+    // Devices entries must live on (--include-device); empty for any
+    pub devices: Vec<u64>,
+    pub broken_symlinks: BrokenSymlinks,
+    pub xattrs: Vec<XattrFilter>,
+    pub mime_types: Vec<String>,
+    pub content: Option<ContentKind>,
+    pub git_paths: Vec<GitPaths>,
+}
+
+impl Default for Filters {
+    fn default() -> Filters {
+        Filters {
+            file_types: FileTypes::all(),
+            glob: None,
+            size_limits: Vec::new(),
+            disk_usage: false,
+            changed_after: None,
+            changed_before: None,
+            newer_than: None,
+            owner: None,
+            readable: false,
+            writable: false,
+            perm: None,
+            links: None,
+            devices: Vec::new(),
+            broken_symlinks: BrokenSymlinks::Include,
+            xattrs: Vec::new(),
+            mime_types: Vec::new(),
+            content: None,
+            git_paths: Vec::new(),
+        }
+    }
+}
+
+impl Filters {
+    /// Whether any condition needs a stat() of the entry, beyond the walker's d_type.
+    pub fn needs_metadata(&self) -> bool {
+        self.file_types.executables_only
+            || self.file_types.empty_only
+            || !self.size_limits.is_empty()
+            || self.changed_after.is_some()
+            || self.changed_before.is_some()
+            || self.newer_than.is_some()
+            || self.owner.is_some()
+            || self.readable
+            || self.writable
+            || self.perm.is_some()
+            || self.links.is_some()
+            || !self.devices.is_empty()
+    }
+
+    pub fn matches(&self, entry: &Entry) -> bool {
+        let meta = entry.metadata.as_ref();
+        if self.glob.as_ref().is_some_and(|glob| !glob.matches(entry.path())) {
+            return false;
+        }
+        if !self.git_paths.iter().all(|git_paths| git_paths.contains(entry.path())) {
+            return false;
+        }
+        match self.broken_symlinks {
+            BrokenSymlinks::Only if !entry.is_broken => return false,
+            BrokenSymlinks::Exclude if entry.is_broken => return false,
+            _ => {}
+        }
+        if self.file_types.should_ignore(entry, meta) {
+            return false;
+        }
+        if !self.size_limits.is_empty() {
+            // Like fd, size constraints only ever match regular files
+            let size = match meta {
+                Some(meta) if meta.is_file() && self.disk_usage => entry::allocated_size(meta),
+                Some(meta) if meta.is_file() => meta.len(),
+                _ => return false,
+            };
+            if !self.size_limits.iter().all(|limit| limit.is_within(size)) {
+                return false;
+            }
+        }
+        if let Some(owner) = &self.owner {
+            if !meta.is_some_and(|meta| owner.matches(meta)) {
+                return false;
+            }
+        }
+        if let Some(links) = &self.links {
+            if !meta.is_some_and(|meta| links.matches(meta)) {
+                return false;
+            }
+        }
+        if !self.devices.is_empty() && !meta.and_then(device::of).is_some_and(|dev| self.devices.contains(&dev)) {
+            return false;
+        }
+        if self.readable || self.writable || self.perm.is_some() {
+            let meta = match meta {
+                Some(meta) => meta,
+                None => return false,
+            };
+            if (self.readable && !is_readable(entry.path(), meta))
+                || (self.writable && !is_writable(entry.path(), meta))
+                || self.perm.is_some_and(|perm| !perm.matches(meta))
+            {
+                return false;
+            }
+        }
+        if self.changed_after.is_some() || self.changed_before.is_some() || self.newer_than.is_some() {
+            // Archive members have no metadata, only the time their archive recorded
+            let modified = match meta {
+                Some(meta) => meta.modified().ok(),
+                None => Some(entry.modified).filter(|modified| *modified != SystemTime::UNIX_EPOCH),
+            };
+            let modified = match modified {
+                Some(modified) => modified,
+                None => return false,
+            };
+            if self.changed_after.is_some_and(|limit| modified < limit)
+                || self.changed_before.is_some_and(|limit| modified > limit)
+                || self.newer_than.is_some_and(|reference| modified <= reference)
+            {
+                return false;
+            }
+        }
+        if !self.xattrs.iter().all(|xattr| xattr.matches(entry.path())) {
+            return false;
+        }
+        if !self.mime_types.is_empty() {
+            // Only sniff content once every cheaper condition has passed
+            let detected = if entry.file_type.is_file() {
+                mime::sniff(entry.path()).ok().flatten()
+            } else {
+                None
+            };
+            match detected {
+                Some(detected) if self.mime_types.iter().any(|pattern| mime::matches_pattern(detected, pattern)) => {}
+                _ => return false,
+            }
+        }
+        if let Some(kind) = self.content {
+            if ContentKind::detect(entry) != Some(kind) {
+                return false;
+            }
+        }
+        true
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn size_filter_units_and_signs() {
+        assert!(matches!(SizeFilter::from_string("+10M"), Ok(SizeFilter::Min(10_000_000))));
+        assert!(matches!(SizeFilter::from_string("-4k"), Ok(SizeFilter::Max(4000))));
+        assert!(matches!(SizeFilter::from_string("2KiB"), Ok(SizeFilter::Equals(2048))));
+        assert!(matches!(SizeFilter::from_string("0"), Ok(SizeFilter::Equals(0))));
+        assert!(matches!(SizeFilter::from_string("1ti"), Ok(SizeFilter::Equals(1_099_511_627_776))));
+    }
+
+    #[test]
+    fn size_filter_rejects_malformed() {
+        for s in ["", "+", "-", "k", "+-1", "10x", "1.5M", "10 M", "99999999999999T"] {
+            assert!(SizeFilter::from_string(s).is_err(), "{}", s);
+        }
+    }
+
+    #[test]
+    fn size_filter_bounds_are_inclusive() {
+        assert!(SizeFilter::Min(10).is_within(10));
+        assert!(!SizeFilter::Min(10).is_within(9));
+        assert!(SizeFilter::Max(10).is_within(10));
+        assert!(!SizeFilter::Max(10).is_within(11));
+        assert!(SizeFilter::Equals(10).is_within(10));
+    }
+
+    #[test]
+    fn perm_filter_modes() {
+        assert!(matches!(PermFilter::from_string("0644"), Ok(PermFilter::Exact(0o644))));
+        assert!(matches!(PermFilter::from_string("-0200"), Ok(PermFilter::AllOf(0o200))));
+        assert!(matches!(PermFilter::from_string("/111"), Ok(PermFilter::AnyOf(0o111))));
+        assert!(matches!(PermFilter::from_string("7777"), Ok(PermFilter::Exact(0o7777))));
+        assert!(matches!(PermFilter::from_string("0"), Ok(PermFilter::Exact(0))));
+    }
+
+    #[test]
+    fn perm_filter_rejects_malformed() {
+        for s in ["", "-", "/", "10000", "0648", "+644", "-+644", "//644", "u+x", "0o644"] {
+            assert!(PermFilter::from_string(s).is_err(), "{}", s);
+        }
+    }
+
+    #[test]
+    fn links_filter_counts() {
+        assert!(matches!(LinksFilter::from_string("+1"), Ok(LinksFilter::MoreThan(1))));
+        assert!(matches!(LinksFilter::from_string("-2"), Ok(LinksFilter::FewerThan(2))));
+        assert!(matches!(LinksFilter::from_string("1"), Ok(LinksFilter::Exactly(1))));
+        assert!(matches!(LinksFilter::from_string("0"), Ok(LinksFilter::Exactly(0))));
+        assert!(matches!(LinksFilter::from_string("+18446744073709551615"), Ok(LinksFilter::MoreThan(u64::MAX))));
+    }
+
+    #[test]
+    fn links_filter_rejects_malformed() {
+        for s in ["", "+", "-", "++1", "+-1", "1.5", "one", " 1", "18446744073709551616"] {
+            assert!(LinksFilter::from_string(s).is_err(), "{}", s);
+        }
+    }
+}
@@ -0,0 +1,172 @@
+//! Queries against the enclosing git repository, done by running `git`.
+
+use std::collections::{HashMap, HashSet};
+use std::fs;
+use std::path::{Component, Path, PathBuf};
+use std::process::Command;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+/// A set of repository paths that walked entries can be checked against.
+pub struct GitPaths {
+    root: PathBuf,
+    canonical_root: PathBuf,
+    paths: HashSet<PathBuf>,
+}
+
+impl GitPaths {
+    /// Paths in a given `git status` state: `modified`, `untracked` or `staged`.
+    /// Directories count as matching when anything below them does.
+    pub fn from_status(root: &Path, states: &[&str]) -> Result<GitPaths, String> {
+        let toplevel = toplevel(root)?;
+        let output = git(root, &["status", "--porcelain=v1", "-z", "--untracked-files=all"])?;
+        let mut paths = HashSet::new();
+        let mut records = output.split('\0');
+        while let Some(record) = records.next() {
+            if record.len() < 4 {
+                continue;
+            }
+            let (index, worktree) = (record.as_bytes()[0], record.as_bytes()[1]);
+            if index == b'R' || index == b'C' {
+                // Renames and copies are followed by their source path
+                records.next();
+            }
+            let selected = states.iter().any(|state| match *state {
+                "untracked" => index == b'?',
+                "staged" => !matches!(index, b' ' | b'?' | b'!'),
+                "modified" => matches!(worktree, b'M' | b'D' | b'T'),
+                _ => false,
+            });
+            if selected {
+                insert_with_ancestors(&mut paths, &toplevel, &record[3..]);
+            }
+        }
+        GitPaths::new(root, paths)
+    }
+
+    /// Files tracked in the index (`git ls-files`), and the directories holding them.
+    /// Files a sparse checkout leaves out of the worktree (skip-worktree) don't count,
+    /// so directories holding nothing but those aren't reported as tracked.
+    pub fn tracked(root: &Path) -> Result<GitPaths, String> {
+        let toplevel = toplevel(root)?;
+        // -t prefixes every path with a status tag and a space; S marks skip-worktree
+        let output = git(root, &["ls-files", "-z", "-t", "--full-name"])?;
+        let mut paths = HashSet::new();
+        for record in output.split('\0').filter(|record| record.len() > 2) {
+            let (tag, path) = record.split_at(2);
+            if tag != "S " {
+                insert_with_ancestors(&mut paths, &toplevel, path);
+            }
+        }
+        GitPaths::new(root, paths)
+    }
+
+    fn new(root: &Path, paths: HashSet<PathBuf>) -> Result<GitPaths, String> {
+        let canonical_root = fs::canonicalize(root).map_err(|e| format!("{}: {}", root.display(), e))?;
+        Ok(GitPaths {
+            root: root.to_path_buf(),
+            canonical_root,
+            paths,
+        })
+    }
+
+    pub fn contains(&self, path: &Path) -> bool {
+        match path.strip_prefix(&self.root) {
+            Ok(relative) => self.paths.contains(&self.canonical_root.join(relative)),
+            Err(_) => self.paths.contains(path),
+        }
+    }
+}
+
+/// Time of the latest commit touching each tracked path; for a directory, the
+/// latest commit touching anything below it.
+pub struct CommitTimes {
+    root: PathBuf,
+    canonical_root: PathBuf,
+    times: HashMap<PathBuf, SystemTime>,
+}
+
+impl CommitTimes {
+    pub fn load(root: &Path) -> Result<CommitTimes, String> {
+        let toplevel = toplevel(root)?;
+        // Commits come newest first, so the first time seen for a path is its latest
+        let output = git(root, &["-c", "core.quotePath=false", "log", "--format=%x00%ct", "--name-only", "--no-renames"])?;
+        let mut times = HashMap::new();
+        let mut time = UNIX_EPOCH;
+        for line in output.lines().filter(|line| !line.is_empty()) {
+            if let Some(epoch) = line.strip_prefix('\0') {
+                time = UNIX_EPOCH + Duration::from_secs(epoch.parse().unwrap_or(0));
+                continue;
+            }
+            let path = toplevel.join(line);
+            for ancestor in path.ancestors() {
+                if times.contains_key(ancestor) {
+                    break;
+                }
+                times.insert(ancestor.to_path_buf(), time);
+                if ancestor == toplevel {
+                    break;
+                }
+            }
+        }
+        let canonical_root = fs::canonicalize(root).map_err(|e| format!("{}: {}", root.display(), e))?;
+        Ok(CommitTimes { root: root.to_path_buf(), canonical_root, times })
+    }
+
+    pub fn get(&self, path: &Path) -> Option<SystemTime> {
+        match path.strip_prefix(&self.root) {
+            Ok(relative) => self.times.get(&self.canonical_root.join(relative)).copied(),
+            Err(_) => self.times.get(path).copied(),
+        }
+    }
+}
+
+/// The nearest of `dir` and its ancestors holding a `.git` directory (or, for worktrees
+/// and submodules, file), written relative to `dir` the way it was given: `src/app`
+/// ascends through `src` and `.`, then `..`, `../..` and so on. Doesn't run git.
+pub fn enclosing_root(dir: &str) -> Option<String> {
+    let mut candidate = PathBuf::from(if dir.is_empty() { "/" } else { dir });
+    loop {
+        if candidate.join(".git").exists() {
+            return Some(candidate.display().to_string());
+        }
+        // Stop at the filesystem root
+        fs::canonicalize(&candidate).ok()?.parent()?;
+        candidate = match candidate.components().next_back() {
+            Some(Component::Normal(_)) => match candidate.parent() {
+                Some(parent) if !parent.as_os_str().is_empty() => parent.to_path_buf(),
+                _ => PathBuf::from("."),
+            },
+            Some(Component::CurDir) => PathBuf::from(".."),
+            _ => candidate.join(".."),
+        };
+    }
+}
+
+fn insert_with_ancestors(paths: &mut HashSet<PathBuf>, toplevel: &Path, relative: &str) {
+    let path = toplevel.join(relative.trim_end_matches('/'));
+    for ancestor in path.ancestors() {
+        if !paths.insert(ancestor.to_path_buf()) || ancestor == toplevel {
+            break;
+        }
+    }
+}
+
+fn toplevel(root: &Path) -> Result<PathBuf, String> {
+    let output = git(root, &["rev-parse", "--show-toplevel"])?;
+    let toplevel = PathBuf::from(output.trim_end_matches('\n'));
+    fs::canonicalize(&toplevel).map_err(|e| format!("{}: {}", toplevel.display(), e))
+}
+
+fn git(root: &Path, args: &[&str]) -> Result<String, String> {
+    let output = Command::new("git")
+        .arg("-C")
+        .arg(root)
+        .args(args)
+        .output()
+        .map_err(|e| format!("failed to run git: {}", e))?;
+    if !output.status.success() {
+        let stderr = String::from_utf8_lossy(&output.stderr);
+        return Err(format!("git {}: {}", args[0], stderr.trim()));
+    }
+    Ok(String::from_utf8_lossy(&output.stdout).into_owned())
+}
@@ -0,0 +1,81 @@
+//! `--grid`: names packed into columns across the terminal, like ls -C.
+
+use std::env;
+use std::io::{self, Write};
+
+use unicode_width::UnicodeWidthStr;
+
+/// Blanks between two columns.
+const GAP: usize = 2;
+const DEFAULT_WIDTH: usize = 80;
+
+/// Columns to fill: $COLUMNS, else the terminal's width, else 80.
+pub fn terminal_width() -> usize {
+    env::var("COLUMNS").ok()
+        .and_then(|columns| columns.parse().ok())
+        .filter(|&columns| columns > 0)
+        .or_else(tty_width)
+        .unwrap_or(DEFAULT_WIDTH)
+}
+
+#[cfg(unix)]
+fn tty_width() -> Option<usize> {
+    // SAFETY: TIOCGWINSZ only fills in the winsize we own
+    let mut size: libc::winsize = unsafe { std::mem::zeroed() };
+    let found = unsafe { libc::ioctl(libc::STDOUT_FILENO, libc::TIOCGWINSZ, &mut size) } == 0;
+    (found && size.ws_col > 0).then_some(size.ws_col as usize)
+}
+
+#[cfg(not(unix))]
+fn tty_width() -> Option<usize> {
+    None
+}
+
+/// Writes `cells` (formatted entries, possibly colored) down the columns of as many
+/// rows as it takes to fit `width`, newest first down each column.
+pub fn write(handle: &mut dyn Write, cells: &[Vec<u8>], width: usize) -> io::Result<()> {
+    if cells.is_empty() {
+        return Ok(());
+    }
+    let widths: Vec<usize> = cells.iter().map(|cell| display_width(cell)).collect();
+    // The most columns that fit; each takes at least one column and a gap
+    let most = (width / (1 + GAP)).clamp(1, cells.len());
+    let rows = (1..=most).rev()
+        .map(|columns| cells.len().div_ceil(columns))
+        .find(|&rows| {
+            let columns = column_widths(&widths, rows);
+            columns.iter().sum::<usize>() + GAP * (columns.len() - 1) <= width
+        })
+        .unwrap_or(cells.len());
+    let column_widths = column_widths(&widths, rows);
+    for row in 0..rows {
+        for position in (row..cells.len()).step_by(rows) {
+            handle.write_all(&cells[position])?;
+            if position + rows < cells.len() {
+                write!(handle, "{:pad$}", "", pad = column_widths[position / rows] - widths[position] + GAP)?;
+            }
+        }
+        writeln!(handle)?;
+    }
+    Ok(())
+}
+
+fn column_widths(widths: &[usize], rows: usize) -> Vec<usize> {
+    widths.chunks(rows).map(|column| column.iter().copied().max().unwrap_or(0)).collect()
+}
+
+/// Terminal columns taken by `cell`, leaving out color escapes.
+fn display_width(cell: &[u8]) -> usize {
+    let text = String::from_utf8_lossy(cell);
+    let mut plain = String::with_capacity(text.len());
+    let mut chars = text.chars();
+    while let Some(c) = chars.next() {
+        if c == '\u{1b}' {
+            // CSI sequences end with a letter
+            chars.by_ref().find(|c| c.is_ascii_alphabetic());
+        } else {
+            plain.push(c);
+        }
+    }
+    plain.width()
+}
@@ -0,0 +1,202 @@
+//! `.hgignore` files, so that Mercurial working copies are listed as cleanly as git ones.
+//!
+//! Only the file at the top of the working copy is read, with both `glob` and
+//! `regexp` syntax. Like Mercurial, patterns aren't rooted unless they use
+//! `rootglob`, and regular expressions may match anywhere in the path.
+
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use globset::{GlobBuilder, GlobSet, GlobSetBuilder};
+use regex_automata::meta::Regex;
+
+#[derive(Clone, Copy)]
+enum Syntax {
+    Glob,
+    RootGlob,
+    Regexp,
+}
+
+impl Syntax {
+    fn from_name(name: &str) -> Option<Syntax> {
+        match name {
+            "glob" => Some(Syntax::Glob),
+            "rootglob" => Some(Syntax::RootGlob),
+            "re" | "regexp" => Some(Syntax::Regexp),
+            _ => None,
+        }
+    }
+}
+
+pub struct HgIgnore {
+    walk_root: PathBuf,
+    // The walk root relative to the top of the working copy
+    walk_root_in_repo: PathBuf,
+    globs: GlobSet,
+    regex: Option<Regex>,
+}
+
+impl HgIgnore {
+    /// Reads the `.hgignore` of the Mercurial working copy holding `walk_root`, if any.
+    /// Invalid lines are reported and skipped, as hg itself would refuse to run.
+    pub fn load(walk_root: &Path) -> Option<HgIgnore> {
+        let absolute = fs::canonicalize(walk_root).ok()?;
+        let repo = absolute.ancestors().find(|dir| dir.join(".hg").is_dir())?;
+        let file = repo.join(".hgignore");
+        let content = fs::read_to_string(&file).ok()?;
+
+        let mut syntax = Syntax::Regexp;
+        let mut globs = GlobSetBuilder::new();
+        let mut regexes = Vec::new();
+        for (number, line) in content.lines().enumerate() {
+            let line = strip_comment(line);
+            let line = line.trim_end();
+            if line.is_empty() {
+                continue;
+            }
+            if let Some(name) = line.strip_prefix("syntax:") {
+                match Syntax::from_name(name.trim()) {
+                    Some(selected) => syntax = selected,
+                    None => eprintln!("sortfs: {}:{}: unknown syntax '{}'", file.display(), number + 1, name.trim()),
+                }
+                continue;
+            }
+            let (line_syntax, pattern) = match line.split_once(':') {
+                Some((name, pattern)) => match Syntax::from_name(name) {
+                    Some(selected) => (selected, pattern),
+                    None => (syntax, line),
+                },
+                None => (syntax, line),
+            };
+            let result = match line_syntax {
+                Syntax::Regexp => match Regex::new(pattern) {
+                    Ok(_) => {
+                        regexes.push(pattern.to_string());
+                        Ok(())
+                    }
+                    Err(_) => Err(format!("'{}' is not a valid regular expression", pattern)),
+                },
+                Syntax::Glob => add_glob(&mut globs, &format!("**/{}", pattern.trim_start_matches('/'))),
+                Syntax::RootGlob => add_glob(&mut globs, pattern.trim_start_matches('/')),
+            };
+            if let Err(e) = result {
+                eprintln!("sortfs: {}:{}: {}", file.display(), number + 1, e);
+            }
+        }
+
+        Some(HgIgnore {
+            walk_root: walk_root.to_path_buf(),
+            walk_root_in_repo: absolute.strip_prefix(repo).ok()?.to_path_buf(),
+            globs: globs.build().ok()?,
+            regex: if regexes.is_empty() { None } else { Regex::new_many(&regexes).ok() },
+        })
+    }
+
+    /// Whether a walked path is ignored. Directories that are get pruned by the
+    /// walker, which takes care of everything below them.
+    pub fn is_ignored(&self, path: &Path) -> bool {
+        let relative = match path.strip_prefix(&self.walk_root) {
+            Ok(relative) if !relative.as_os_str().is_empty() => self.walk_root_in_repo.join(relative),
+            _ => return false,
+        };
+        let relative = match relative.to_str() {
+            Some(relative) => relative.replace(std::path::MAIN_SEPARATOR, "/"),
+            None => return false,
+        };
+        self.globs.is_match(&relative) || self.regex.as_ref().is_some_and(|regex| regex.is_match(&relative))
+    }
+}
+
+/// Drops a `#` comment; `\#` stands for a literal `#`.
+fn strip_comment(line: &str) -> String {
+    let mut stripped = String::with_capacity(line.len());
+    let mut chars = line.chars().peekable();
+    while let Some(c) = chars.next() {
+        match c {
+            '\\' if chars.peek() == Some(&'#') => stripped.push(chars.next().unwrap()),
+            '#' => break,
+            _ => stripped.push(c),
+        }
+    }
+    stripped
+}
+
+fn add_glob(globs: &mut GlobSetBuilder, pattern: &str) -> Result<(), String> {
+    let glob = GlobBuilder::new(pattern)
+        .literal_separator(true)
+        .build()
+        .map_err(|e| e.to_string())?;
+    globs.add(glob);
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::env;
+
+    fn working_copy(name: &str, hgignore: &str) -> PathBuf {
+        let repo = env::temp_dir().join(format!("sortfs-hgignore-{}-{}", name, std::process::id()));
+        let _ = fs::remove_dir_all(&repo);
+        fs::create_dir_all(repo.join(".hg")).unwrap();
+        fs::create_dir_all(repo.join("sub")).unwrap();
+        fs::write(repo.join(".hgignore"), hgignore).unwrap();
+        repo
+    }
+
+    #[test]
+    fn glob_and_regexp_syntax() {
+        let repo = working_copy("syntax", "\
+# a comment
+.*\\.orig$   # regexp is the default
+syntax: glob
+*.pyc
+rootglob:build/*
+re:^dist/
+syntax: rootglob
+top.txt
+");
+        let hg = HgIgnore::load(&repo).unwrap();
+        assert!(hg.is_ignored(&repo.join("a.orig")));
+        assert!(hg.is_ignored(&repo.join("sub/a.orig")));
+        assert!(!hg.is_ignored(&repo.join("a.original")));
+        assert!(hg.is_ignored(&repo.join("sub/deep.pyc")));
+        assert!(hg.is_ignored(&repo.join("build/out")));
+        assert!(!hg.is_ignored(&repo.join("sub/build/out")));
+        assert!(hg.is_ignored(&repo.join("dist/x")));
+        assert!(!hg.is_ignored(&repo.join("dist")));
+        assert!(!hg.is_ignored(&repo.join("sub/dist/x")));
+        assert!(hg.is_ignored(&repo.join("top.txt")));
+        assert!(!hg.is_ignored(&repo.join("sub/top.txt")));
+        fs::remove_dir_all(&repo).unwrap();
+    }
+
+    #[test]
+    fn walk_roots_below_the_top() {
+        let repo = working_copy("below", "syntax: rootglob\nsub/*.log\n");
+        let hg = HgIgnore::load(&repo.join("sub")).unwrap();
+        assert!(hg.is_ignored(&repo.join("sub/a.log")));
+        assert!(!hg.is_ignored(&repo.join("sub")));
+        assert!(!hg.is_ignored(&repo.join("a.log")));
+        assert!(!hg.is_ignored(Path::new("/elsewhere/sub/a.log")));
+        fs::remove_dir_all(&repo).unwrap();
+    }
+
+    #[test]
+    fn invalid_lines_are_skipped() {
+        let repo = working_copy("invalid", "syntax: svn\nre:(unclosed\nglob:a[\nglob:*.tmp\n[.]bak$\n");
+        let hg = HgIgnore::load(&repo).unwrap();
+        assert!(hg.is_ignored(&repo.join("x.tmp")));
+        assert!(hg.is_ignored(&repo.join("x.bak")));
+        assert!(!hg.is_ignored(&repo.join("(unclosed")));
+        fs::remove_dir_all(&repo).unwrap();
+    }
+
+    #[test]
+    fn escaped_hashes() {
+        assert_eq!(strip_comment("a\\#b # c"), "a#b ");
+        assert_eq!(strip_comment("#all"), "");
+        assert_eq!(strip_comment("plain"), "plain");
+        assert_eq!(strip_comment("trailing\\"), "trailing\\");
+    }
+}
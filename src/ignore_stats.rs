@@ -0,0 +1,165 @@
+//! `--stats`: how many entries each ignore source kept out of the listing.
+//!
+//! The walker doesn't say why it skips an entry, so the tree is walked once with
+//! nothing ignored and again each time one more source is switched on, in the order
+//! below. Whatever disappears at a step is booked to the source switched on there.
+
+use std::collections::{HashMap, HashSet};
+use std::io::{self, Write};
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+
+use ignore::{WalkBuilder, WalkState};
+
+use crate::hgignore::HgIgnore;
+use crate::submodules::SubmoduleExcludes;
+use crate::WalkOptions;
+
+/// Directories and extensions listed per source.
+const REPORT_LIMIT: usize = 10;
+
+#[derive(Clone, Copy, PartialEq, PartialOrd)]
+enum Source {
+    // The walk with every source off
+    Nothing,
+    Overrides,
+    Hidden,
+    Gitignore,
+    Hgignore,
+    DotIgnore,
+    CustomFiles,
+}
+
+const SOURCES: [Source; 6] = [
+    Source::Overrides,
+    Source::Hidden,
+    Source::Gitignore,
+    Source::Hgignore,
+    Source::DotIgnore,
+    Source::CustomFiles,
+];
+
+impl Source {
+    fn name(self) -> &'static str {
+        match self {
+            Source::Nothing => "nothing",
+            Source::Overrides => "excluded directories",
+            Source::Hidden => "hidden filter",
+            Source::Gitignore => "gitignore",
+            Source::Hgignore => "hgignore",
+            Source::DotIgnore => ".ignore",
+            Source::CustomFiles => "custom ignore files",
+        }
+    }
+}
+
+/// Paths listed by a walk with every source up to `last` switched on, as far as `options` enables them.
+fn walk(root: &Path, options: &WalkOptions, last: Source) -> HashSet<PathBuf> {
+    let ignore_files = options.ignore;
+    let vcs = ignore_files && options.ignore_vcs;
+    let mut builder = WalkBuilder::new(root);
+    for root in &options.extra_roots {
+        builder.add(root);
+    }
+    builder
+        .standard_filters(false)
+        .parents(ignore_files && options.ignore_parents)
+        .follow_links(options.follow_links)
+        .same_file_system(options.same_file_system)
+        .max_depth(options.max_depth)
+        .threads(options.threads);
+    if last >= Source::Overrides {
+        builder.overrides(options.excluded_dirs_override(root));
+    }
+    if last >= Source::Hidden {
+        builder.hidden(!options.hidden);
+    }
+    if last >= Source::Gitignore {
+        builder
+            .git_ignore(vcs)
+            .git_global(vcs && options.ignore_vcs_global)
+            .git_exclude(vcs && options.ignore_vcs_exclude);
+    }
+    // Both are applied by one filter, which the walker has only one of
+    let submodule_excludes = (last >= Source::Gitignore && vcs && options.ignore_vcs_exclude).then(SubmoduleExcludes::new);
+    let hg_ignores: Vec<HgIgnore> = if last >= Source::Hgignore && vcs {
+        std::iter::once(root)
+            .chain(options.extra_roots.iter().map(PathBuf::as_path))
+            .filter_map(HgIgnore::load)
+            .collect()
+    } else {
+        Vec::new()
+    };
+    builder.filter_entry(move |entry| {
+        let is_dir = entry.file_type().is_some_and(|file_type| file_type.is_dir());
+        !submodule_excludes.as_ref().is_some_and(|excludes| excludes.is_ignored(entry.path(), is_dir))
+            && !hg_ignores.iter().any(|hg_ignore| hg_ignore.is_ignored(entry.path()))
+    });
+    if last >= Source::DotIgnore {
+        builder.ignore(ignore_files);
+    }
+    if last >= Source::CustomFiles && ignore_files {
+        for name in options.custom_ignore_filenames() {
+            builder.add_custom_ignore_filename(name);
+        }
+    }
+
+    let paths = Mutex::new(HashSet::new());
+    builder.build_parallel().run(|| {
+        Box::new(|entry| {
+            if let Ok(entry) = entry {
+                paths.lock().unwrap().insert(entry.into_path());
+            }
+            WalkState::Continue
+        })
+    });
+    paths.into_inner().unwrap()
+}
+
+/// The first component below whichever root holds `path`.
+fn top_level(path: &Path, roots: &[&Path]) -> String {
+    let relative = roots.iter()
+        .find_map(|root| path.strip_prefix(root).ok())
+        .unwrap_or(path);
+    relative.components().next().map_or(".".to_string(), |top| top.as_os_str().to_string_lossy().into_owned())
+}
+
+fn extension(path: &Path) -> String {
+    path.extension().map_or("(none)".to_string(), |extension| format!(".{}", extension.to_string_lossy()))
+}
+
+/// The `REPORT_LIMIT` most frequent keys, as `KEY COUNT, ...`.
+fn most_frequent(keys: impl Iterator<Item = String>) -> String {
+    let mut counts: HashMap<String, usize> = HashMap::new();
+    for key in keys {
+        *counts.entry(key).or_default() += 1;
+    }
+    let mut counts: Vec<(String, usize)> = counts.into_iter().collect();
+    counts.sort_by(|(a_key, a), (b_key, b)| b.cmp(a).then_with(|| a_key.cmp(b_key)));
+    counts.iter()
+        .take(REPORT_LIMIT)
+        .map(|(key, count)| format!("{} {}", key, count))
+        .collect::<Vec<_>>()
+        .join(", ")
+}
+
+pub fn report(root: &Path, options: &WalkOptions, handle: &mut dyn Write) -> io::Result<()> {
+    let roots: Vec<&Path> = std::iter::once(root).chain(options.extra_roots.iter().map(PathBuf::as_path)).collect();
+    let mut previous = walk(root, options, Source::Nothing);
+    let total = previous.len();
+    let mut excluded = Vec::new();
+    for source in SOURCES {
+        let current = walk(root, options, source);
+        let dropped: Vec<PathBuf> = previous.iter().filter(|path| !current.contains(*path)).cloned().collect();
+        excluded.push((source, dropped));
+        previous = current;
+    }
+
+    writeln!(handle, "stats: {} of {} entries left after ignore rules", previous.len(), total)?;
+    for (source, dropped) in excluded.iter().filter(|(_, dropped)| !dropped.is_empty()) {
+        writeln!(handle, "  {}: {} excluded", source.name(), dropped.len())?;
+        writeln!(handle, "    by top-level entry: {}", most_frequent(dropped.iter().map(|path| top_level(path, &roots))))?;
+        writeln!(handle, "    by extension: {}", most_frequent(dropped.iter().map(|path| extension(path))))?;
+    }
+    Ok(())
+}
@@ -0,0 +1,315 @@
+//! Minimal JSON encoding for machine-readable output, and decoding for snapshots.
+
+use std::fmt::Write;
+
+/// Quotes and escapes `s` as a JSON string literal.
+pub fn string(s: &str) -> String {
+    let mut quoted = String::with_capacity(s.len() + 2);
+    quoted.push('"');
+    for c in s.chars() {
+        match c {
+            '"' => quoted.push_str("\\\""),
+            '\\' => quoted.push_str("\\\\"),
+            '\n' => quoted.push_str("\\n"),
+            '\r' => quoted.push_str("\\r"),
+            '\t' => quoted.push_str("\\t"),
+            c if (c as u32) < 0x20 => {
+                let _ = write!(quoted, "\\u{:04x}", c as u32);
+            }
+            c => quoted.push(c),
+        }
+    }
+    quoted.push('"');
+    quoted
+}
+
+/// A parsed JSON value. Numbers keep their text, so that integers beyond what an
+/// f64 holds exactly survive.
+pub enum Value {
+    Null,
+    Bool(bool),
+    Number(String),
+    String(String),
+    Array(Vec<Value>),
+    Object(Vec<(String, Value)>),
+}
+
+impl Value {
+    /// The value of `key`, if this is an object that has it.
+    pub fn get(&self, key: &str) -> Option<&Value> {
+        match self {
+            Value::Object(members) => members.iter().find(|(name, _)| name == key).map(|(_, value)| value),
+            _ => None,
+        }
+    }
+
+    pub fn as_str(&self) -> Option<&str> {
+        match self {
+            Value::String(s) => Some(s),
+            _ => None,
+        }
+    }
+
+    pub fn as_u64(&self) -> Option<u64> {
+        match self {
+            Value::Number(number) => number.parse().ok(),
+            _ => None,
+        }
+    }
+
+    pub fn as_array(&self) -> Option<&[Value]> {
+        match self {
+            Value::Array(values) => Some(values),
+            _ => None,
+        }
+    }
+
+    /// Encodes the value back into compact JSON.
+    pub fn encode(&self) -> String {
+        match self {
+            Value::Null => "null".to_string(),
+            Value::Bool(value) => value.to_string(),
+            Value::Number(number) => number.clone(),
+            Value::String(s) => string(s),
+            Value::Array(values) => {
+                format!("[{}]", values.iter().map(Value::encode).collect::<Vec<_>>().join(","))
+            }
+            Value::Object(members) => {
+                let members: Vec<String> = members.iter()
+                    .map(|(name, value)| format!("{}:{}", string(name), value.encode()))
+                    .collect();
+                format!("{{{}}}", members.join(","))
+            }
+        }
+    }
+}
+
+/// Parses a complete JSON document.
+pub fn parse(text: &str) -> Result<Value, String> {
+    let mut parser = Parser { text, bytes: text.as_bytes(), position: 0, depth: 0 };
+    let value = parser.value()?;
+    parser.skip_whitespace();
+    if parser.position != parser.bytes.len() {
+        return Err(parser.error("trailing characters"));
+    }
+    Ok(value)
+}
+
+/// Arrays and objects nested deeper than this are refused rather than overflowing the stack.
+const MAX_DEPTH: usize = 128;
+
+struct Parser<'a> {
+    text: &'a str,
+    bytes: &'a [u8],
+    position: usize,
+    depth: usize,
+}
+
+impl Parser<'_> {
+    fn error(&self, message: &str) -> String {
+        format!("{} at offset {}", message, self.position)
+    }
+
+    fn skip_whitespace(&mut self) {
+        while self.bytes.get(self.position).is_some_and(|byte| byte.is_ascii_whitespace()) {
+            self.position += 1;
+        }
+    }
+
+    fn expect(&mut self, literal: &str) -> Result<(), String> {
+        if self.bytes[self.position..].starts_with(literal.as_bytes()) {
+            self.position += literal.len();
+            Ok(())
+        } else {
+            Err(self.error(&format!("expected '{}'", literal)))
+        }
+    }
+
+    fn value(&mut self) -> Result<Value, String> {
+        if self.depth == MAX_DEPTH {
+            return Err(self.error("nested too deeply"));
+        }
+        self.depth += 1;
+        let value = self.unnested_value();
+        self.depth -= 1;
+        value
+    }
+
+    fn unnested_value(&mut self) -> Result<Value, String> {
+        self.skip_whitespace();
+        match self.bytes.get(self.position) {
+            Some(b'n') => self.expect("null").map(|_| Value::Null),
+            Some(b't') => self.expect("true").map(|_| Value::Bool(true)),
+            Some(b'f') => self.expect("false").map(|_| Value::Bool(false)),
+            Some(b'"') => self.string().map(Value::String),
+            Some(b'[') => {
+                self.position += 1;
+                let mut values = Vec::new();
+                self.skip_whitespace();
+                if self.bytes.get(self.position) == Some(&b']') {
+                    self.position += 1;
+                    return Ok(Value::Array(values));
+                }
+                loop {
+                    values.push(self.value()?);
+                    self.skip_whitespace();
+                    match self.bytes.get(self.position) {
+                        Some(b',') => self.position += 1,
+                        Some(b']') => {
+                            self.position += 1;
+                            return Ok(Value::Array(values));
+                        }
+                        _ => return Err(self.error("expected ',' or ']'")),
+                    }
+                }
+            }
+            Some(b'{') => {
+                self.position += 1;
+                let mut members = Vec::new();
+                self.skip_whitespace();
+                if self.bytes.get(self.position) == Some(&b'}') {
+                    self.position += 1;
+                    return Ok(Value::Object(members));
+                }
+                loop {
+                    self.skip_whitespace();
+                    let name = self.string()?;
+                    self.skip_whitespace();
+                    self.expect(":")?;
+                    members.push((name, self.value()?));
+                    self.skip_whitespace();
+                    match self.bytes.get(self.position) {
+                        Some(b',') => self.position += 1,
+                        Some(b'}') => {
+                            self.position += 1;
+                            return Ok(Value::Object(members));
+                        }
+                        _ => return Err(self.error("expected ',' or '}'")),
+                    }
+                }
+            }
+            Some(b'-' | b'0'..=b'9') => {
+                let start = self.position;
+                while self.bytes.get(self.position).is_some_and(|byte| matches!(byte, b'-' | b'+' | b'.' | b'e' | b'E' | b'0'..=b'9')) {
+                    self.position += 1;
+                }
+                let number = &self.text[start..self.position];
+                match number.parse::<f64>() {
+                    Ok(_) => Ok(Value::Number(number.to_string())),
+                    Err(_) => Err(self.error("malformed number")),
+                }
+            }
+            _ => Err(self.error("expected a value")),
+        }
+    }
+
+    fn string(&mut self) -> Result<String, String> {
+        self.expect("\"")?;
+        let mut string = String::new();
+        loop {
+            // Only ever advanced by whole characters, so this is a char boundary
+            let mut chars = self.text[self.position..].chars();
+            match chars.next() {
+                None => return Err(self.error("unterminated string")),
+                Some('"') => {
+                    self.position += 1;
+                    return Ok(string);
+                }
+                Some('\\') => {
+                    let escaped = chars.next().ok_or_else(|| self.error("unterminated string"))?;
+                    self.position += 1 + escaped.len_utf8();
+                    match escaped {
+                        '"' | '\\' | '/' => string.push(escaped),
+                        'b' => string.push('\u{8}'),
+                        'f' => string.push('\u{c}'),
+                        'n' => string.push('\n'),
+                        'r' => string.push('\r'),
+                        't' => string.push('\t'),
+                        'u' => {
+                            let mut unit = self.hex_unit()?;
+                            // A UTF-16 surrogate pair spans two escapes
+                            if (0xd800..0xdc00).contains(&unit) && self.bytes[self.position..].starts_with(b"\\u") {
+                                self.position += 2;
+                                let low = self.hex_unit()?;
+                                if !(0xdc00..0xe000).contains(&low) {
+                                    return Err(self.error("invalid \\u escape"));
+                                }
+                                unit = 0x10000 + ((unit - 0xd800) << 10) + (low - 0xdc00);
+                            }
+                            string.push(char::from_u32(unit).ok_or_else(|| self.error("invalid \\u escape"))?);
+                        }
+                        _ => return Err(self.error("invalid escape")),
+                    }
+                }
+                Some(c) => {
+                    string.push(c);
+                    self.position += c.len_utf8();
+                }
+            }
+        }
+    }
+
+    fn hex_unit(&mut self) -> Result<u32, String> {
+        let digits = self.bytes.get(self.position..self.position + 4)
+            .filter(|digits| digits.iter().all(u8::is_ascii_hexdigit))
+            .and_then(|digits| std::str::from_utf8(digits).ok())
+            .and_then(|digits| u32::from_str_radix(digits, 16).ok())
+            .ok_or_else(|| self.error("invalid \\u escape"))?;
+        self.position += 4;
+        Ok(digits)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn encodes_strings() {
+        assert_eq!(string("plain"), "\"plain\"");
+        assert_eq!(string("a\"b\\c\n\t"), "\"a\\\"b\\\\c\\n\\t\"");
+        assert_eq!(string("\u{1}é"), "\"\\u0001é\"");
+    }
+
+    #[test]
+    fn parses_documents() {
+        let value = parse(r#" {"path":"a\/b","mtime":1706702400,"tags":[true,false,null],"big":18446744073709551615} "#).unwrap();
+        assert_eq!(value.get("path").and_then(Value::as_str), Some("a/b"));
+        assert_eq!(value.get("mtime").and_then(Value::as_u64), Some(1_706_702_400));
+        assert_eq!(value.get("big").and_then(Value::as_u64), Some(u64::MAX));
+        assert_eq!(value.get("tags").and_then(Value::as_array).map(<[Value]>::len), Some(3));
+        assert_eq!(value.get("missing").map(Value::encode), None);
+        assert_eq!(parse("[]").unwrap().encode(), "[]");
+        assert_eq!(parse("{ }").unwrap().encode(), "{}");
+        assert_eq!(parse("-1.5e3").unwrap().encode(), "-1.5e3");
+    }
+
+    #[test]
+    fn parses_escapes() {
+        assert_eq!(parse(r#""\u00e9\ud83d\ude00\b\f""#).unwrap().as_str(), Some("é😀\u{8}\u{c}"));
+        assert_eq!(parse("\"é\"").unwrap().as_str(), Some("é"));
+    }
+
+    #[test]
+    fn round_trips() {
+        let text = r#"{"a":[1,"x\ny",{"b":null}],"c":false}"#;
+        assert_eq!(parse(text).unwrap().encode(), text);
+    }
+
+    #[test]
+    fn rejects_malformed() {
+        for text in [
+            "", " ", "{", "[1,", "[1 2]", "{\"a\" 1}", "{\"a\":1,}", "{a:1}", "nul", "tru", "\"open",
+            "\"\\", "\"\\x\"", "\"\\é\"", "\"\\u12\"", "\"\\u+041\"", "\"\\ud83d\\u0041\"", "\"\\udc00\"",
+            "-", "1-2", "1e", "1 2", "{}x",
+        ] {
+            assert!(parse(text).is_err(), "{:?}", text);
+        }
+    }
+
+    #[test]
+    fn refuses_deep_nesting() {
+        assert!(parse(&format!("{}{}", "[".repeat(MAX_DEPTH), "]".repeat(MAX_DEPTH))).is_ok());
+        assert!(parse(&"[".repeat(100_000)).is_err());
+    }
+}
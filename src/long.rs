@@ -0,0 +1,106 @@
+//! `--long`: ls -l style columns in front of each path.
+
+use std::collections::HashMap;
+use std::fs::FileType;
+use std::io::{self, Write};
+
+use crate::entry::Entry;
+use crate::time::TimeStyle;
+use crate::users;
+
+/// Owner names and column widths, worked out over every listed entry up front so
+/// that lines formatted in parallel still line up.
+pub struct LongColumns {
+    owners: HashMap<u32, String>,
+    owner_width: usize,
+    size_width: usize,
+    time_style: TimeStyle,
+}
+
+impl LongColumns {
+    pub fn new<'a>(entries: impl Iterator<Item = &'a Entry>, time_style: TimeStyle) -> LongColumns {
+        let mut owners = HashMap::new();
+        let mut owner_width = 1;
+        let mut size_width = 1;
+        for entry in entries {
+            if let Some(uid) = entry.uid() {
+                let name = owners.entry(uid).or_insert_with(|| users::user_name(uid));
+                owner_width = owner_width.max(name.len());
+            }
+            size_width = size_width.max(entry.measured_size(false).map_or(1, |size| size.to_string().len()));
+        }
+        LongColumns { owners, owner_width, size_width, time_style }
+    }
+
+    /// Writes `MODE OWNER SIZE TIME ` for `entry`, with `-` for what wasn't stat()ed.
+    pub fn write(&self, handle: &mut dyn Write, entry: &Entry) -> io::Result<()> {
+        let owner = entry.uid().and_then(|uid| self.owners.get(&uid)).map_or("-", String::as_str);
+        let size = entry.measured_size(false).map_or_else(|| "-".to_string(), |size| size.to_string());
+        write!(handle, "{} {:<owner_width$} {:>size_width$} {:>time_width$} ",
+               mode_string(entry), owner, size, self.time_style.format(entry.modified),
+               owner_width = self.owner_width, size_width = self.size_width, time_width = self.time_style.width())
+    }
+}
+
+/// `drwxr-xr-x`: the entry's type and permission bits, as ls -l shows them.
+fn mode_string(entry: &Entry) -> String {
+    let kind = if entry.is_symlink { 'l' } else { type_char(&entry.file_type) };
+    let mut mode = String::with_capacity(10);
+    mode.push(kind);
+    match entry.metadata.as_ref().and_then(permission_bits) {
+        Some(bits) => {
+            // Owner, group and others, each with its set-id or sticky bit over the x
+            let specials = [(0o4000, 's'), (0o2000, 's'), (0o1000, 't')];
+            for (i, (special, letter)) in specials.iter().enumerate() {
+                let shift = 6 - 3 * i;
+                mode.push(if bits & (0o4 << shift) != 0 { 'r' } else { '-' });
+                mode.push(if bits & (0o2 << shift) != 0 { 'w' } else { '-' });
+                let execute = bits & (0o1 << shift) != 0;
+                mode.push(match (bits & special != 0, execute) {
+                    (true, true) => *letter,
+                    (true, false) => letter.to_ascii_uppercase(),
+                    (false, true) => 'x',
+                    (false, false) => '-',
+                });
+            }
+        }
+        None => mode.push_str("?????????"),
+    }
+    mode
+}
+
+#[cfg(unix)]
+fn type_char(file_type: &FileType) -> char {
+    use std::os::unix::fs::FileTypeExt;
+    if file_type.is_dir() {
+        'd'
+    } else if file_type.is_fifo() {
+        'p'
+    } else if file_type.is_socket() {
+        's'
+    } else if file_type.is_char_device() {
+        'c'
+    } else if file_type.is_block_device() {
+        'b'
+    } else {
+        '-'
+    }
+}
+
+#[cfg(not(unix))]
+fn type_char(file_type: &FileType) -> char {
+    if file_type.is_dir() { 'd' } else { '-' }
+}
+
+#[cfg(unix)]
+fn permission_bits(meta: &std::fs::Metadata) -> Option<u32> {
+    use std::os::unix::fs::PermissionsExt;
+    Some(meta.permissions().mode() & 0o7777)
+}
+
+// Only the read-only attribute to go by
+#[cfg(not(unix))]
+fn permission_bits(meta: &std::fs::Metadata) -> Option<u32> {
+    let bits = if meta.is_dir() { 0o777 } else { 0o666 };
+    Some(if meta.permissions().readonly() { bits & 0o555 } else { bits })
+}
@@ -1,20 +1,100 @@
-use clap::{App, Arg};
-use std::io::{self, Write, BufWriter};
+use clap::{App, AppSettings, Arg, ArgMatches, SubCommand};
+use std::io::{self, Write, BufWriter, IsTerminal};
 use std::path::PathBuf;
-use std::time::SystemTime;
+use std::time::{Duration, Instant, SystemTime};
 use std::process;
+use std::sync::atomic::{AtomicBool, Ordering};
 use std::sync::{Arc, Mutex};
 use std::fs::metadata;
 use rayon::prelude::*;
-use ignore::{WalkBuilder, DirEntry, overrides::OverrideBuilder};
-use std::path::Path;
+use ignore::{WalkBuilder, DirEntry, overrides::{Override, OverrideBuilder}};
+use std::path::{Component, Path};
 use std::fs;
+use std::env;
+use std::collections::{HashMap, HashSet};
+use std::iter;
+use std::mem;
 
 use lscolors::{LsColors, Style};
 
-#[cfg(all(
-    not(feature = "nu-ansi-term"),
-))]
+mod action;
+mod annotate;
+mod archive;
+mod checkpoint;
+mod device;
+mod doctor;
+mod drives;
+mod entry;
+mod filter;
+mod git;
+mod grid;
+mod hgignore;
+mod ignore_stats;
+mod json;
+mod long;
+mod memory;
+mod mime;
+mod mru;
+mod output;
+mod priority;
+mod profile;
+mod recency;
+mod remote;
+mod rules;
+mod sink;
+mod snapshot;
+mod split;
+mod submodules;
+mod template;
+mod time;
+mod trash;
+mod tree;
+mod users;
+mod warm;
+mod winpath;
+mod xattr;
+use action::Action;
+use annotate::Annotations;
+use entry::Entry;
+use filter::{BrokenSymlinks, ContentKind, FileTypes, Filters, GlobFilter, LinksFilter, OwnerFilter, PermFilter, PruneGlobs, SizeFilter, XattrFilter};
+use git::GitPaths;
+use checkpoint::{Checkpoint, Progress};
+use hgignore::HgIgnore;
+use long::LongColumns;
+use submodules::SubmoduleExcludes;
+use mru::MruList;
+use output::{Column, Format};
+use profile::WalkProfile;
+use recency::Recency;
+use remote::{RemoteMounts, Stat};
+use rules::SortfsIgnore;
+use snapshot::{Change, Environment, Snapshot};
+use split::AgeSplit;
+use time::TimeStyle;
+use template::Template;
+
+/// Lines written to a terminal before the rest is summarized instead.
+const DEFAULT_TTY_LIMIT: &str = "1000";
+
+/// Longest wait for a stat() on a network filesystem before listing the entry without it.
+const DEFAULT_STAT_TIMEOUT: &str = "5s";
+
+/// Lines formatted in parallel per batch of output.
+const OUTPUT_CHUNK_LEN: usize = 4096;
+
+#[global_allocator]
+static ALLOCATOR: memory::ReserveAllocator = memory::ReserveAllocator;
+
+/// Set when a walk stopped early (`--timeout`, or out of memory), for `--strict`.
+static WALK_PARTIAL: AtomicBool = AtomicBool::new(false);
+
+/// Pseudo-filesystems and device trees that `--system-safe` doesn't walk.
+const SYSTEM_PSEUDO_DIRS: [&str; 4] = ["/proc", "/sys", "/dev", "/run"];
+
+/// Walker threads under `--nice-io` without `--threads`, and so stat() calls in flight.
+const NICE_IO_THREADS: usize = 2;
+
+#[cfg(not(feature = "nu-ansi-term"))]
 compile_error!(
     "feature must be enabled: nu-ansi-term"
 );
@@ -24,7 +104,71 @@ fn print_path(handle: &mut dyn Write, path: &str, is_dir: bool) -> io::Result<()
     if is_dir && !path.eq("/") {
         write!(handle, "/")?;
     }
-    writeln!(handle)?;
+    Ok(())
+}
+
+/// `--porcelain` v1 line: `KIND\tMTIME\tSIZE\tPATH`, with `-` for an unknown size.
+fn print_porcelain(handle: &mut dyn Write, path: &str, entry: &Entry) -> io::Result<()> {
+    let mtime = entry.modified.duration_since(std::time::UNIX_EPOCH).map_or(0, |d| d.as_secs());
+    match entry.size() {
+        Some(size) => write!(handle, "{}\t{}\t{}\t{}", entry.kind(), mtime, size, path),
+        None => write!(handle, "{}\t{}\t-\t{}", entry.kind(), mtime, path),
+    }
+}
+
+/// What the machine-readable formats show of an entry beyond its path and metadata.
+#[derive(Default)]
+struct Details<'a> {
+    // Components below the entry's root
+    depth: Option<usize>,
+    // --detect-text's guess
+    content: Option<ContentKind>,
+    // --annotate's label
+    label: Option<&'a str>,
+}
+
+/// An `--output json` object.
+fn print_json(handle: &mut dyn Write, path: &str, entry: &Entry, details: &Details) -> io::Result<()> {
+    let mtime = entry.modified.duration_since(std::time::UNIX_EPOCH).map_or(0, |d| d.as_secs());
+    write!(handle, "{{\"path\":{},\"mtime\":{},\"mtime_iso\":{},\"size\":",
+           json::string(path), mtime, json::string(&time::format_utc(entry.modified)))?;
+    match entry.size() {
+        Some(size) => write!(handle, "{}", size)?,
+        None => write!(handle, "null")?,
+    }
+    write!(handle, ",\"type\":{},\"depth\":", json::string(entry.kind()))?;
+    match details.depth {
+        Some(depth) => write!(handle, "{}", depth)?,
+        None => write!(handle, "null")?,
+    }
+    if let Some(content) = details.content {
+        write!(handle, ",\"content\":{}", json::string(content.name()))?;
+    }
+    if let Some(label) = details.label {
+        write!(handle, ",\"label\":{}", json::string(label))?;
+    }
+    write!(handle, "}}")
+}
+
+/// An `--output tsv` or `--output csv` line, without its newline.
+fn print_delimited(handle: &mut dyn Write, format: Format, columns: &[Column], path: &str, entry: &Entry, details: &Details) -> io::Result<()> {
+    let separator = if format == Format::Csv { "," } else { "\t" };
+    let mtime = entry.modified.duration_since(std::time::UNIX_EPOCH).map_or(0, |d| d.as_secs());
+    for (i, column) in columns.iter().enumerate() {
+        if i > 0 {
+            write!(handle, "{}", separator)?;
+        }
+        match column {
+            Column::Path => write!(handle, "{}", output::escape_field(format, path))?,
+            Column::Mtime => write!(handle, "{}", mtime)?,
+            Column::MtimeIso => write!(handle, "{}", time::format_utc(entry.modified))?,
+            Column::Size => write!(handle, "{}", entry.size().map_or(String::new(), |size| size.to_string()))?,
+            Column::Type => write!(handle, "{}", entry.kind())?,
+            Column::Depth => write!(handle, "{}", details.depth.map_or(String::new(), |depth| depth.to_string()))?,
+            Column::Content => write!(handle, "{}", details.content.map_or("", ContentKind::name))?,
+            Column::Label => write!(handle, "{}", output::escape_field(format, details.label.unwrap_or("")))?,
+        }
+    }
     Ok(())
 }
 
@@ -39,7 +183,20 @@ fn print_lscolor_path(handle: &mut dyn Write, ls_colors: &LsColors, path: &str,
     if is_dir && !path.eq("/") {
         write!(handle, "/")?;
     }
-    writeln!(handle)?;
+    Ok(())
+}
+
+fn print_lscolor_basename(handle: &mut dyn Write, ls_colors: &LsColors, path: &str, real_path: &Path, is_dir: bool) -> io::Result<()> {
+    let split = path.rfind('/').map_or(0, |i| i + 1);
+    write!(handle, "{}", &path[..split])?;
+    #[cfg(any(feature = "nu-ansi-term", feature = "gnu_legacy"))]
+    {
+        let ansi_style = ls_colors.style_for_path(real_path).map(Style::to_nu_ansi_term_style).unwrap_or_default();
+        write!(handle, "{}", ansi_style.paint(&path[split..]))?;
+    }
+    if is_dir && !path.eq("/") {
+        write!(handle, "/")?;
+    }
     Ok(())
 }
 
@@ -51,117 +208,719 @@ fn is_dir(entry: &DirEntry) -> bool {
         .unwrap_or(false)
 }
 
-fn starts_with_word(entry: &ignore::DirEntry, word: &str) -> bool {
-    entry.path().to_str().map_or(false, |path| path.starts_with(word))
+/// Whether `dir` is one of the kernel's pseudo-filesystems (or /dev, /run), which
+/// `--system-safe` keeps out of the walk. Only directories with one of their names
+/// are resolved, so the check stays cheap.
+fn is_system_pseudo_dir(dir: &Path) -> bool {
+    dir.file_name().is_some_and(|name| SYSTEM_PSEUDO_DIRS.iter().any(|pseudo| name == &pseudo[1..]))
+        && fs::canonicalize(dir).is_ok_and(|resolved| SYSTEM_PSEUDO_DIRS.iter().any(|pseudo| resolved == Path::new(pseudo)))
 }
 
-fn build_entries(dirs_only: bool, max_depth: Option<usize>, current_dir: &PathBuf, leftover: String) -> Vec<(DirEntry, SystemTime)> {
-    // Use max threads
-    let num_threads = num_cpus::get();
+fn starts_with_word(entry: &ignore::DirEntry, word: &str, ignore_case: bool) -> bool {
+    path_starts_with_word(entry.path(), word, ignore_case)
+}
 
-    // Builder for current_dir
-    let mut builder = WalkBuilder::new(&current_dir);
+/// With `ignore_case`, `word` must already be lowercase.
+fn path_starts_with_word(path: &Path, word: &str, ignore_case: bool) -> bool {
+    match path.to_str() {
+        Some(path) if ignore_case => path.to_lowercase().starts_with(word),
+        Some(path) => path.starts_with(word),
+        None => false,
+    }
+}
 
-    // Ignore ".git/" sub-path
-    let mut overrides = OverrideBuilder::new(&current_dir);
-    overrides.add("!**/.git/*").unwrap();
-    builder.overrides(overrides.build().unwrap());
+/// Walk settings that aren't per-entry filters.
+struct WalkOptions {
+    max_depth: Option<usize>,
+    min_depth: usize,
+    sort: bool,
+    // stat() every entry even when neither sorting nor the filters need it
+    metadata: bool,
+    hidden: bool,
+    // .gitignore, .git/info/exclude and the global excludes file
+    ignore_vcs: bool,
+    // The global excludes file (core.excludesFile) and .git/info/exclude, on their own
+    ignore_vcs_global: bool,
+    ignore_vcs_exclude: bool,
+    // Ignore files in the directories above the walk root
+    ignore_parents: bool,
+    ignore_fd: bool,
+    // Further gitignore-style files to respect, see --ignore-filename
+    ignore_filenames: Vec<String>,
+    // every ignore file, including .ignore and .sortfsignore
+    ignore: bool,
+    // What .sortfsignore ages count back from, see --now
+    now: SystemTime,
+    ignore_case: bool,
+    prune: Option<PruneGlobs>,
+    // Directory names whose contents are never listed, .git and .hg unless --include-vcs-dirs
+    excluded_dirs: Vec<String>,
+    // Walked together with the main root, see --root
+    extra_roots: Vec<PathBuf>,
+    same_file_system: bool,
+    // Devices whose directories aren't entered, see --exclude-device
+    excluded_devices: Vec<u64>,
+    // Longest wait for a stat() on a network filesystem
+    stat_timeout: Option<Duration>,
+    // Time after which the walk stops and lists what it found, see --timeout
+    timeout: Option<Duration>,
+    // Where progress is saved, and whether to pick it up, see --checkpoint
+    checkpoint: Option<Checkpoint>,
+    // Descend into symlinked directories and stat() link targets rather than the links
+    follow_links: bool,
+    // Symlinked directories followed along one path before descent stops
+    max_symlink_depth: Option<usize>,
+    // Order entries by depth first, see --bfs
+    breadth_first: bool,
+    threads: usize,
+    // List the files inside zip and tar archives, see --archives
+    archives: bool,
+    // Skip /proc, /sys, /dev and /run, see --system-safe
+    system_safe: bool,
+    // Shown each entry as soon as the walk lists it, see --output jsonl
+    stream: Option<Box<StreamFn>>,
+}
 
-    let current_dir_path = current_dir.display().to_string();
-    let leftover_mode = leftover.len() > 0;
+type StreamFn = dyn Fn(&Entry) + Send + Sync;
 
-    // Create walker from builder
-    let walker;
-    if dirs_only {
-        if leftover_mode {
-            walker = builder
-                .standard_filters(true)
-                .add_custom_ignore_filename(".fdignore")
-                .hidden(false)
-                .follow_links(true)
-                .filter_entry(move |entry| is_dir(entry) && starts_with_word(entry, &leftover)) // dir-only + leftover
-                .max_depth(max_depth)
-                .threads(num_threads)
-                .build_parallel();
+impl WalkOptions {
+    /// Ignores ".git/", ".hg/" and other excluded sub-paths.
+    fn excluded_dirs_override(&self, root: &Path) -> Override {
+        let mut overrides = OverrideBuilder::new(root);
+        for name in &self.excluded_dirs {
+            overrides.add(&format!("!**/{}/*", name)).unwrap();
+        }
+        overrides.build().unwrap()
+    }
+
+    /// Ignore files handed to the walker besides .gitignore and .ignore.
+    fn custom_ignore_filenames(&self) -> Vec<&str> {
+        let fdignore = Some(".fdignore").filter(|_| self.ignore_fd);
+        fdignore.into_iter()
+            .chain(self.ignore_filenames.iter().map(String::as_str))
+            .collect()
+    }
+
+    /// The settings that decide which entries a walk lists, as recorded in snapshots.
+    fn describe(&self) -> String {
+        let flag = |set: bool| if set { "yes" } else { "no" };
+        let depth = |depth: Option<usize>| depth.map_or("-".to_string(), |depth| depth.to_string());
+        format!("hidden={} ignore={} ignore-vcs={} fdignore={} min-depth={} max-depth={} one-file-system={}",
+                flag(self.hidden), flag(self.ignore), flag(self.ignore_vcs), flag(self.ignore_fd),
+                self.min_depth, depth(self.max_depth), flag(self.same_file_system))
+    }
+}
+
+impl Default for WalkOptions {
+    fn default() -> WalkOptions {
+        WalkOptions {
+            max_depth: None,
+            min_depth: 0,
+            sort: true,
+            metadata: false,
+            hidden: true,
+            ignore_vcs: true,
+            ignore_vcs_global: true,
+            ignore_vcs_exclude: true,
+            ignore_parents: true,
+            ignore_fd: true,
+            ignore_filenames: Vec::new(),
+            ignore: true,
+            now: SystemTime::now(),
+            ignore_case: false,
+            prune: None,
+            excluded_dirs: vec![".git".to_string(), ".hg".to_string()],
+            extra_roots: Vec::new(),
+            same_file_system: false,
+            excluded_devices: Vec::new(),
+            stat_timeout: None,
+            timeout: None,
+            checkpoint: None,
+            follow_links: false,
+            max_symlink_depth: None,
+            breadth_first: false,
+            threads: num_cpus::get(),
+            archives: false,
+            system_safe: false,
+            stream: None,
+        }
+    }
+}
+
+/// How listed paths are shown: relative to PREFIX, unless they belong to another
+/// root or --full-path or --prefix-target say otherwise.
+struct PathDisplay {
+    prefix_dir: PathBuf,
+    target_dir: String,
+    // PREFIX as the walker's paths start with it
+    leading_path: String,
+    extra_roots: Vec<PathBuf>,
+    full_path: bool,
+    prefix_target: bool,
+    root_from_list: bool,
+    stdin_mode: bool,
+    // Only the last component, see --basename
+    basename: bool,
+}
+
+impl PathDisplay {
+    /// The text listed for `path`, or None for a root itself.
+    fn show(&self, path: &Path) -> Option<String> {
+        let extra_root = self.extra_roots.iter().find(|root| path.starts_with(root));
+        if extra_root.is_some_and(|root| path == root) || (self.root_from_list && path == self.prefix_dir) {
+            return None;
+        }
+        if self.basename {
+            // A root has no name of its own to show
+            return path.file_name().filter(|_| path != self.prefix_dir).map(|name| name.to_string_lossy().into_owned());
+        }
+        let path_disp = if self.prefix_target && extra_root.is_none() {
+            format!("{}{}{}", self.target_dir, std::path::MAIN_SEPARATOR, path.display())
+        } else {
+            format!("{}", path.display())
+        };
+        // Entries of other roots keep their root, or they couldn't be told apart
+        if self.full_path || extra_root.is_some() || self.root_from_list || self.stdin_mode {
+            Some(path_disp)
+        } else if path_disp.len() > self.leading_path.len() {
+            Some(path_disp[winpath::relative_start(&self.leading_path)..].to_string())
         } else {
-            walker = builder
-                .standard_filters(true)
-                .add_custom_ignore_filename(".fdignore")
-                .hidden(false)
-                .follow_links(true)
-                .filter_entry(move |entry| is_dir(entry)) // dir-only
-                .max_depth(max_depth)
-                .threads(num_threads)
-                .build_parallel();
+            None
         }
+    }
+
+    /// Components of `path` below the root it was found under.
+    fn depth(&self, path: &Path) -> Option<usize> {
+        iter::once(&self.prefix_dir).chain(&self.extra_roots)
+            .filter_map(|root| path.strip_prefix(root).ok())
+            .map(|relative| relative.components().count())
+            .min()
+    }
+}
+
+fn build_entries(filters: &Filters, options: &WalkOptions, current_dir: &PathBuf, leftover: String, profile: Option<&WalkProfile>) -> Vec<Entry> {
+    // Builder for current_dir
+    let mut builder = WalkBuilder::new(current_dir);
+    for root in &options.extra_roots {
+        builder.add(root);
+    }
+
+    builder.overrides(options.excluded_dirs_override(current_dir));
+
+    let current_dir_path = current_dir.display().to_string();
+    let leftover_mode = !leftover.is_empty();
+    let ignore_case = options.ignore_case;
+    let leftover = if ignore_case { leftover.to_lowercase() } else { leftover };
+    let leftover_prefix = leftover.clone();
+    let dirs_only = filters.file_types.is_dirs_only();
+    let prune = options.prune.clone();
+    let system_safe = options.system_safe;
+    // A resumed walk lists what the checkpoint has and leaves its complete directories out
+    let resumed = options.checkpoint.as_ref().filter(|checkpoint| checkpoint.resume).and_then(Checkpoint::load);
+    let (complete, resumed_paths) = resumed.unwrap_or_default();
+    let resumed_entries = checkpoint::restat(&resumed_paths, filters, options.follow_links);
+    let progress = options.checkpoint.as_ref().map(|_| Arc::new(Progress::new(complete)));
+    let walk_progress = progress.clone();
+    let excluded_devices = options.excluded_devices.clone();
+    // Mercurial has no ignore-file support in the walker, so its rules are applied here
+    let hg_ignores: Vec<HgIgnore> = if options.ignore && options.ignore_vcs {
+        iter::once(current_dir).chain(&options.extra_roots).filter_map(|root| HgIgnore::load(root)).collect()
     } else {
-        if leftover_mode {
-            walker = builder
-                .standard_filters(true)
-                .add_custom_ignore_filename(".fdignore")
-                .hidden(false)
-                .follow_links(true)
-                .filter_entry(move |entry| starts_with_word(entry, &leftover)) // leftover
-                .max_depth(max_depth)
-                .threads(num_threads)
-                .build_parallel();
-        } else {
-            walker = builder
-                .standard_filters(true)
-                .add_custom_ignore_filename(".fdignore")
-                .hidden(false)
-                .follow_links(true)
-                .max_depth(max_depth)
-                .threads(num_threads)
-                .build_parallel();
+        Vec::new()
+    };
+    // It would read .sortfsignore conditions as part of the glob
+    let sortfs_ignore = options.ignore.then(|| {
+        let roots: Vec<PathBuf> = iter::once(current_dir).chain(&options.extra_roots).cloned().collect();
+        SortfsIgnore::new(&roots, options.now, options.ignore_parents)
+    });
+    // Nor does it find a submodule's .git/info/exclude
+    let submodule_excludes = (options.ignore && options.ignore_vcs && options.ignore_vcs_exclude).then(SubmoduleExcludes::new);
+    // Name-only queries can rely on the walker's d_type and skip stat() entirely
+    let needs_metadata = options.sort || options.metadata || filters.needs_metadata();
+
+    builder
+        .standard_filters(true)
+        .ignore(options.ignore)
+        .parents(options.ignore && options.ignore_parents)
+        .git_ignore(options.ignore && options.ignore_vcs)
+        .git_global(options.ignore && options.ignore_vcs && options.ignore_vcs_global)
+        .git_exclude(options.ignore && options.ignore_vcs && options.ignore_vcs_exclude);
+    if options.ignore {
+        for name in options.custom_ignore_filenames() {
+            builder.add_custom_ignore_filename(name);
         }
     }
 
-    // Run the walker to collect (entry, modified) vector
-    let results = Arc::new(Mutex::new(Vec::new()));
+    // Create walker from builder
+    let walker = builder
+        .hidden(!options.hidden)
+        .follow_links(options.follow_links)
+        .same_file_system(options.same_file_system)
+        .filter_entry(move |entry| {
+            if prune.as_ref().is_some_and(|prune| is_dir(entry) && prune.prunes(entry.path())) {
+                return false;
+            }
+            if system_safe && is_dir(entry) && is_system_pseudo_dir(entry.path()) {
+                return false;
+            }
+            if !excluded_devices.is_empty() && is_dir(entry)
+                && entry.metadata().ok().and_then(|meta| device::of(&meta)).is_some_and(|dev| excluded_devices.contains(&dev))
+            {
+                return false;
+            }
+            if hg_ignores.iter().any(|hg_ignore| hg_ignore.is_ignored(entry.path())) {
+                return false;
+            }
+            if submodule_excludes.as_ref().is_some_and(|excludes| excludes.is_ignored(entry.path(), is_dir(entry))) {
+                return false;
+            }
+            if sortfs_ignore.as_ref().is_some_and(|rules| rules.is_ignored(entry.path(), is_dir(entry), || entry.metadata().ok())) {
+                return false;
+            }
+            if walk_progress.as_ref().is_some_and(|progress| progress.is_complete(entry.path())) {
+                walk_progress.as_ref().unwrap().skipped_complete(entry.path());
+                return false;
+            }
+            let walked = (!dirs_only || is_dir(entry)) && (!leftover_mode || starts_with_word(entry, &leftover, ignore_case));
+            if let (true, Some(progress)) = (walked, &walk_progress) {
+                progress.queued(entry.path());
+            }
+            walked
+        })
+        .max_depth(options.max_depth)
+        .threads(options.threads)
+        .build_parallel();
+
+    // Run the walker to collect entries
+    let leftover_prefix = leftover_prefix.as_str();
+    let min_depth = options.min_depth;
+    let remote_mounts = options.stat_timeout
+        .map(|_| RemoteMounts::detect())
+        .filter(|mounts| !mounts.is_empty());
+    let remote_mounts = remote_mounts.as_ref();
+    if let Some(stream) = &options.stream {
+        resumed_entries.iter().for_each(stream);
+    }
+    let results = Arc::new(Mutex::new(resumed_entries));
+    let progress = progress.as_deref();
+    // Directories reached through symlinks, with the number of links on the way
+    let link_depths: Mutex<HashMap<PathBuf, usize>> = Mutex::new(HashMap::new());
+    let link_depths = &link_depths;
+    let deadline = options.timeout.map(|timeout| Instant::now() + timeout);
+    let timed_out = &AtomicBool::new(false);
+    let out_of_memory = &AtomicBool::new(false);
     walker.run(|| {
+        let saved_results = Arc::clone(&results);
         let results = Arc::clone(&results);
+        let mut recorder = profile.map(WalkProfile::recorder);
+        let mut visit = move |entry: Result<DirEntry, ignore::Error>| {
+            if deadline.is_some_and(|deadline| Instant::now() >= deadline) {
+                timed_out.store(true, Ordering::Relaxed);
+                return ignore::WalkState::Quit;
+            }
+            let entry = match entry {
+                Ok(entry) => entry,
+                Err(err) => {
+                    // follow_links() reports dangling symlinks and symlink loops as errors
+                    let entry = broken_symlink_entry(&err).or_else(|| {
+                        let (child, ancestor) = symlink_loop(&err)?;
+                        eprintln!("sortfs: {}: symlink loop back to {}, not followed", child.display(), ancestor.display());
+                        Entry::from_path(child)
+                    });
+                    if let Some(entry) = entry {
+                        let depth = iter::once(current_dir).chain(&options.extra_roots)
+                            .find_map(|root| entry.path().strip_prefix(root).ok())
+                            .map_or(0, |path| path.components().count());
+                        if depth >= min_depth && filters.matches(&entry) && (!leftover_mode || path_starts_with_word(entry.path(), leftover_prefix, ignore_case))
+                            && !collect(&results, vec![entry], out_of_memory, options.stream.as_deref())
+                        {
+                            return ignore::WalkState::Quit;
+                        }
+                    }
+                    return ignore::WalkState::Continue;
+                }
+            };
+            // Count the directory links followed to get here, see --max-symlink-depth
+            let mut walk_state = ignore::WalkState::Continue;
+            if let (Some(max_links), true) = (options.max_symlink_depth, is_dir(&entry)) {
+                let mut link_depths = link_depths.lock().unwrap();
+                let parent_links = entry.path().parent().and_then(|parent| link_depths.get(parent)).copied().unwrap_or(0);
+                let links = parent_links + usize::from(entry.path_is_symlink());
+                if links > max_links {
+                    walk_state = ignore::WalkState::Skip;
+                } else if links > 0 {
+                    link_depths.insert(entry.path().to_path_buf(), links);
+                }
+            }
+            // Shallower levels are still walked, just not listed
+            if entry.depth() < min_depth {
+                return walk_state;
+            }
+            if let Some(recorder) = recorder.as_mut() {
+                recorder.start_entry();
+            }
+            let started = Instant::now();
+            // Bounded on remote mounts, see --stat-timeout
+            let remote_stat = remote_mounts.zip(options.stat_timeout)
+                .filter(|_| needs_metadata)
+                .and_then(|(mounts, timeout)| mounts.metadata(entry.path(), timeout));
+            let meta = match (needs_metadata, remote_stat) {
+                (false, _) => None,
+                (true, Some(Stat::Done(meta))) => meta,
+                (true, Some(Stat::TimedOut(mount_point))) => {
+                    eprintln!("sortfs: {}: stat timed out, {} and everything else on it listed without metadata",
+                              entry.path().display(), mount_point.display());
+                    None
+                }
+                (true, Some(Stat::Skipped)) => None,
+                (true, None) if options.follow_links => metadata(entry.path()).ok(),
+                (true, None) => entry.path().symlink_metadata().ok(),
+            };
+            if let Some(recorder) = recorder.as_mut() {
+                recorder.stat_done(started);
+            }
+            let entry = match Entry::from_dir_entry(entry, meta) {
+                Some(entry) => entry,
+                None => return walk_state,
+            };
+            let started = Instant::now();
+            let matched = filters.matches(&entry);
+            if let Some(recorder) = recorder.as_mut() {
+                recorder.filter_done(started);
+                recorder.finish_entry(entry.path());
+            }
+            // Archives are looked into whether or not they match themselves
+            if options.archives && entry.file_type.is_file() {
+                let members = archive::members(&entry, filters);
+                if !members.is_empty() && !collect(&results, members, out_of_memory, options.stream.as_deref()) {
+                    return ignore::WalkState::Quit;
+                }
+            }
+            if matched && !collect(&results, vec![entry], out_of_memory, options.stream.as_deref()) {
+                return ignore::WalkState::Quit;
+            }
+            walk_state
+        };
+        // The directory this thread handed over last; the walker has read it by the time the thread is back
+        let mut last_dir: Option<PathBuf> = None;
         Box::new(move |entry| {
-            if let Ok(entry) = entry {
-                let modified = metadata(entry.path())
-                    .and_then(|meta| meta.modified())
-                    .unwrap_or(SystemTime::UNIX_EPOCH); // default to UNIX_EPOCH if error
-                let mut results = results.lock().unwrap();
-                results.push((entry, modified));
-            }
-            ignore::WalkState::Continue
+            let (checkpoint, progress) = match (&options.checkpoint, progress) {
+                (Some(checkpoint), Some(progress)) => (checkpoint, progress),
+                _ => return visit(entry),
+            };
+            let visited = entry.as_ref().ok().map(|entry| (entry.path().to_path_buf(), is_dir(entry)));
+            if visited.is_some() {
+                if let Some(dir) = last_dir.take() {
+                    progress.read(&dir);
+                }
+            }
+            if let Some((dir, true)) = &visited {
+                progress.entered(dir);
+            }
+            let walk_state = visit(entry);
+            match (visited, &walk_state) {
+                (_, ignore::WalkState::Quit) => {}
+                (Some((dir, true)), ignore::WalkState::Continue) => last_dir = Some(dir),
+                (Some((dir, true)), _) => progress.read(&dir),
+                (Some((path, false)), _) => progress.listed(&path),
+                (None, _) => {}
+            }
+            if progress.save_due() {
+                if let Err(e) = checkpoint.save(progress, &saved_results.lock().unwrap()) {
+                    eprintln!("sortfs: warning: {}: {}", checkpoint.file.display(), e);
+                }
+            }
+            walk_state
         })
     });
 
-    let mut results = results.lock().unwrap();
+    if timed_out.load(Ordering::Relaxed) {
+        eprintln!("sortfs: warning: walk stopped after {:?}, results are partial", options.timeout.unwrap());
+    }
+    if out_of_memory.load(Ordering::Relaxed) {
+        eprintln!("sortfs: warning: out of memory for more entries, results are partial");
+    }
+    let stopped = timed_out.load(Ordering::Relaxed) || out_of_memory.load(Ordering::Relaxed);
+    if stopped {
+        WALK_PARTIAL.store(true, Ordering::Relaxed);
+    }
+    if let (Some(checkpoint), Some(progress)) = (&options.checkpoint, progress) {
+        if stopped {
+            match checkpoint.save(progress, &results.lock().unwrap()) {
+                Ok(()) => eprintln!("sortfs: progress saved, continue with --resume"),
+                Err(e) => eprintln!("sortfs: warning: {}: {}", checkpoint.file.display(), e),
+            }
+        } else {
+            checkpoint.remove();
+        }
+    }
+    let mut results = mem::take(&mut *results.lock().unwrap());
 
     // Remove the first entry (walk target) for the leftover mode
-    if leftover_mode && results.len() > 0 {
-        let (top_entry, _) = results.get(0).unwrap();
+    if leftover_mode && !results.is_empty() {
+        let top_entry = results.first().unwrap();
         if current_dir_path.eq(&top_entry.path().display().to_string()) {
             results.remove(0);
         }
     }
 
-    // Sort the results by the "modified"
-    results.par_sort_by(|(_a, a_modified), (_b, b_modified)| {
-        b_modified.cmp(&a_modified)
-    });
+    // Sort the results by the "modified", within each level for a breadth-first listing
+    if options.breadth_first {
+        let roots: Vec<&Path> = iter::once(current_dir.as_path()).chain(options.extra_roots.iter().map(PathBuf::as_path)).collect();
+        let depth = |entry: &Entry| {
+            let root = roots.iter().find(|root| entry.path().starts_with(root));
+            root.map_or(entry.path(), |root| entry.path().strip_prefix(root).unwrap()).components().count()
+        };
+        if options.sort {
+            results.par_sort_by_cached_key(|entry| (depth(entry), std::cmp::Reverse(entry.modified)));
+        } else {
+            results.par_sort_by_cached_key(depth);
+        }
+    } else if options.sort && out_of_memory.load(Ordering::Relaxed) {
+        // A stable sort needs a buffer the size of half the results; this one sorts in place
+        results.par_sort_unstable_by(|a, b| b.modified.cmp(&a.modified));
+    } else if options.sort {
+        results.par_sort_by(|a, b| b.modified.cmp(&a.modified));
+    }
+
+    results
+}
+
+/// Stats the given paths in parallel instead of walking (`--stdin`), keeping the paths as
+/// written. Paths that don't exist are skipped.
+fn entries_from_paths(paths: &[String], filters: &Filters, sort: bool) -> Vec<Entry> {
+    let mut entries: Vec<Entry> = paths.par_iter()
+        .filter_map(|path| {
+            let path = Path::new(path);
+            Entry::from_path(path).or_else(|| Entry::from_broken_symlink(path))
+        })
+        .filter(|entry| filters.matches(entry))
+        .collect();
+    if sort {
+        entries.par_sort_by(|a, b| b.modified.cmp(&a.modified));
+    }
+    entries
+}
+
+/// Adds `entries` to a walk's results. Once memory runs short, the walk is better
+/// stopped with what it has than aborted with nothing: this returns false and sets
+/// `out_of_memory` instead.
+fn collect(results: &Mutex<Vec<Entry>>, entries: Vec<Entry>, out_of_memory: &AtomicBool, stream: Option<&StreamFn>) -> bool {
+    let mut results = results.lock().unwrap();
+    if memory::reserve_used() || results.try_reserve(entries.len()).is_err() {
+        out_of_memory.store(true, Ordering::Relaxed);
+        return false;
+    }
+    if let Some(stream) = stream {
+        entries.iter().for_each(stream);
+    }
+    results.extend(entries);
+    true
+}
+
+/// Recovers the dangling symlink behind a walker error, if that's what it was.
+fn broken_symlink_entry(err: &ignore::Error) -> Option<Entry> {
+    match err {
+        ignore::Error::WithDepth { err, .. } => broken_symlink_entry(err),
+        ignore::Error::WithPath { path, .. } => Entry::from_broken_symlink(path),
+        _ => None,
+    }
+}
+
+/// Keeps one entry per file reached through several paths (`--dedupe`), at the first one's
+/// rank. The most direct path wins: not a link itself, then the fewest components.
+fn dedupe_files(entries: Vec<Entry>) -> Vec<Entry> {
+    let directness = |entry: &Entry| (entry.is_symlink, entry.path().components().count());
+    let mut kept: Vec<Entry> = Vec::with_capacity(entries.len());
+    let mut positions = HashMap::new();
+    for entry in entries {
+        let id = match entry.file_id() {
+            Some(id) => id,
+            None => {
+                kept.push(entry);
+                continue;
+            }
+        };
+        match positions.get(&id) {
+            Some(&position) => {
+                if directness(&entry) < directness(&kept[position]) {
+                    kept[position] = entry;
+                }
+            }
+            None => {
+                positions.insert(id, kept.len());
+                kept.push(entry);
+            }
+        }
+    }
+    kept
+}
+
+/// The link and the directory it leads back to, when the walker hit a symlink cycle.
+fn symlink_loop(err: &ignore::Error) -> Option<(&Path, &Path)> {
+    match err {
+        ignore::Error::WithDepth { err, .. } | ignore::Error::WithPath { err, .. } => symlink_loop(err),
+        ignore::Error::Loop { ancestor, child } => Some((child, ancestor)),
+        _ => None,
+    }
+}
+
+/// Size of every entry; with `cumulative`, directories also count everything listed below
+/// them, like du(1).
+fn size_totals(entries: &[Entry], disk_usage: bool, cumulative: bool) -> Vec<u64> {
+    let sizes = entries.iter().map(|entry| entry.measured_size(disk_usage).unwrap_or(0));
+    if !cumulative {
+        return sizes.collect();
+    }
+    let mut totals: HashMap<&Path, u64> = HashMap::new();
+    for (entry, size) in entries.iter().zip(sizes) {
+        for path in entry.path().ancestors() {
+            *totals.entry(path).or_default() += size;
+        }
+    }
+    entries.iter().map(|entry| totals[entry.path()]).collect()
+}
+
+/// Puts the directories between each entry and its root before it (`--with-parents`).
+/// Every directory is listed once: before its first descendant, or where it matched.
+fn with_parents(entries: Vec<Entry>, roots: &[PathBuf]) -> Vec<Entry> {
+    let mut listed: HashSet<PathBuf> = HashSet::new();
+    let mut with_parents = Vec::with_capacity(entries.len());
+    for entry in entries {
+        if listed.contains(entry.path()) {
+            continue;
+        }
+        if let Some(root) = roots.iter().find(|root| entry.path().starts_with(root)) {
+            // Listing a directory lists its ancestors, so the first listed one ends the search
+            let missing: Vec<&Path> = entry.path().ancestors()
+                .skip(1)
+                .take_while(|ancestor| ancestor != root && !listed.contains(*ancestor))
+                .collect();
+            for ancestor in missing.into_iter().rev() {
+                with_parents.extend(Entry::from_path(ancestor));
+                listed.insert(ancestor.to_path_buf());
+            }
+        }
+        listed.insert(entry.path().to_path_buf());
+        with_parents.push(entry);
+    }
+    with_parents
+}
+
+/// Whether the listing shows or works with what only stat() tells (times, sizes,
+/// owners), so the walk must fetch it even when it doesn't sort. Every output mode
+/// and post-walk step that reads `Entry::metadata` or `modified` belongs here.
+fn listing_needs_metadata(matches: &ArgMatches, output_format: Format) -> bool {
+    const STAT_ARGS: [&str; 14] = [
+        "group-by", "group-by-age", "format", "long", "size-column", "time-style", "age",
+        "dedupe", "track-replacements", "save-snapshot", "since-last", "recency", "sink",
+        "split-by-age",
+    ];
+    output_format != Format::Plain
+        || matches!(matches.value_of("sort"), Some("churn" | "size" | "du"))
+        || STAT_ARGS.iter().any(|arg| matches.is_present(arg))
+}
+
+/// Orders entries into age buckets (keeping their order within each) and names the
+/// first entry of each non-empty bucket, see --group-by-age.
+fn group_by_age(entries: Vec<Entry>, now: SystemTime) -> (Vec<Entry>, Vec<(usize, String)>) {
+    let days = |count: u64| now.checked_sub(Duration::from_secs(count * 24 * 60 * 60)).unwrap_or(SystemTime::UNIX_EPOCH);
+    let buckets = [
+        ("Today", time::parse_datetime("today", now).unwrap_or_else(|| days(1))),
+        ("Yesterday", time::parse_datetime("yesterday", now).unwrap_or_else(|| days(2))),
+        ("This week", days(7)),
+        ("This month", days(30)),
+        ("Older", SystemTime::UNIX_EPOCH),
+    ];
+    let bucket = |entry: &Entry| buckets.iter().position(|(_, start)| entry.modified >= *start).unwrap_or(buckets.len() - 1);
+    let mut entries = entries;
+    entries.sort_by_key(|entry| bucket(entry));
+    let mut headers: Vec<(usize, String)> = Vec::new();
+    let mut previous = None;
+    for (index, entry) in entries.iter().enumerate() {
+        let current = bucket(entry);
+        if previous != Some(current) {
+            headers.push((index, buckets[current].0.to_string()));
+            previous = Some(current);
+        }
+    }
+    (entries, headers)
+}
+
+/// Clusters entries by owning user. Users are ordered by their newest entry and
+/// each cluster keeps the incoming order; returns the entries with the index at
+/// which every user's header belongs.
+fn group_by_user(entries: Vec<Entry>) -> (Vec<Entry>, Vec<(usize, String)>) {
+    let mut groups: Vec<(Option<u32>, Vec<Entry>)> = Vec::new();
+    let mut positions = HashMap::new();
+    for entry in entries {
+        let uid = entry.uid();
+        let position = *positions.entry(uid).or_insert_with(|| {
+            groups.push((uid, Vec::new()));
+            groups.len() - 1
+        });
+        groups[position].1.push(entry);
+    }
+    let newest = |group: &Vec<Entry>| group.iter().map(|entry| entry.modified).max();
+    groups.sort_by_key(|(_, group)| std::cmp::Reverse(newest(group)));
 
-    results.to_vec()
+    let mut grouped = Vec::new();
+    let mut headers = Vec::new();
+    for (uid, group) in groups {
+        let name = uid.map_or_else(|| "?".to_string(), users::user_name);
+        headers.push((grouped.len(), name));
+        grouped.extend(group);
+    }
+    (grouped, headers)
 }
 
-fn normalize_path(path: &str) -> std::io::Result<String> {
+fn normalize_path(path: &str, keep_links: bool) -> std::io::Result<String> {
     let path = Path::new(path);
-    let canonical_path = fs::canonicalize(path)?;
+    if keep_links {
+        return absolute_path(path).map(|path| winpath::simplify(path).to_string_lossy().into_owned());
+    }
+    let canonical_path = winpath::simplify(fs::canonicalize(path)?);
     Ok(canonical_path.to_string_lossy().into_owned())
 }
 
+/// Makes `path` absolute without resolving symlinks, so a symlinked PREFIX keeps its name.
+fn absolute_path(path: &Path) -> io::Result<PathBuf> {
+    // Fail the same way canonicalize() would for a missing target
+    metadata(path)?;
+    lexically_absolute(path)
+}
+
+/// `path` joined to the working directory, with `.` and `..` resolved by name alone.
+fn lexically_absolute(path: &Path) -> io::Result<PathBuf> {
+    let mut absolute = env::current_dir()?;
+    for component in path.components() {
+        match component {
+            Component::CurDir => {}
+            Component::ParentDir => {
+                absolute.pop();
+            }
+            component => absolute.push(component),
+        }
+    }
+    Ok(absolute)
+}
+
+/// Non-empty lines of FILE, or of stdin for `-`.
+fn read_lines(source: &str) -> io::Result<Vec<String>> {
+    let content = if source == "-" {
+        io::read_to_string(io::stdin())?
+    } else {
+        fs::read_to_string(source)?
+    };
+    Ok(content.lines().map(str::trim).filter(|line| !line.is_empty()).map(String::from).collect())
+}
+
 fn main() -> io::Result<()> {
+    memory::set_aside();
     let ls_colors = LsColors::from_env().unwrap_or_default();
 
-    let stdout = io::stdout();
-    let mut writer = BufWriter::new(stdout.lock());
+    // Not locked for good: walker threads write to it themselves with --output jsonl
+    let mut writer = BufWriter::new(io::stdout());
 
     let matches = App::new("sortfs")
         .version("1.0")
@@ -182,117 +941,1667 @@ fn main() -> io::Result<()> {
                 .help("Show directories only")
         )
         .arg(
-            Arg::with_name("full-path")
-                .short("f")
-                .long("full-path")
-                .help("Show fullpath")
+            Arg::with_name("hidden")
+                .short("H")
+                .long("hidden")
+                .overrides_with("no-hidden")
+                .help("Include hidden files and directories (default)")
         )
         .arg(
-            Arg::with_name("color")
-                .short("c")
-                .long("color")
-                .help("Use ls-colors")
+            Arg::with_name("no-hidden")
+                .long("no-hidden")
+                .overrides_with("hidden")
+                .help("Skip hidden files and don't descend into hidden directories")
         )
         .arg(
-            Arg::with_name("prefix-target")
-                .short("p")
-                .long("prefix-target")
-                .help("Put the target-dir as prefix")
+            Arg::with_name("case-sensitive")
+                .short("s")
+                .long("case-sensitive")
+                .overrides_with("ignore-case")
+                .help("Match LEFTOVER case-sensitively (default)")
         )
         .arg(
-            Arg::with_name("max-depth")
-                .short("m")
-                .long("max-depth")
+            Arg::with_name("ignore-case")
+                .short("i")
+                .long("ignore-case")
+                .overrides_with("case-sensitive")
+                .help("Match LEFTOVER case-insensitively")
+        )
+        .arg(
+            Arg::with_name("no-ignore")
+                .short("I")
+                .long("no-ignore")
+                .help("Don't respect .gitignore, .ignore, .fdignore or .sortfsignore files")
+        )
+        .arg(
+            Arg::with_name("no-ignore-vcs")
+                .long("no-ignore-vcs")
+                .help("Don't respect .gitignore, .hgignore, .git/info/exclude or the global git excludes file")
+        )
+        .arg(
+            Arg::with_name("no-global-ignore-file")
+                .long("no-global-ignore-file")
+                .help("Don't respect the global git excludes file (core.excludesFile)")
+        )
+        .arg(
+            Arg::with_name("no-ignore-exclude")
+                .long("no-ignore-exclude")
+                .help("Don't respect .git/info/exclude")
+        )
+        .arg(
+            Arg::with_name("no-ignore-parent")
+                .long("no-ignore-parent")
+                .help("Don't respect ignore files in the directories above PREFIX")
+        )
+        .arg(
+            Arg::with_name("no-fdignore")
+                .long("no-fdignore")
+                .help("Don't respect .fdignore files")
+        )
+        .arg(
+            Arg::with_name("ignore-filename")
+                .long("ignore-filename")
                 .takes_value(true)
-                .help("max depth for directory walk through")
+                .multiple(true)
+                .number_of_values(1)
+                .value_name("NAME")
+                .help("Also respect ignore files named NAME, like .fdignore (e.g. .agignore)")
         )
-        .get_matches();
-
-    let dirs_only = matches.is_present("dirs-only");
-    let full_path = matches.is_present("full-path");
-    let color = matches.is_present("color");
-    let mut prefix_target = matches.is_present("prefix-target");
-    if full_path {
-        prefix_target = false;
-    }
-
-    let mut target_dir = matches.value_of("PREFIX").unwrap_or(".");
-    target_dir = target_dir.trim_end_matches('/');
-
-    let leftover_val = matches.value_of("LEFTOVER").unwrap_or("");
-
-    let max_depth = matches.value_of("max-depth").unwrap_or("");
-    let max_depth: Option<usize> = match max_depth.parse::<usize>() {
-        Ok(n) => Some(n),
-        Err(_) => None
-    };
-
-    let prefix_dir;
-    let leftover;
-    if full_path {
-        match normalize_path(target_dir) {
-            Ok(normalized) => {
-                prefix_dir = PathBuf::from(normalized.clone());
-                if leftover_val.len() > 0 {
-                    leftover = format!("{}/{}", normalized, leftover_val).to_string();
-                } else {
-                    leftover = "".to_string();
-                }
-            },
-            Err(e) => {
-                eprintln!("Error: {}", e);
-                process::exit(1);
-            }
-        }
-    } else {
-        prefix_dir = PathBuf::from(target_dir);
-        if leftover_val.len() > 0 {
-            leftover = format!("{}/{}", target_dir, leftover_val).to_string();
-        } else {
-            leftover = "".to_string();
-        }
-    }
-    let entries = build_entries(dirs_only, max_depth, &prefix_dir, leftover);
-    let mut leading_path = prefix_dir.to_str().unwrap();
-    leading_path = leading_path.trim_end_matches('/');
-
-    let mut is_first = true;
-    for e in &entries {
-        let path = e.0.path();
-        let path_disp;
-        if prefix_target {
-            path_disp = format!("{}/{}", target_dir, path.display());
-        } else {
-            path_disp = format!("{}", path.display());
-        }
-        let res;
-        if full_path {
-            if color {
-                res = print_lscolor_path(&mut writer, &ls_colors, path_disp.as_ref(), path.is_dir());
-            } else {
-                res = print_path(&mut writer, path_disp.as_ref(), path.is_dir());
-            }
-        } else {
-            if path_disp.len() > leading_path.len() {
-                if color {
-                    res = print_lscolor_path(&mut writer, &ls_colors, path_disp[leading_path.len() + 1..].as_ref(), path.is_dir());
-                } else {
-                    res = print_path(&mut writer, path_disp[leading_path.len() + 1..].as_ref(), path.is_dir());
-                }
-            } else {
-                res = Ok(());
-            }
-        }
-        if let Err(_) = res {
-            process::exit(1);
-        }
-
-        if is_first == true {
-            writer.flush().unwrap();
-            is_first = false;
-        }
-    }
-
-    writer.flush().unwrap();
+        .arg(
+            Arg::with_name("one-file-system")
+                .short("x")
+                .long("one-file-system")
+                .help("Don't descend into directories on other filesystems than their root")
+        )
+        .arg(
+            Arg::with_name("exclude-device")
+                .long("exclude-device")
+                .takes_value(true)
+                .multiple(true)
+                .number_of_values(1)
+                .value_name("DEV")
+                .help("Skip directories on device DEV (a device number, MAJOR:MINOR or mount source like /dev/sdb1) and everything below them")
+        )
+        .arg(
+            Arg::with_name("include-device")
+                .long("include-device")
+                .takes_value(true)
+                .multiple(true)
+                .number_of_values(1)
+                .value_name("DEV")
+                .help("Only list entries on device DEV, given like for --exclude-device")
+        )
+        .arg(
+            Arg::with_name("glob")
+                .short("g")
+                .long("glob")
+                .takes_value(true)
+                .multiple(true)
+                .number_of_values(1)
+                .value_name("GLOB")
+                .help("Only show entries whose path below the root matches GLOB; use **/ to match at any depth")
+        )
+        .arg(
+            Arg::with_name("unanchored")
+                .long("unanchored")
+                .requires("glob")
+                .help("Let --glob patterns match at any depth, as if they started with **/")
+        )
+        .arg(
+            Arg::with_name("follow")
+                .short("L")
+                .long("follow")
+                .overrides_with("no-follow")
+                .help("Descend into symlinked directories (and junctions on Windows) and sort links by their target's mtime")
+        )
+        .arg(
+            Arg::with_name("no-follow")
+                .long("no-follow")
+                .overrides_with("follow")
+                .help("List symlinks and junctions themselves, with their own mtime (default)")
+        )
+        .arg(
+            Arg::with_name("archives")
+                .long("archives")
+                .conflicts_with_all(&["action", "stdin"])
+                .help("Also list the files inside zip and tar(.gz) archives, as ARCHIVE!/PATH")
+        )
+        .arg(
+            Arg::with_name("dedupe")
+                .long("dedupe")
+                .help("List files reached through several symlinked paths only once, by their most direct path")
+        )
+        .arg(
+            Arg::with_name("max-symlink-depth")
+                .long("max-symlink-depth")
+                .takes_value(true)
+                .value_name("N")
+                .requires("follow")
+                .help("Follow at most N symlinked directories along a path; deeper links are listed but not entered")
+        )
+        .arg(
+            Arg::with_name("stat-timeout")
+                .long("stat-timeout")
+                .takes_value(true)
+                .value_name("DURATION")
+                .help("On network filesystems (NFS, SMB, sshfs, ...) list an entry without its metadata when \
+                       stat() takes longer than this, and the rest of that filesystem too (defaults to 5s, 0 waits forever)")
+        )
+        .arg(
+            Arg::with_name("timeout")
+                .long("timeout")
+                .takes_value(true)
+                .value_name("DURATION")
+                .help("Stop walking after DURATION and list what was found so far, warning that it's partial")
+        )
+        .arg(
+            Arg::with_name("strict")
+                .long("strict")
+                .help("Exit with status 1 when the listing is partial (--timeout, or out of memory), without running --action")
+        )
+        .arg(
+            Arg::with_name("checkpoint")
+                .long("checkpoint")
+                .conflicts_with_all(&["stdin", "archives"])
+                .help("Save the walk's progress every 15 seconds (and when --timeout stops it), for --resume")
+        )
+        .arg(
+            Arg::with_name("resume")
+                .long("resume")
+                .conflicts_with_all(&["stdin", "archives"])
+                .help("Continue the walk saved by an interrupted --checkpoint run of the same command, and checkpoint it")
+        )
+        .arg(
+            Arg::with_name("prune")
+                .long("prune")
+                .takes_value(true)
+                .multiple(true)
+                .number_of_values(1)
+                .value_name("GLOB")
+                .help("Skip directories matching GLOB without descending into them")
+        )
+        .arg(
+            Arg::with_name("system-safe")
+                .long("system-safe")
+                .overrides_with("no-system-safe")
+                .help("Skip /proc, /sys, /dev and /run wherever the walk reaches them (the default when walking /)")
+        )
+        .arg(
+            Arg::with_name("no-system-safe")
+                .long("no-system-safe")
+                .overrides_with("system-safe")
+                .help("Walk /proc, /sys, /dev and /run like any other directory, even from /")
+        )
+        .arg(
+            Arg::with_name("exclude-dir")
+                .long("exclude-dir")
+                .takes_value(true)
+                .multiple(true)
+                .number_of_values(1)
+                .value_name("NAME")
+                .help("Never list the contents of directories named NAME, like .git (e.g. .hg, node_modules)")
+        )
+        .arg(
+            Arg::with_name("include-vcs-dirs")
+                .long("include-vcs-dirs")
+                .help("List the contents of .git and .hg directories too")
+        )
+        .arg(
+            Arg::with_name("type")
+                .short("t")
+                .long("type")
+                .takes_value(true)
+                .multiple(true)
+                .number_of_values(1)
+                .possible_values(&["f", "file", "d", "dir", "directory", "l", "symlink",
+                                   "x", "executable", "e", "empty", "s", "special"])
+                .help("Filter by type: file (f), directory (d), symlink (l), executable (x), empty (e), socket/special (s)")
+        )
+        .arg(
+            Arg::with_name("empty")
+                .short("e")
+                .long("empty")
+                .help("Only show empty files and directories without children (same as -t e)")
+        )
+        .arg(
+            Arg::with_name("size")
+                .short("S")
+                .long("size")
+                .takes_value(true)
+                .multiple(true)
+                .number_of_values(1)
+                .allow_hyphen_values(true)
+                .help("Limit results by file size: +10M (at least), -4k (at most), 512 (exactly)")
+        )
+        .arg(
+            Arg::with_name("changed-within")
+                .long("changed-within")
+                .takes_value(true)
+                .help("Only show entries modified within the duration (e.g. 2d, 10h) or since the date (e.g. 2024-01-31)")
+        )
+        .arg(
+            Arg::with_name("changed-before")
+                .long("changed-before")
+                .takes_value(true)
+                .help("Only show entries modified before the duration (e.g. 1w) or the date (e.g. 2024-01-31 12:00)")
+        )
+        .arg(
+            Arg::with_name("now")
+                .long("now")
+                .takes_value(true)
+                .value_name("TIMESTAMP")
+                .help("Evaluate relative times (durations, today, .sortfsignore ages, \
+                       --time-style relative, --age, --group-by-age) against TIMESTAMP instead of the current time")
+        )
+        .arg(
+            Arg::with_name("newer-than")
+                .long("newer-than")
+                .takes_value(true)
+                .value_name("FILE")
+                .help("Only show entries modified more recently than FILE")
+        )
+        .arg(
+            Arg::with_name("owner")
+                .short("o")
+                .long("owner")
+                .takes_value(true)
+                .value_name("user:group")
+                .allow_hyphen_values(true)
+                .help("Filter by owning user and/or group (names or ids, prefix with '!' to exclude)")
+        )
+        .arg(
+            Arg::with_name("readable")
+                .long("readable")
+                .help("Only show entries the current user can read")
+        )
+        .arg(
+            Arg::with_name("writable")
+                .long("writable")
+                .help("Only show entries the current user can write")
+        )
+        .arg(
+            Arg::with_name("perm")
+                .long("perm")
+                .takes_value(true)
+                .value_name("MODE")
+                .allow_hyphen_values(true)
+                .help("Filter by octal mode like find -perm: 0644 (exactly), -0644 (all bits), /0111 (any bit)")
+        )
+        .arg(
+            Arg::with_name("links")
+                .long("links")
+                .takes_value(true)
+                .value_name("N")
+                .allow_hyphen_values(true)
+                .help("Filter by hard link count like find -links: +1 (more than one), -2 (fewer than two), 1 (exactly)")
+        )
+        .arg(
+            Arg::with_name("has-xattr")
+                .long("has-xattr")
+                .takes_value(true)
+                .multiple(true)
+                .number_of_values(1)
+                .value_name("NAME[=VALUE]")
+                .help("Only show entries carrying the extended attribute, optionally with this exact value")
+        )
+        .arg(
+            Arg::with_name("broken-symlinks")
+                .long("broken-symlinks")
+                .help("Only show symlinks whose target doesn't exist")
+        )
+        .arg(
+            Arg::with_name("no-broken-symlinks")
+                .long("no-broken-symlinks")
+                .conflicts_with("broken-symlinks")
+                .help("Omit symlinks whose target doesn't exist")
+        )
+        .arg(
+            Arg::with_name("mime")
+                .long("mime")
+                .takes_value(true)
+                .multiple(true)
+                .number_of_values(1)
+                .value_name("TYPE")
+                .help("Only show files whose content looks like the MIME type (e.g. image/*, video/mp4)")
+        )
+        .arg(
+            Arg::with_name("text")
+                .long("text")
+                .visible_alias("text-only")
+                .help("Only show text files (no NUL byte in the first 8000 bytes)")
+        )
+        .arg(
+            Arg::with_name("detect-text")
+                .long("detect-text")
+                .help("Mark each file as text or binary in an extra column (a content field or column in the other --output formats and --sink records)")
+        )
+        .arg(
+            Arg::with_name("binary")
+                .long("binary")
+                .conflicts_with("text")
+                .help("Only show binary files")
+        )
+        .arg(
+            Arg::with_name("tracked")
+                .long("tracked")
+                .help("Only show files tracked by git (and directories containing them)")
+        )
+        .arg(
+            Arg::with_name("git-status")
+                .long("git-status")
+                .takes_value(true)
+                .multiple(true)
+                .number_of_values(1)
+                .possible_values(&["modified", "untracked", "staged"])
+                .help("Only show entries in the given git working-tree state")
+        )
+        .arg(
+            Arg::with_name("full-path")
+                .short("f")
+                .long("full-path")
+                .help("Show fullpath")
+        )
+        .arg(
+            Arg::with_name("keep-link-prefix")
+                .long("keep-link-prefix")
+                .help("With --full-path, keep a symlinked target-dir as typed instead of resolving it")
+        )
+        .arg(
+            Arg::with_name("color")
+                .short("c")
+                .long("color")
+                .help("Use ls-colors")
+        )
+        .arg(
+            Arg::with_name("color-style")
+                .long("color-style")
+                .takes_value(true)
+                .possible_values(&["full", "basename"])
+                .help("Color every path component (full) or only the final one (basename); implies --color")
+        )
+        .arg(
+            Arg::with_name("prefix-target")
+                .short("p")
+                .long("prefix-target")
+                .help("Put the target-dir as prefix")
+        )
+        .arg(
+            Arg::with_name("repo-root")
+                .long("repo-root")
+                .help("Walk from the nearest directory at or above PREFIX that contains .git")
+        )
+        .arg(
+            Arg::with_name("root")
+                .long("root")
+                .takes_value(true)
+                .multiple(true)
+                .number_of_values(1)
+                .value_name("DIR")
+                .conflicts_with_all(&["LEFTOVER", "tracked", "git-status"])
+                .help("Also walk DIR and merge its entries into the same listing (repeatable)")
+        )
+        .arg(
+            Arg::with_name("roots-from")
+                .long("roots-from")
+                .takes_value(true)
+                .value_name("FILE")
+                .conflicts_with_all(&["LEFTOVER", "tracked", "git-status"])
+                .help("Also walk the directories listed one per line in FILE, or stdin for -")
+        )
+        .arg(
+            Arg::with_name("all-drives")
+                .long("all-drives")
+                .conflicts_with_all(&["PREFIX", "LEFTOVER", "roots-from", "repo-root", "tracked", "git-status"])
+                .help("Walk every fixed drive together (Windows only)")
+        )
+        .arg(
+            Arg::with_name("stdin")
+                .long("stdin")
+                .conflicts_with_all(&["PREFIX", "LEFTOVER", "root", "roots-from", "all-drives", "tracked", "git-status"])
+                .help("Don't walk; sort the paths read from stdin, one per line (e.g. from fd or git ls-files)")
+        )
+        .arg(
+            Arg::with_name("max-depth")
+                .short("m")
+                .long("max-depth")
+                .takes_value(true)
+                .help("max depth for directory walk through")
+        )
+        .arg(
+            Arg::with_name("min-depth")
+                .long("min-depth")
+                .takes_value(true)
+                .help("Only show entries at least this deep (1 for the direct children)")
+        )
+        .arg(
+            Arg::with_name("exact-depth")
+                .long("exact-depth")
+                .takes_value(true)
+                .conflicts_with_all(&["max-depth", "min-depth"])
+                .help("Only show entries at exactly this depth")
+        )
+        .arg(
+            Arg::with_name("candidates-for")
+                .long("candidates-for")
+                .takes_value(true)
+                .possible_values(&["cd"])
+                .help("Emit completion candidates for the given command (cd: directories only)")
+        )
+        .arg(
+            Arg::with_name("sort")
+                .long("sort")
+                .takes_value(true)
+                .possible_values(&["mtime", "churn", "size", "du"])
+                .help("Sort key: modification time (default), churn since the --since snapshot, size, \
+                       or du (size including everything listed below a directory)")
+        )
+        .arg(
+            Arg::with_name("disk-usage")
+                .long("disk-usage")
+                .help("Measure sizes by the space allocated on disk instead of the apparent size")
+        )
+        .arg(
+            Arg::with_name("since")
+                .long("since")
+                .takes_value(true)
+                .value_name("DATE|SNAPSHOT")
+                .help("Only show entries modified since the date or duration, or compare against a \
+                       snapshot file saved by --save-snapshot")
+        )
+        .arg(
+            Arg::with_name("until")
+                .long("until")
+                .takes_value(true)
+                .value_name("DATE")
+                .help("Only show entries modified until the date or duration (e.g. 2024-02-01, yesterday)")
+        )
+        .arg(
+            Arg::with_name("track-replacements")
+                .long("track-replacements")
+                .help("Treat files replaced by a new inode since the --since snapshot as changed at the \
+                       time of replacement, even if their mtime was preserved")
+        )
+        .arg(
+            Arg::with_name("merge-mru")
+                .long("merge-mru")
+                .takes_value(true)
+                .value_name("FILE")
+                .help("Rank files by the newer of their mtime and their last use in FILE, a list of \
+                       PATH or EPOCH<TAB>PATH lines such as an editor's recent files")
+        )
+        .arg(
+            Arg::with_name("recency")
+                .long("recency")
+                .takes_value(true)
+                .value_name("SOURCE[,SOURCE]")
+                .help("Rank by the time from the first source that knows an entry: mtime, ctime, \
+                       git (last commit) or mru:FILE (e.g. git,mtime)")
+        )
+        .arg(
+            Arg::with_name("save-snapshot")
+                .long("save-snapshot")
+                .takes_value(true)
+                .value_name("FILE")
+                .help("Save the listing as a snapshot for later --sort churn runs")
+        )
+        .arg(
+            Arg::with_name("since-last")
+                .long("since-last")
+                .conflicts_with_all(&["porcelain", "stdin"])
+                .help("Mark entries new (+) or modified (~) since the last --since-last run on the same directory")
+        )
+        .arg(
+            Arg::with_name("no-sort")
+                .long("no-sort")
+                .help("Print entries in walk order without sorting by modification time")
+        )
+        .arg(
+            Arg::with_name("threads")
+                .short("j")
+                .long("threads")
+                .takes_value(true)
+                .value_name("N")
+                .help("Number of threads for walking and formatting (defaults to the number of CPUs)")
+        )
+        .arg(
+            Arg::with_name("nice-io")
+                .long("nice-io")
+                .help("Go easy on the disk: stat() with fewer threads at once and, on Linux, at idle I/O priority")
+        )
+        .arg(
+            Arg::with_name("bfs")
+                .long("bfs")
+                .help("List shallow entries before deeper ones, newest first within each level unless --no-sort")
+        )
+        .arg(
+            Arg::with_name("group-by")
+                .long("group-by")
+                .takes_value(true)
+                .possible_values(&["owner"])
+                .help("Cluster results under per-owner headers, most recently active owner first")
+        )
+        .arg(
+            Arg::with_name("group-by-age")
+                .long("group-by-age")
+                .conflicts_with_all(&["group-by", "porcelain", "format", "tree", "grid", "print0", "split-by-age", "with-parents"])
+                .help("List results under Today, Yesterday, This week, This month and Older headers")
+        )
+        .arg(
+            Arg::with_name("stats")
+                .long("stats")
+                .alias("debug")
+                .conflicts_with("stdin")
+                .help("Report to stderr how many entries each ignore source excluded, by top-level \
+                       directory and extension (walks the tree once per source)")
+        )
+        .arg(
+            Arg::with_name("profile-walk")
+                .long("profile-walk")
+                .help("Report the slowest directories of the walk on stderr")
+        )
+        .arg(
+            Arg::with_name("annotate")
+                .long("annotate")
+                .takes_value(true)
+                .value_name("FILE.tsv")
+                .help("Append labels from a PATH<TAB>LABEL file to matching entries (a label field or column in the other --output formats and --sink records)")
+        )
+        .arg(
+            Arg::with_name("porcelain")
+                .long("porcelain")
+                .takes_value(true)
+                .min_values(0)
+                .max_values(1)
+                .require_equals(true)
+                .possible_values(&["v1"])
+                .value_name("VERSION")
+                .conflicts_with_all(&["group-by", "annotate", "detect-text"])
+                .help("Stable tab-separated output for scripts: kind, epoch mtime, size and path (v1)")
+        )
+        .arg(
+            Arg::with_name("output")
+                .long("output")
+                .takes_value(true)
+                .value_name("FORMAT")
+                .possible_values(Format::NAMES)
+                .conflicts_with("porcelain")
+                .help("Output format: plain paths (default), porcelain lines as with --porcelain, a json array, jsonl objects (printed during the walk with --no-sort), or tsv or csv --columns")
+        )
+        .arg(
+            Arg::with_name("format")
+                .long("format")
+                .takes_value(true)
+                .value_name("TEMPLATE")
+                .conflicts_with_all(&["output", "porcelain", "group-by", "annotate", "detect-text"])
+                .help("Print each entry as TEMPLATE, with {path}, {basename}, {parent}, {mtime} or {mtime:STRFTIME}, {epoch}, {size} and {type} filled in")
+        )
+        .arg(
+            Arg::with_name("basename")
+                .long("basename")
+                .conflicts_with_all(&["tree", "full-path", "prefix-target"])
+                .help("Print only the last component of each path, still ordered across the whole walk")
+        )
+        .arg(
+            Arg::with_name("tree")
+                .long("tree")
+                .conflicts_with_all(&["output", "porcelain", "format", "group-by", "print0"])
+                .help("Draw the results as a tree, each under its nearest listed directory, with siblings newest first")
+        )
+        .arg(
+            Arg::with_name("grid")
+                .short("C")
+                .long("grid")
+                .conflicts_with_all(&["output", "porcelain", "format", "group-by", "print0", "tree", "long",
+                                      "annotate", "detect-text", "output-file", "split-by-age"])
+                .help("Pack the results into columns across the terminal, newest first down each column, like ls -C")
+        )
+        .arg(
+            Arg::with_name("long")
+                .short("l")
+                .long("long")
+                .conflicts_with_all(&["output", "porcelain", "format"])
+                .help("Show permissions, owner, size and modification time in front of each path, like ls -l")
+        )
+        .arg(
+            Arg::with_name("time-style")
+                .long("time-style")
+                .takes_value(true)
+                .value_name("STYLE")
+                .conflicts_with_all(&["output", "porcelain", "format"])
+                .help("Show each entry's modification time in front of its path (or in the --long column) as iso, long-iso, full-iso, relative, age or +FORMAT (strftime)")
+        )
+        .arg(
+            Arg::with_name("age")
+                .long("age")
+                .conflicts_with_all(&["time-style", "output", "porcelain", "format"])
+                .help("Show each entry's age in front of its path, compactly: 45s, 3m, 2h, 5d, 8w, 2y (--time-style age)")
+        )
+        .arg(
+            Arg::with_name("size-column")
+                .long("size-column")
+                .conflicts_with_all(&["output", "porcelain", "format"])
+                .help("Show each entry's size in front of its path, in ls -h units (1.4M, 23K); see --disk-usage")
+        )
+        .arg(
+            Arg::with_name("print0")
+                .short("0")
+                .long("print0")
+                .conflicts_with("group-by")
+                .help("End each entry with a NUL instead of a newline, for xargs -0 and fzf --read0")
+        )
+        .arg(
+            Arg::with_name("columns")
+                .long("columns")
+                .takes_value(true)
+                .value_name("LIST")
+                .requires("output")
+                .help("Fields of --output tsv and csv lines, comma separated: path, mtime, mtime_iso, size, type, depth, content (with --detect-text), label (with --annotate) (default: path,mtime,size, then content and label when those are on)")
+        )
+        .arg(
+            Arg::with_name("output-file")
+                .long("output-file")
+                .takes_value(true)
+                .value_name("FILE")
+                .conflicts_with("split-by-age")
+                .help("Write the listing to FILE instead of stdout")
+        )
+        .arg(
+            Arg::with_name("tee")
+                .long("tee")
+                .requires("output-file")
+                .help("Also print plain paths to stdout while --output-file gets the --output format")
+        )
+        .arg(
+            Arg::with_name("action")
+                .long("action")
+                .takes_value(true)
+                .value_name("copy:DEST|move:DEST|trash|delete")
+                .help("After listing, copy or move the listed entries below DEST (keeping their relative paths), \
+                       move them to the trash or delete them")
+        )
+        .arg(
+            Arg::with_name("dry-run")
+                .long("dry-run")
+                .requires("action")
+                .help("Show what --action would do without touching any file")
+        )
+        .arg(
+            Arg::with_name("force")
+                .long("force")
+                .requires("action")
+                .help("Allow --action delete")
+        )
+        .arg(
+            Arg::with_name("yes")
+                .short("y")
+                .long("yes")
+                .requires("action")
+                .help("Apply --action without asking for confirmation")
+        )
+        .arg(
+            Arg::with_name("sink")
+                .long("sink")
+                .takes_value(true)
+                .value_name("unix:PATH|fifo:PATH")
+                .help("Also stream results as NDJSON to a Unix socket or named pipe")
+        )
+        .arg(
+            Arg::with_name("split-by-age")
+                .long("split-by-age")
+                .takes_value(true)
+                .value_name("DIR")
+                .conflicts_with("group-by")
+                .help("Write results to today.txt, week.txt, month.txt and older.txt in DIR instead of stdout")
+        )
+        .arg(
+            Arg::with_name("max-results")
+                .short("n")
+                .long("max-results")
+                .takes_value(true)
+                .value_name("N")
+                .help("Only show the first N entries (the N newest unless --no-sort)")
+        )
+        .arg(
+            Arg::with_name("with-parents")
+                .long("with-parents")
+                .conflicts_with("group-by")
+                .help("Also list each entry's parent directories, once each and before their first entry")
+        )
+        .arg(
+            Arg::with_name("per-dir-limit")
+                .long("per-dir-limit")
+                .takes_value(true)
+                .value_name("N")
+                .help("Show at most N entries from any one directory, so a single busy directory can't crowd out the rest")
+        )
+        .arg(
+            Arg::with_name("tty-limit")
+                .long("tty-limit")
+                .takes_value(true)
+                .value_name("LINES")
+                .help("Stop after this many lines when writing to a terminal (defaults to 1000)")
+        )
+        .arg(
+            Arg::with_name("no-limit-tty")
+                .long("no-limit-tty")
+                .help("Print every entry even when writing to a terminal")
+        )
+        .subcommand(
+            SubCommand::with_name("doctor")
+                .about("Exercise walking, sorting, ignore handling and colors on a scratch tree")
+        )
+        .subcommand(
+            SubCommand::with_name("warm")
+                .about("Stat every entry at idle I/O priority so that later queries hit the OS caches")
+                .arg(
+                    Arg::with_name("PATH")
+                        .help("Directory to warm up (defaults to current directory)")
+                        .index(1)
+                )
+        )
+        .subcommand(
+            SubCommand::with_name("snapshot")
+                .about("Work with files written by --save-snapshot")
+                .setting(AppSettings::SubcommandRequiredElseHelp)
+                .subcommand(
+                    SubCommand::with_name("inspect")
+                        .about("Show a snapshot's format version, environment and entry summary")
+                        .arg(
+                            Arg::with_name("FILE")
+                                .help("Snapshot file")
+                                .required(true)
+                                .index(1)
+                        )
+                )
+        )
+        .get_matches();
+
+    if matches.subcommand_matches("doctor").is_some() {
+        return doctor::run();
+    }
+    if let Some(warm) = matches.subcommand_matches("warm") {
+        return warm::run(Path::new(warm.value_of("PATH").unwrap_or(".")));
+    }
+    if let Some(inspect) = matches.subcommand_matches("snapshot").and_then(|snapshot| snapshot.subcommand_matches("inspect")) {
+        let file = Path::new(inspect.value_of("FILE").unwrap());
+        return match Snapshot::load(file) {
+            Ok(snapshot) => snapshot.describe(&mut io::stdout()),
+            Err(e) => {
+                eprintln!("Error: {}: {}", file.display(), e);
+                process::exit(1);
+            }
+        };
+    }
+
+    let threads = match matches.value_of("threads") {
+        Some(count) => match count.parse::<usize>() {
+            Ok(count) if count > 0 => count,
+            _ => {
+                eprintln!("Error: '{}' is not a valid thread count", count);
+                process::exit(1);
+            }
+        },
+        None if matches.is_present("nice-io") => NICE_IO_THREADS,
+        None => num_cpus::get(),
+    };
+    if matches.is_present("nice-io") {
+        // Before any thread is started, so the walker threads inherit the class
+        if let Err(e) = priority::idle_io() {
+            eprintln!("sortfs: warning: running at normal I/O priority: {}", e);
+        }
+    }
+    // Also bounds the formatting, content detection and --stdin stat() passes
+    let _ = rayon::ThreadPoolBuilder::new().num_threads(threads).build_global();
+
+    let cd_candidates = matches.value_of("candidates-for") == Some("cd");
+    let mut type_values: Vec<&str> = matches.values_of("type").map(|values| values.collect()).unwrap_or_default();
+    if matches.is_present("dirs-only") {
+        type_values.push("d");
+    }
+    if matches.is_present("empty") {
+        type_values.push("e");
+    }
+    let file_types = if cd_candidates {
+        FileTypes::from_values(["d"].iter().copied())
+    } else if type_values.is_empty() {
+        FileTypes::all()
+    } else {
+        FileTypes::from_values(type_values.into_iter())
+    };
+    // fish completes directories with a trailing slash, other shells add it themselves
+    let trailing_slash = !cd_candidates || env::var("SHELL").is_ok_and(|shell| shell.ends_with("/fish"));
+    let full_path = matches.is_present("full-path");
+    let output_format = if matches.is_present("porcelain") {
+        Format::Porcelain
+    } else {
+        matches.value_of("output").and_then(Format::from_name).unwrap_or(Format::Plain)
+    };
+    let delimited = matches!(output_format, Format::Tsv | Format::Csv);
+    let template = matches.value_of("format").map(|template| {
+        Template::parse(template).unwrap_or_else(|e| {
+            eprintln!("Error: --format: {}", e);
+            process::exit(1);
+        })
+    });
+    if matches.is_present("columns") && !delimited {
+        eprintln!("Error: --columns is for --output tsv and --output csv");
+        process::exit(1);
+    }
+    let mut columns = Column::parse_list(matches.value_of("columns").unwrap_or(Column::DEFAULT)).unwrap_or_else(|e| {
+        eprintln!("Error: --columns: {}", e);
+        process::exit(1);
+    });
+    for (column, name, arg) in [(Column::Content, "content", "detect-text"), (Column::Label, "label", "annotate")] {
+        if !matches.is_present(arg) && columns.contains(&column) {
+            eprintln!("Error: --columns: the {} column needs --{}", name, arg);
+            process::exit(1);
+        }
+        // Without --columns, whatever was asked for is shown rather than dropped
+        if matches.is_present(arg) && !matches.is_present("columns") {
+            columns.push(column);
+        }
+    }
+    // clap only knows that --porcelain conflicts with them, not --output porcelain
+    if let Some(arg) = ["annotate", "detect-text"].iter().find(|arg| output_format == Format::Porcelain && matches.is_present(arg)) {
+        eprintln!("Error: --{} doesn't apply to porcelain output, whose v1 lines are fixed", arg);
+        process::exit(1);
+    }
+    if output_format == Format::Json && matches.is_present("print0") {
+        eprintln!("Error: --print0 doesn't apply to --output json, which is one array");
+        process::exit(1);
+    }
+    if output_format == Format::Json && matches.is_present("split-by-age") {
+        eprintln!("Error: --output json writes a single array and can't be split with --split-by-age");
+        process::exit(1);
+    }
+    let color = matches.is_present("color") || matches.is_present("color-style");
+    let color_basename = matches.value_of("color-style") == Some("basename");
+    let mut prefix_target = matches.is_present("prefix-target");
+    if full_path {
+        prefix_target = false;
+    }
+
+    // With no PREFIX, the first directory read by --roots-from (or --all-drives) stands in for it
+    let roots_from = match matches.value_of("roots-from") {
+        Some(source) => read_lines(source).unwrap_or_else(|e| {
+            eprintln!("Error: {}: {}", source, e);
+            process::exit(1);
+        }),
+        None if matches.is_present("all-drives") => drives::fixed_drives().unwrap_or_else(|e| {
+            eprintln!("Error: {}", e);
+            process::exit(1);
+        }),
+        None => Vec::new(),
+    };
+    let root_from_list = matches.value_of("PREFIX").is_none() && !roots_from.is_empty();
+    if matches.is_present("roots-from") && roots_from.is_empty() && matches.value_of("PREFIX").is_none() {
+        return Ok(());
+    }
+    let mut target_dir = matches.value_of("PREFIX")
+        .or_else(|| roots_from.first().map(String::as_str))
+        .unwrap_or(".");
+    target_dir = winpath::trim_separators(target_dir);
+    let repo_root;
+    if matches.is_present("repo-root") {
+        repo_root = git::enclosing_root(target_dir).unwrap_or_else(|| {
+            eprintln!("Error: no git repository contains {}", if target_dir.is_empty() { "/" } else { target_dir });
+            process::exit(1);
+        });
+        target_dir = winpath::trim_separators(&repo_root);
+    }
+
+    let leftover_val = matches.value_of("LEFTOVER").unwrap_or("");
+
+    let max_depth = matches.value_of("max-depth").unwrap_or("");
+    let max_depth: Option<usize> = max_depth.parse::<usize>().ok();
+    let min_depth = matches.value_of("min-depth").unwrap_or("");
+    let min_depth: usize = min_depth.parse::<usize>().unwrap_or(0);
+    let exact_depth: Option<usize> = matches.value_of("exact-depth").and_then(|depth| depth.parse::<usize>().ok());
+    let (min_depth, max_depth) = match exact_depth {
+        Some(depth) => (depth, Some(depth)),
+        None => (min_depth, max_depth),
+    };
+
+    let prefix_dir;
+    let leftover;
+    if full_path {
+        match normalize_path(target_dir, matches.is_present("keep-link-prefix")) {
+            Ok(normalized) => {
+                prefix_dir = PathBuf::from(normalized.clone());
+                if !leftover_val.is_empty() {
+                    leftover = format!("{}/{}", normalized, leftover_val).to_string();
+                } else {
+                    leftover = "".to_string();
+                }
+            },
+            Err(e) => {
+                eprintln!("Error: {}", e);
+                process::exit(1);
+            }
+        }
+    } else {
+        prefix_dir = PathBuf::from(target_dir);
+        if !leftover_val.is_empty() {
+            leftover = format!("{}/{}", target_dir, leftover_val).to_string();
+        } else {
+            leftover = "".to_string();
+        }
+    }
+    let listed_roots = roots_from.iter().skip(if root_from_list { 1 } else { 0 }).map(String::as_str);
+    let extra_roots: Vec<PathBuf> = matches.values_of("root").into_iter().flatten().chain(listed_roots).map(|root| {
+        let root = winpath::trim_separators(root);
+        if !full_path {
+            return PathBuf::from(if root.is_empty() { "/" } else { root });
+        }
+        match normalize_path(root, matches.is_present("keep-link-prefix")) {
+            Ok(normalized) => PathBuf::from(normalized),
+            Err(e) => {
+                eprintln!("Error: {}: {}", root, e);
+                process::exit(1);
+            }
+        }
+    }).collect();
+    let roots: Vec<PathBuf> = iter::once(prefix_dir.clone()).chain(extra_roots.iter().cloned()).collect();
+    let size_limits = matches.values_of("size")
+        .map(|values| values.map(SizeFilter::from_string).collect::<Result<Vec<_>, _>>())
+        .unwrap_or_else(|| Ok(Vec::new()))
+        .unwrap_or_else(|e| {
+            eprintln!("Error: {}", e);
+            process::exit(1);
+        });
+    let owner = matches.value_of("owner").map(|value| {
+        OwnerFilter::from_string(value).unwrap_or_else(|e| {
+            eprintln!("Error: {}", e);
+            process::exit(1);
+        })
+    });
+    let perm = matches.value_of("perm").map(|value| {
+        PermFilter::from_string(value).unwrap_or_else(|e| {
+            eprintln!("Error: {}", e);
+            process::exit(1);
+        })
+    });
+    let links = matches.value_of("links").map(|value| {
+        LinksFilter::from_string(value).unwrap_or_else(|e| {
+            eprintln!("Error: {}", e);
+            process::exit(1);
+        })
+    });
+    let resolve_devices = |name| -> Vec<u64> {
+        matches.values_of(name).into_iter().flatten().flat_map(|spec| {
+            device::resolve(spec).unwrap_or_else(|e| {
+                eprintln!("Error: {}", e);
+                process::exit(1);
+            })
+        }).collect()
+    };
+    let devices = resolve_devices("include-device");
+    let excluded_devices = resolve_devices("exclude-device");
+    let xattrs = matches.values_of("has-xattr").map(|values| {
+        values.map(|value| XattrFilter::from_string(value).unwrap_or_else(|e| {
+            eprintln!("Error: {}", e);
+            process::exit(1);
+        })).collect()
+    }).unwrap_or_default();
+    let now = match matches.value_of("now") {
+        Some(value) => time::parse_datetime(value, SystemTime::now()).unwrap_or_else(|| {
+            eprintln!("Error: '{}' is not a date (e.g. 2024-01-31 12:00 or @1706702400)", value);
+            process::exit(1);
+        }),
+        None => SystemTime::now(),
+    };
+    let parse_time_arg = |name: &str| {
+        matches.value_of(name).map(|value| {
+            time::parse_time_bound(value, now).unwrap_or_else(|e| {
+                eprintln!("Error: {}", e);
+                process::exit(1);
+            })
+        })
+    };
+    let mut git_paths = Vec::new();
+    if matches.is_present("tracked") {
+        git_paths.push(GitPaths::tracked(&prefix_dir));
+    }
+    if let Some(states) = matches.values_of("git-status") {
+        git_paths.push(GitPaths::from_status(&prefix_dir, &states.collect::<Vec<_>>()));
+    }
+    let git_paths = git_paths.into_iter().collect::<Result<Vec<_>, _>>().unwrap_or_else(|e| {
+        eprintln!("Error: {}", e);
+        process::exit(1);
+    });
+    // --since names either a point in time or a snapshot file to compare against
+    let since_snapshot = matches.value_of("since").filter(|since| Path::new(since).is_file());
+    let since = if since_snapshot.is_none() { parse_time_arg("since") } else { None };
+    let changed_before = match (parse_time_arg("changed-before"), parse_time_arg("until")) {
+        (Some(before), Some(until)) => Some(before.min(until)),
+        (before, until) => before.or(until),
+    };
+    let filters = Filters {
+        file_types,
+        glob: matches.values_of("glob").map(|values| {
+            let patterns: Vec<&str> = values.collect();
+            GlobFilter::new(&roots, &patterns, !matches.is_present("unanchored")).unwrap_or_else(|e| {
+                eprintln!("Error: {}", e);
+                process::exit(1);
+            })
+        }),
+        size_limits,
+        disk_usage: matches.is_present("disk-usage"),
+        changed_after: parse_time_arg("changed-within").max(since),
+        changed_before,
+        newer_than: matches.value_of("newer-than").map(|reference| {
+            metadata(reference).and_then(|meta| meta.modified()).unwrap_or_else(|e| {
+                eprintln!("Error: {}: {}", reference, e);
+                process::exit(1);
+            })
+        }),
+        owner,
+        readable: matches.is_present("readable"),
+        writable: matches.is_present("writable"),
+        perm,
+        links,
+        devices,
+        xattrs,
+        mime_types: matches.values_of("mime").map(|values| values.map(String::from).collect()).unwrap_or_default(),
+        content: if matches.is_present("text") {
+            Some(ContentKind::Text)
+        } else if matches.is_present("binary") {
+            Some(ContentKind::Binary)
+        } else {
+            None
+        },
+        git_paths,
+        broken_symlinks: if matches.is_present("broken-symlinks") {
+            BrokenSymlinks::Only
+        } else if matches.is_present("no-broken-symlinks") {
+            BrokenSymlinks::Exclude
+        } else {
+            BrokenSymlinks::Include
+        },
+    };
+    let profile = if matches.is_present("profile-walk") {
+        Some(WalkProfile::new())
+    } else {
+        None
+    };
+    let group_by_owner = matches.value_of("group-by") == Some("owner");
+    let group_by_mtime = matches.is_present("group-by-age");
+    let sort_churn = matches.value_of("sort") == Some("churn");
+    let sort_size = matches!(matches.value_of("sort"), Some("size" | "du"));
+    let baseline = since_snapshot.map(|file| {
+        Snapshot::load(Path::new(file)).unwrap_or_else(|e| {
+            eprintln!("Error: {}: {}", file, e);
+            process::exit(1);
+        })
+    });
+    let last_run = matches.is_present("since-last").then(|| {
+        let file = snapshot::state_file(&prefix_dir).unwrap_or_else(|| {
+            eprintln!("Error: --since-last needs HOME or XDG_STATE_HOME to keep its state in");
+            process::exit(1);
+        });
+        // The first run has nothing to compare against and marks nothing
+        let previous = match Snapshot::load(&file) {
+            Ok(previous) => Some(previous),
+            Err(e) if e.kind() == io::ErrorKind::NotFound => None,
+            Err(e) => {
+                eprintln!("sortfs: warning: {}: {}", file.display(), e);
+                None
+            }
+        };
+        (file, previous)
+    });
+    let recency = matches.value_of("recency").map(|sources| {
+        Recency::from_string(sources, &prefix_dir).unwrap_or_else(|e| {
+            eprintln!("Error: {}", e);
+            process::exit(1);
+        })
+    });
+    let track_replacements = matches.is_present("track-replacements");
+    if track_replacements && baseline.is_none() {
+        eprintln!("Error: --track-replacements needs a --since SNAPSHOT to compare against");
+        process::exit(1);
+    }
+    if sort_churn && baseline.is_none() {
+        eprintln!("Error: --sort churn needs a --since SNAPSHOT to compare against");
+        process::exit(1);
+    }
+    let stdin_mode = matches.is_present("stdin");
+    let print0 = matches.is_present("print0");
+    let paths = Arc::new(PathDisplay {
+        prefix_dir: prefix_dir.clone(),
+        target_dir: target_dir.to_string(),
+        leading_path: winpath::trim_separators(prefix_dir.to_str().unwrap()).to_string(),
+        extra_roots: extra_roots.clone(),
+        full_path,
+        prefix_target,
+        root_from_list,
+        stdin_mode,
+        basename: matches.is_present("basename"),
+    });
+    // Lines can go out during the walk only if nothing after it reorders or drops entries
+    let reordering = ["sort", "bfs", "dedupe", "with-parents", "group-by", "group-by-age", "max-results", "per-dir-limit",
+                      "merge-mru", "recency", "track-replacements", "candidates-for"];
+    let stream = output_format == Format::Jsonl && matches.is_present("no-sort") && !stdin_mode
+        && !reordering.iter().any(|arg| matches.is_present(arg))
+        && !["output-file", "tee", "split-by-age", "detect-text", "annotate"].iter().any(|arg| matches.is_present(arg))
+        && !io::stdout().is_terminal();
+    let options = WalkOptions {
+        stream: stream.then(|| {
+            let paths = Arc::clone(&paths);
+            Box::new(move |entry: &Entry| {
+                if let Some(disp) = paths.show(entry.path()) {
+                    let mut line = Vec::new();
+                    if print_json(&mut line, &disp, entry, &Details { depth: paths.depth(entry.path()), ..Details::default() }).is_ok() {
+                        let _ = output::end_entry(&mut line, print0);
+                        if io::stdout().lock().write_all(&line).is_err() {
+                            process::exit(1);
+                        }
+                    }
+                }
+            }) as Box<StreamFn>
+        }),
+        max_depth,
+        min_depth,
+        sort: !matches.is_present("no-sort"),
+        breadth_first: matches.is_present("bfs"),
+        threads,
+        archives: matches.is_present("archives"),
+        // Walking / would otherwise wander into procfs and device nodes
+        system_safe: matches.is_present("system-safe")
+            || (!matches.is_present("no-system-safe") && roots.iter().any(|root| fs::canonicalize(root).is_ok_and(|root| root.parent().is_none()))),
+        metadata: listing_needs_metadata(&matches, output_format),
+        hidden: !matches.is_present("no-hidden"),
+        ignore_vcs: !matches.is_present("no-ignore-vcs"),
+        ignore_vcs_global: !matches.is_present("no-global-ignore-file"),
+        ignore_vcs_exclude: !matches.is_present("no-ignore-exclude"),
+        ignore_parents: !matches.is_present("no-ignore-parent"),
+        ignore_fd: !matches.is_present("no-fdignore"),
+        ignore_filenames: matches.values_of("ignore-filename").map(|values| values.map(String::from).collect()).unwrap_or_default(),
+        ignore: !matches.is_present("no-ignore"),
+        now,
+        ignore_case: matches.is_present("ignore-case"),
+        prune: matches.values_of("prune").map(|values| {
+            PruneGlobs::new(&prefix_dir, &values.collect::<Vec<_>>()).unwrap_or_else(|e| {
+                eprintln!("Error: {}", e);
+                process::exit(1);
+            })
+        }),
+        excluded_dirs: {
+            let mut names = if matches.is_present("include-vcs-dirs") { Vec::new() } else { vec![".git".to_string(), ".hg".to_string()] };
+            for name in matches.values_of("exclude-dir").into_iter().flatten() {
+                if name.is_empty() || name.contains('/') || globset::Glob::new(name).is_err() {
+                    eprintln!("Error: '{}' is not a directory name", name);
+                    process::exit(1);
+                }
+                names.push(name.to_string());
+            }
+            names
+        },
+        extra_roots: extra_roots.clone(),
+        same_file_system: matches.is_present("one-file-system"),
+        excluded_devices,
+        follow_links: matches.is_present("follow"),
+        max_symlink_depth: matches.value_of("max-symlink-depth").map(|depth| {
+            depth.parse::<usize>().unwrap_or_else(|_| {
+                eprintln!("Error: '{}' is not a valid link count", depth);
+                process::exit(1);
+            })
+        }),
+        stat_timeout: match matches.value_of("stat-timeout").unwrap_or(DEFAULT_STAT_TIMEOUT) {
+            "0" => None,
+            value => Some(time::parse_duration(value).unwrap_or_else(|| {
+                eprintln!("Error: '{}' is not a duration (e.g. 5s)", value);
+                process::exit(1);
+            })),
+        },
+        timeout: matches.value_of("timeout").map(|value| {
+            time::parse_duration(value).unwrap_or_else(|| {
+                eprintln!("Error: '{}' is not a duration (e.g. 2s)", value);
+                process::exit(1);
+            })
+        }),
+        checkpoint: (matches.is_present("checkpoint") || matches.is_present("resume")).then(|| {
+            let file = Checkpoint::file(&prefix_dir).unwrap_or_else(|| {
+                eprintln!("Error: --checkpoint needs HOME or XDG_STATE_HOME to keep its progress in");
+                process::exit(1);
+            });
+            Checkpoint { file, query: Checkpoint::query(), resume: matches.is_present("resume") }
+        }),
+    };
+    let environment = Environment::current(&prefix_dir, options.describe());
+    if let (Some(baseline), Some(file)) = (&baseline, since_snapshot) {
+        let (fatal, warnings) = baseline.environment.incompatibilities(&environment);
+        for warning in warnings {
+            eprintln!("sortfs: warning: snapshot {}: {}", file, warning);
+        }
+        if let Some(problem) = fatal.first() {
+            eprintln!("Error: can't compare against snapshot {}: {}", file, problem);
+            process::exit(1);
+        }
+    }
+    let mut entries = if stdin_mode {
+        let paths = read_lines("-").unwrap_or_else(|e| {
+            eprintln!("Error: stdin: {}", e);
+            process::exit(1);
+        });
+        entries_from_paths(&paths, &filters, options.sort)
+    } else {
+        build_entries(&filters, &options, &prefix_dir, leftover, profile.as_ref())
+    };
+    if matches.is_present("dedupe") {
+        entries = dedupe_files(entries);
+    }
+    if let Some(file) = matches.value_of("save-snapshot") {
+        if let Err(e) = Snapshot::save(Path::new(file), &entries, &prefix_dir, &environment) {
+            eprintln!("Error: {}: {}", file, e);
+            process::exit(1);
+        }
+    }
+    // Keyed by path, as the entries are reordered below
+    let mut changes: HashMap<PathBuf, Change> = HashMap::new();
+    if let Some((file, previous)) = &last_run {
+        if let Some(previous) = previous {
+            changes = entries.iter()
+                .filter_map(|e| previous.change(e, &prefix_dir).map(|change| (e.path.clone(), change)))
+                .collect();
+        }
+        let saved = file.parent().map_or(Ok(()), fs::create_dir_all)
+            .and_then(|_| Snapshot::save(file, &entries, &prefix_dir, &environment));
+        if let Err(e) = saved {
+            eprintln!("Error: {}: {}", file.display(), e);
+            process::exit(1);
+        }
+    }
+    if let (true, Some(baseline)) = (track_replacements, &baseline) {
+        if baseline.track_replacements(&mut entries, &prefix_dir) && options.sort {
+            entries.par_sort_by(|a, b| b.modified.cmp(&a.modified));
+        }
+    }
+    if let Some(file) = matches.value_of("merge-mru") {
+        let mru = MruList::load(Path::new(file), &prefix_dir).unwrap_or_else(|e| {
+            eprintln!("Error: {}: {}", file, e);
+            process::exit(1);
+        });
+        mru.merge(&mut entries, &roots, &filters);
+        if options.sort {
+            entries.par_sort_by(|a, b| b.modified.cmp(&a.modified));
+        }
+    }
+    if let Some(recency) = &recency {
+        recency.apply(&mut entries);
+        if options.sort {
+            entries.par_sort_by(|a, b| b.modified.cmp(&a.modified));
+        }
+    }
+    if let (true, Some(baseline)) = (sort_churn, &baseline) {
+        // Stable sort: equally churned entries stay newest first
+        let churn = baseline.churn(&entries, &prefix_dir);
+        let mut scored: Vec<(u64, Entry)> = churn.into_iter().zip(entries).collect();
+        scored.sort_by_key(|(churn, _)| std::cmp::Reverse(*churn));
+        entries = scored.into_iter().map(|(_, entry)| entry).collect();
+    }
+    if sort_size {
+        let sizes = size_totals(&entries, filters.disk_usage, matches.value_of("sort") == Some("du"));
+        let mut scored: Vec<(u64, Entry)> = sizes.into_iter().zip(entries).collect();
+        scored.sort_by_key(|(size, _)| std::cmp::Reverse(*size));
+        entries = scored.into_iter().map(|(_, entry)| entry).collect();
+    }
+    if matches.is_present("with-parents") {
+        entries = with_parents(entries, &roots);
+    }
+    let mut group_headers = Vec::new();
+    if group_by_owner {
+        let (grouped, headers) = group_by_user(entries);
+        entries = grouped;
+        group_headers = headers;
+    }
+    if group_by_mtime {
+        let (grouped, headers) = group_by_age(entries, now);
+        entries = grouped;
+        group_headers = headers;
+    }
+    let mut group_headers = group_headers.into_iter().peekable();
+
+    let annotations = matches.value_of("annotate").map(|file| {
+        Annotations::load(Path::new(file)).unwrap_or_else(|e| {
+            eprintln!("Error: {}: {}", file, e);
+            process::exit(1);
+        })
+    });
+
+    let action = matches.value_of("action").map(|value| {
+        Action::from_string(value).unwrap_or_else(|e| {
+            eprintln!("Error: {}", e);
+            process::exit(1);
+        })
+    });
+    let mut action_paths = Vec::new();
+
+    // Sampling file heads is I/O bound, so it's spread over rayon's pool
+    let detect_text = matches.is_present("detect-text");
+    let content_kinds: Vec<Option<ContentKind>> = if detect_text {
+        entries.par_iter().map(ContentKind::detect).collect()
+    } else {
+        Vec::new()
+    };
+
+    let mut sink = matches.value_of("sink").map(|target| {
+        sink::open(target).unwrap_or_else(|e| {
+            eprintln!("Error: {}: {}", target, e);
+            process::exit(1);
+        })
+    });
+
+    // Case-insensitive filesystems can't hold names differing only by case
+    let dedupe_case = cd_candidates && cfg!(any(windows, target_os = "macos"));
+    let mut seen = HashSet::new();
+
+    let mut output_file = matches.value_of("output-file").map(|file| {
+        let created = fs::File::create(file).unwrap_or_else(|e| {
+            eprintln!("Error: {}: {}", file, e);
+            process::exit(1);
+        });
+        (file, BufWriter::new(created))
+    });
+    let to_file = output_file.is_some();
+    let tee = matches.is_present("tee");
+    // Laid out once every entry is formatted, see --grid
+    let mut grid_cells = matches.is_present("grid").then(Vec::new);
+
+    let mut split = matches.value_of("split-by-age").map(|dir| {
+        AgeSplit::create(Path::new(dir), now).unwrap_or_else(|e| {
+            eprintln!("Error: {}", e);
+            process::exit(1);
+        })
+    });
+
+    // Don't flood an interactive terminal with a huge listing
+    let line_limit = if split.is_none() && sink.is_none() && !to_file && !matches.is_present("no-limit-tty") && io::stdout().is_terminal() {
+        let limit = matches.value_of("tty-limit").unwrap_or(DEFAULT_TTY_LIMIT);
+        match limit.parse::<usize>() {
+            Ok(limit) => Some(limit),
+            Err(_) => {
+                eprintln!("Error: '{}' is not a valid line count", limit);
+                process::exit(1);
+            }
+        }
+    } else {
+        None
+    };
+    let max_results = matches.value_of("max-results").map(|count| {
+        count.parse::<usize>().unwrap_or_else(|_| {
+            eprintln!("Error: '{}' is not a valid result count", count);
+            process::exit(1);
+        })
+    });
+    let per_dir_limit = matches.value_of("per-dir-limit").map(|count| {
+        count.parse::<usize>().unwrap_or_else(|_| {
+            eprintln!("Error: '{}' is not a valid result count", count);
+            process::exit(1);
+        })
+    });
+    let mut per_dir_counts: HashMap<&Path, usize> = HashMap::new();
+    let mut lines_written = 0;
+
+    // Pick the lines to print first; this needs the sequential state (limits, dedupe)
+    let mut listed: Vec<(usize, String)> = Vec::new();
+    let mut not_shown = 0;
+    for (index, e) in entries.iter().enumerate() {
+        if max_results == Some(listed.len()) {
+            break;
+        }
+        if line_limit == Some(listed.len()) {
+            not_shown = entries.len() - index;
+            break;
+        }
+        let path = e.path();
+        let disp = match paths.show(path) {
+            Some(disp) => disp,
+            None => continue,
+        };
+        if dedupe_case && !seen.insert(disp.to_lowercase()) {
+            continue;
+        }
+        if let (Some(limit), Some(parent)) = (per_dir_limit, path.parent()) {
+            let count = per_dir_counts.entry(parent).or_default();
+            if *count == limit {
+                continue;
+            }
+            *count += 1;
+        }
+        listed.push((index, disp));
+    }
+
+    let mut tree_connectors = HashMap::new();
+    if matches.is_present("tree") {
+        let (arranged, connectors) = tree::arrange(listed);
+        listed = arranged;
+        tree_connectors = connectors;
+    }
+
+    let size_column = matches.is_present("size-column");
+    let style = if matches.is_present("age") { Some("age") } else { matches.value_of("time-style") };
+    let mut time_style = style.map(|style| {
+        TimeStyle::parse(style, now).unwrap_or_else(|e| {
+            eprintln!("Error: --time-style: {}", e);
+            process::exit(1);
+        })
+    });
+    let long_columns = matches.is_present("long").then(|| {
+        let style = time_style.take().unwrap_or_else(|| TimeStyle::Strftime("%Y-%m-%d %H:%M".to_string()));
+        LongColumns::new(listed.iter().map(|(index, _)| &entries[*index]), style)
+    });
+
+    let details = |index: usize| {
+        let e = &entries[index];
+        Details {
+            depth: paths.depth(e.path()),
+            content: content_kinds.get(index).copied().flatten(),
+            label: annotations.as_ref().and_then(|a| a.label(e.path(), &prefix_dir)),
+        }
+    };
+
+    let format_line = |index: usize, disp: &str, format: Format, color: bool| -> io::Result<Vec<u8>> {
+        let e = &entries[index];
+        let mut line = Vec::new();
+        if let (Some(template), Format::Plain) = (&template, format) {
+            template.render(&mut line, disp, e)?;
+            output::end_entry(&mut line, print0)?;
+            return Ok(line);
+        }
+        if format == Format::Porcelain {
+            print_porcelain(&mut line, disp, e)?;
+            output::end_entry(&mut line, print0)?;
+            return Ok(line);
+        }
+        if format == Format::Json {
+            // The array opens on the first line and closes on the last
+            write!(line, "{}", if Some(index) == listed.first().map(|(first, _)| *first) { "[" } else { " " })?;
+            print_json(&mut line, disp, e, &details(index))?;
+            writeln!(line, "{}", if Some(index) == listed.last().map(|(last, _)| *last) { "]" } else { "," })?;
+            return Ok(line);
+        }
+        if format == Format::Jsonl {
+            print_json(&mut line, disp, e, &details(index))?;
+            output::end_entry(&mut line, print0)?;
+            return Ok(line);
+        }
+        if matches!(format, Format::Tsv | Format::Csv) {
+            print_delimited(&mut line, format, &columns, disp, e, &details(index))?;
+            output::end_entry(&mut line, print0)?;
+            return Ok(line);
+        }
+        let is_dir = e.is_dir() && trailing_slash;
+        if let Some(long_columns) = &long_columns {
+            long_columns.write(&mut line, e)?;
+        }
+        if let Some(time_style) = &time_style {
+            write!(line, "{:>width$} ", time_style.format(e.modified), width = time_style.width())?;
+        }
+        if size_column {
+            let size = e.measured_size(filters.disk_usage).map_or_else(|| "-".to_string(), output::human_size);
+            write!(line, "{:>5} ", size)?;
+        }
+        if last_run.is_some() {
+            let marker = changes.get(e.path()).map_or(' ', |change| change.marker());
+            write!(line, "{} ", marker)?;
+        }
+        if let Some(connector) = tree_connectors.get(&index) {
+            write!(line, "{}", connector)?;
+        }
+        // Tree labels are relative to the directory above, so only the real path can be styled
+        if color && (color_basename || !tree_connectors.is_empty()) {
+            print_lscolor_basename(&mut line, &ls_colors, disp, e.path(), is_dir)?;
+        } else if color {
+            print_lscolor_path(&mut line, &ls_colors, disp, is_dir)?;
+        } else {
+            print_path(&mut line, disp, is_dir)?;
+        }
+        match (detect_text, content_kinds.get(index).copied().flatten()) {
+            (true, Some(content)) => write!(line, "\t{}", content.name())?,
+            (true, None) => write!(line, "\t-")?,
+            (false, _) => {}
+        }
+        if let Some(label) = annotations.as_ref().and_then(|a| a.label(e.path(), &prefix_dir)) {
+            write!(line, "\t{}", label)?;
+        }
+        output::end_entry(&mut line, print0)?;
+        Ok(line)
+    };
+
+    // Format (and color) chunks of lines in parallel, then write them in order. The first
+    // chunk is a single line so that it shows up without waiting for the rest.
+    let mut start = 0;
+    let mut chunk_len = 1;
+    while start < listed.len() {
+        let chunk = &listed[start..listed.len().min(start + chunk_len)];
+        // Colors are for the terminal; a file only gets them when it's the one being teed
+        let lines: Vec<_> = chunk.par_iter()
+            .map(|(index, disp)| {
+                let line = format_line(*index, disp, output_format, color && !to_file);
+                let tee_line = tee.then(|| format_line(*index, disp, Format::Plain, color));
+                (line, tee_line)
+            })
+            .collect();
+        for ((index, disp), (line, tee_line)) in chunk.iter().zip(lines) {
+            let e = &entries[*index];
+            while let Some((_, header)) = group_headers.next_if(|(start, _)| *start <= *index) {
+                let separator = if lines_written > 0 { "\n" } else { "" };
+                if let Some((file, output)) = output_file.as_mut().filter(|_| output_format == Format::Plain) {
+                    if let Err(e) = writeln!(output, "{}{}:", separator, header) {
+                        eprintln!("Error: {}: {}", file, e);
+                        process::exit(1);
+                    }
+                }
+                if (tee || (!to_file && output_format == Format::Plain)) && writeln!(writer, "{}{}:", separator, header).is_err() {
+                    process::exit(1);
+                }
+            }
+            if let Some(split) = split.as_mut() {
+                if let Err(e) = line.and_then(|line| split.write(e.modified, &line)) {
+                    eprintln!("Error: {}", e);
+                    process::exit(1);
+                }
+            } else if let Some((file, output)) = output_file.as_mut() {
+                if let Err(e) = line.and_then(|line| output.write_all(&line)) {
+                    eprintln!("Error: {}: {}", file, e);
+                    process::exit(1);
+                }
+            } else if let Some(cells) = grid_cells.as_mut() {
+                match line {
+                    Ok(mut line) => {
+                        line.pop();
+                        cells.push(line);
+                    }
+                    Err(_) => process::exit(1),
+                }
+            } else if !stream && line.and_then(|line| writer.write_all(&line)).is_err() {
+                process::exit(1);
+            }
+            if tee_line.is_some_and(|line| line.and_then(|line| writer.write_all(&line)).is_err()) {
+                process::exit(1);
+            }
+            if let Some(sink) = sink.as_mut() {
+                let record = print_json(sink.as_mut(), disp, e, &details(*index)).and_then(|_| writeln!(sink));
+                if let Err(e) = record {
+                    eprintln!("Error: sink: {}", e);
+                    process::exit(1);
+                }
+            }
+            if action.is_some() {
+                action_paths.push(e.path());
+            }
+            lines_written += 1;
+        }
+        if start == 0 {
+            writer.flush().unwrap();
+        }
+        start += chunk.len();
+        chunk_len = OUTPUT_CHUNK_LEN;
+    }
+    if listed.is_empty() && output_format == Format::Json {
+        if let Some((file, output)) = output_file.as_mut() {
+            if let Err(e) = writeln!(output, "[]") {
+                eprintln!("Error: {}: {}", file, e);
+                process::exit(1);
+            }
+        } else if writeln!(writer, "[]").is_err() {
+            process::exit(1);
+        }
+    }
+    if let Some(cells) = grid_cells {
+        if grid::write(&mut writer, &cells, grid::terminal_width()).is_err() {
+            process::exit(1);
+        }
+    }
+    if not_shown > 0 {
+        writer.flush().unwrap();
+        eprintln!("sortfs: {} more entries not shown on the terminal (pipe the output or use --no-limit-tty)",
+                  not_shown);
+    }
+
+    writer.flush().unwrap();
+    if let Some((file, mut output)) = output_file {
+        if let Err(e) = output.flush() {
+            eprintln!("Error: {}: {}", file, e);
+            process::exit(1);
+        }
+    }
+    if let Some(split) = split {
+        if let Err(e) = split.finish() {
+            eprintln!("Error: {}", e);
+            process::exit(1);
+        }
+    }
+    if let Some(mut sink) = sink {
+        if let Err(e) = sink.flush() {
+            eprintln!("Error: sink: {}", e);
+            process::exit(1);
+        }
+    }
+
+    if let Some(profile) = profile {
+        profile.report(&mut io::stderr())?;
+    }
+    if matches.is_present("stats") {
+        ignore_stats::report(&prefix_dir, &options, &mut io::stderr())?;
+    }
+    if matches.is_present("strict") && WALK_PARTIAL.load(Ordering::Relaxed) {
+        process::exit(1);
+    }
+
+    if let Some(action) = action.filter(|_| !action_paths.is_empty()) {
+        let dry_run = matches.is_present("dry-run");
+        if action.is_destructive() && !dry_run && !matches.is_present("force") {
+            eprintln!("Error: --action {} can't be undone; pass --force to confirm", matches.value_of("action").unwrap());
+            process::exit(1);
+        }
+        if !dry_run && !matches.is_present("yes") && !action::confirm(&action.prompt(action_paths.len())) {
+            process::exit(1);
+        }
+        if !action.run(&action_paths, &roots, dry_run) {
+            process::exit(1);
+        }
+    }
     Ok(())
 }
@@ -0,0 +1,82 @@
+//! A reserve of memory handed back the first time an allocation fails, so that a
+//! walk that runs out of memory can still sort and print what it found instead of
+//! aborting with nothing.
+
+use std::alloc::{GlobalAlloc, Layout, System};
+use std::ptr;
+use std::sync::atomic::{AtomicBool, AtomicPtr, AtomicUsize, Ordering};
+
+/// Size of the reserve; halved down to `MIN_RESERVE_SIZE` while that much can't be had.
+const RESERVE_SIZE: usize = 32 << 20;
+const MIN_RESERVE_SIZE: usize = 1 << 20;
+
+static RESERVE: AtomicPtr<u8> = AtomicPtr::new(ptr::null_mut());
+static RESERVE_LEN: AtomicUsize = AtomicUsize::new(0);
+static RESERVE_USED: AtomicBool = AtomicBool::new(false);
+
+/// The system allocator, falling back on the reserve when it fails.
+pub struct ReserveAllocator;
+
+unsafe impl GlobalAlloc for ReserveAllocator {
+    unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+        let allocated = System.alloc(layout);
+        if allocated.is_null() && release() {
+            return System.alloc(layout);
+        }
+        allocated
+    }
+
+    unsafe fn alloc_zeroed(&self, layout: Layout) -> *mut u8 {
+        let allocated = System.alloc_zeroed(layout);
+        if allocated.is_null() && release() {
+            return System.alloc_zeroed(layout);
+        }
+        allocated
+    }
+
+    unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+        System.dealloc(ptr, layout)
+    }
+
+    unsafe fn realloc(&self, ptr: *mut u8, layout: Layout, new_size: usize) -> *mut u8 {
+        let allocated = System.realloc(ptr, layout, new_size);
+        if allocated.is_null() && release() {
+            return System.realloc(ptr, layout, new_size);
+        }
+        allocated
+    }
+}
+
+/// Sets the reserve aside. It's never written to, so it costs address space, not memory.
+pub fn set_aside() {
+    let mut size = RESERVE_SIZE;
+    while size >= MIN_RESERVE_SIZE {
+        let layout = Layout::from_size_align(size, 1).unwrap();
+        // SAFETY: the layout has a non-zero size
+        let reserve = unsafe { System.alloc(layout) };
+        if !reserve.is_null() {
+            RESERVE_LEN.store(size, Ordering::SeqCst);
+            RESERVE.store(reserve, Ordering::SeqCst);
+            return;
+        }
+        size /= 2;
+    }
+}
+
+/// Whether an allocation has failed and the reserve has been handed back.
+pub fn reserve_used() -> bool {
+    RESERVE_USED.load(Ordering::Relaxed)
+}
+
+/// Hands the reserve back to the system, if it's still held.
+fn release() -> bool {
+    let reserve = RESERVE.swap(ptr::null_mut(), Ordering::SeqCst);
+    if reserve.is_null() {
+        return false;
+    }
+    RESERVE_USED.store(true, Ordering::Relaxed);
+    // SAFETY: `reserve` came from System.alloc with this layout, and the swap above
+    // makes this the only place that frees it
+    unsafe { System.dealloc(reserve, Layout::from_size_align_unchecked(RESERVE_LEN.load(Ordering::SeqCst), 1)) };
+    true
+}
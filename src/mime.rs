@@ -0,0 +1,157 @@
+//! Content sniffing: MIME types from magic bytes for `--mime`, and text vs
+//! binary for `--text`/`--binary`.
+
+use std::fs::File;
+use std::io::{self, Read};
+use std::path::Path;
+
+/// Bytes read from the head of a file; enough for the tar header at offset 257.
+const SNIFF_LEN: usize = 512;
+
+/// Bytes checked for NUL when telling text from binary, the same amount git uses.
+const TEXT_SNIFF_LEN: u64 = 8000;
+
+/// Signatures checked in order, as (offset, magic bytes, MIME type).
+const SIGNATURES: &[(usize, &[u8], &str)] = &[
+    (0, b"\x89PNG\r\n\x1a\n", "image/png"),
+    (0, b"\xff\xd8\xff", "image/jpeg"),
+    (0, b"GIF87a", "image/gif"),
+    (0, b"GIF89a", "image/gif"),
+    (0, b"II*\x00", "image/tiff"),
+    (0, b"MM\x00*", "image/tiff"),
+    (0, b"8BPS", "image/vnd.adobe.photoshop"),
+    (0, b"\x00\x00\x01\x00", "image/x-icon"),
+    (0, b"BM", "image/bmp"),
+    (0, b"\x1aE\xdf\xa3", "video/x-matroska"),
+    (0, b"FLV", "video/x-flv"),
+    (0, b"fLaC", "audio/flac"),
+    (0, b"OggS", "audio/ogg"),
+    (0, b"ID3", "audio/mpeg"),
+    (0, b"\xff\xfb", "audio/mpeg"),
+    (0, b"\xff\xf3", "audio/mpeg"),
+    (0, b"\xff\xf2", "audio/mpeg"),
+    (0, b"%PDF-", "application/pdf"),
+    (0, b"PK\x03\x04", "application/zip"),
+    (0, b"\x1f\x8b", "application/gzip"),
+    (0, b"BZh", "application/x-bzip2"),
+    (0, b"\xfd7zXZ\x00", "application/x-xz"),
+    (0, b"7z\xbc\xaf\x27\x1c", "application/x-7z-compressed"),
+    (0, b"Rar!\x1a\x07", "application/vnd.rar"),
+    (0, b"\x28\xb5\x2f\xfd", "application/zstd"),
+    (257, b"ustar", "application/x-tar"),
+    (0, b"\x7fELF", "application/x-executable"),
+    (0, b"\x00asm", "application/wasm"),
+    (0, b"SQLite format 3\x00", "application/vnd.sqlite3"),
+    (0, b"wOFF", "font/woff"),
+    (0, b"wOF2", "font/woff2"),
+    (0, b"OTTO", "font/otf"),
+    (0, b"\x00\x01\x00\x00\x00", "font/ttf"),
+];
+
+/// Guesses the MIME type of a file from its first bytes.
+pub fn sniff(path: &Path) -> io::Result<Option<&'static str>> {
+    let mut head = Vec::with_capacity(SNIFF_LEN);
+    File::open(path)?.take(SNIFF_LEN as u64).read_to_end(&mut head)?;
+    Ok(detect(&head))
+}
+
+/// Whether a file looks binary, i.e. has a NUL byte near its start.
+pub fn looks_binary(path: &Path) -> io::Result<bool> {
+    let mut head = Vec::with_capacity(TEXT_SNIFF_LEN as usize);
+    File::open(path)?.take(TEXT_SNIFF_LEN).read_to_end(&mut head)?;
+    Ok(head.contains(&0))
+}
+
+fn detect(head: &[u8]) -> Option<&'static str> {
+    // Container formats identified by a sub-type tag
+    if head.len() >= 12 && &head[0..4] == b"RIFF" {
+        return match &head[8..12] {
+            b"WEBP" => Some("image/webp"),
+            b"WAVE" => Some("audio/wav"),
+            b"AVI " => Some("video/x-msvideo"),
+            _ => None,
+        };
+    }
+    if head.len() >= 12 && &head[4..8] == b"ftyp" {
+        return match &head[8..12] {
+            b"avif" | b"avis" => Some("image/avif"),
+            b"heic" | b"heix" | b"mif1" | b"msf1" => Some("image/heif"),
+            b"qt  " => Some("video/quicktime"),
+            b"M4A " | b"M4B " | b"M4P " => Some("audio/mp4"),
+            b"3gp4" | b"3gp5" | b"3gp6" | b"3ge6" | b"3gg6" => Some("video/3gpp"),
+            b"3g2a" | b"3g2b" | b"3g2c" => Some("video/3gpp2"),
+            b"crx " => Some("image/x-canon-cr3"),
+            b"isom" | b"iso2" | b"iso4" | b"iso5" | b"iso6" | b"mp41" | b"mp42" | b"mmp4" | b"avc1"
+            | b"dash" | b"M4V " | b"f4v " | b"MSNV" | b"NDAS" => Some("video/mp4"),
+            // Plenty of brands aren't video, so an unknown one is no guess at all
+            _ => None,
+        };
+    }
+    if head.starts_with(b"\x1aE\xdf\xa3") && head.windows(4).any(|w| w == b"webm") {
+        return Some("video/webm");
+    }
+    SIGNATURES.iter()
+        .find(|(offset, magic, _)| head.get(*offset..offset + magic.len()) == Some(*magic))
+        .map(|(_, _, mime)| *mime)
+}
+
+/// Matches a detected type against a pattern such as `image/png`, `image/*` or `*/*`.
+pub fn matches_pattern(mime: &str, pattern: &str) -> bool {
+    match (mime.split_once('/'), pattern.split_once('/')) {
+        (Some((top, sub)), Some((want_top, want_sub))) => {
+            (want_top == "*" || want_top.eq_ignore_ascii_case(top))
+                && (want_sub == "*" || want_sub.eq_ignore_ascii_case(sub))
+        }
+        _ => mime.eq_ignore_ascii_case(pattern),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn ftyp(brand: &[u8]) -> Vec<u8> {
+        let mut head = b"\x00\x00\x00\x18ftyp".to_vec();
+        head.extend_from_slice(brand);
+        head.extend_from_slice(b"\x00\x00\x02\x00");
+        head
+    }
+
+    #[test]
+    fn signatures() {
+        assert_eq!(detect(b"\x89PNG\r\n\x1a\n\x00\x00"), Some("image/png"));
+        assert_eq!(detect(b"%PDF-1.7"), Some("application/pdf"));
+        assert_eq!(detect(b"RIFF\x00\x00\x00\x00WEBPVP8 "), Some("image/webp"));
+        let mut tar = vec![0; 512];
+        tar[257..262].copy_from_slice(b"ustar");
+        assert_eq!(detect(&tar), Some("application/x-tar"));
+    }
+
+    #[test]
+    fn ftyp_brands() {
+        assert_eq!(detect(&ftyp(b"isom")), Some("video/mp4"));
+        assert_eq!(detect(&ftyp(b"heic")), Some("image/heif"));
+        assert_eq!(detect(&ftyp(b"avif")), Some("image/avif"));
+        assert_eq!(detect(&ftyp(b"M4A ")), Some("audio/mp4"));
+        assert_eq!(detect(&ftyp(b"3gp5")), Some("video/3gpp"));
+        assert_eq!(detect(&ftyp(b"crx ")), Some("image/x-canon-cr3"));
+        assert_eq!(detect(&ftyp(b"zzzz")), None);
+    }
+
+    #[test]
+    fn short_or_unknown_heads() {
+        assert_eq!(detect(b""), None);
+        assert_eq!(detect(b"RIFF"), None);
+        assert_eq!(detect(b"\x00\x00\x00\x18ftyp"), None);
+        assert_eq!(detect(b"plain text"), None);
+    }
+
+    #[test]
+    fn patterns() {
+        assert!(matches_pattern("image/png", "image/*"));
+        assert!(matches_pattern("image/png", "*/*"));
+        assert!(matches_pattern("image/png", "IMAGE/PNG"));
+        assert!(!matches_pattern("image/png", "video/*"));
+        assert!(!matches_pattern("image/png", "image"));
+    }
+}
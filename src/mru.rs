@@ -0,0 +1,148 @@
+//! `--merge-mru`: folding an editor's recently-used list into the listing.
+
+use std::collections::HashMap;
+use std::env;
+use std::fs;
+use std::io;
+use std::path::{Component, Path, PathBuf};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use crate::entry::Entry;
+use crate::filter::Filters;
+
+/// Recently used files read from lines of `PATH` or `EPOCH<TAB>PATH`, keyed by absolute path.
+///
+/// `~/` is expanded (as in vim's `:oldfiles`), relative paths are taken relative to the
+/// walk root and `#` starts a comment line. A path without a time only makes sure the
+/// file is listed.
+pub struct MruList {
+    cwd: PathBuf,
+    times: HashMap<PathBuf, Option<SystemTime>>,
+}
+
+impl MruList {
+    pub fn load(file: &Path, root: &Path) -> io::Result<MruList> {
+        let content = fs::read_to_string(file)?;
+        let cwd = env::current_dir()?;
+        let root = lexical_absolute(&cwd, root);
+        let home = env::var_os("HOME").map(PathBuf::from);
+        let mut times = HashMap::new();
+        for (number, line) in content.lines().enumerate() {
+            if line.trim().is_empty() || line.starts_with('#') {
+                continue;
+            }
+            let (time, path) = match line.split_once('\t') {
+                Some((epoch, path)) => match epoch.trim().parse().ok().and_then(epoch_time) {
+                    Some(time) => (Some(time), path),
+                    None => {
+                        return Err(io::Error::new(
+                            io::ErrorKind::InvalidData,
+                            format!("{}:{}: expected PATH or EPOCH<TAB>PATH", file.display(), number + 1),
+                        ));
+                    }
+                },
+                None => (None, line),
+            };
+            let path = match (path.strip_prefix("~/"), &home) {
+                (Some(rest), Some(home)) => home.join(rest),
+                _ => PathBuf::from(path),
+            };
+            let path = lexical_absolute(&root, &path);
+            // The same file may be listed more than once; keep its latest use
+            let latest = times.entry(path).or_insert(time);
+            *latest = (*latest).max(time);
+        }
+        Ok(MruList { cwd, times })
+    }
+
+    /// When `path` was last used, if the list gives a time for it.
+    pub fn last_used(&self, path: &Path) -> Option<SystemTime> {
+        self.times.get(&lexical_absolute(&self.cwd, path)).copied().flatten()
+    }
+
+    /// Raises listed entries to the time they were last used, if that's newer, and adds
+    /// the used files under `roots` that the walk didn't list but that pass `filters`.
+    pub fn merge(&self, entries: &mut Vec<Entry>, roots: &[PathBuf], filters: &Filters) {
+        let mut unlisted: HashMap<&Path, Option<SystemTime>> =
+            self.times.iter().map(|(path, time)| (path.as_path(), *time)).collect();
+        for entry in entries.iter_mut() {
+            let path = lexical_absolute(&self.cwd, entry.path());
+            if let Some(time) = unlisted.remove(path.as_path()) {
+                if let Some(time) = time.filter(|time| *time > entry.modified) {
+                    entry.modified = time;
+                }
+            }
+        }
+
+        for (path, time) in unlisted {
+            let root = roots.iter().find_map(|root| {
+                let relative = path.strip_prefix(lexical_absolute(&self.cwd, root)).ok()?;
+                Some(root.join(relative))
+            });
+            let mut entry = match root.and_then(|path| Entry::from_path(&path)) {
+                Some(entry) => entry,
+                None => continue,
+            };
+            if !filters.matches(&entry) {
+                continue;
+            }
+            if let Some(time) = time.filter(|time| *time > entry.modified) {
+                entry.modified = time;
+            }
+            entries.push(entry);
+        }
+    }
+}
+
+/// `UNIX_EPOCH` plus `epoch` seconds, unless that's negative, not a number or out of range.
+fn epoch_time(epoch: f64) -> Option<SystemTime> {
+    UNIX_EPOCH.checked_add(Duration::try_from_secs_f64(epoch).ok()?)
+}
+
+/// `path` made absolute against `base` with `.` and `..` resolved, without touching the disk.
+fn lexical_absolute(base: &Path, path: &Path) -> PathBuf {
+    let mut absolute = base.to_path_buf();
+    for component in path.components() {
+        match component {
+            Component::CurDir => {}
+            Component::ParentDir => {
+                absolute.pop();
+            }
+            component => absolute.push(component),
+        }
+    }
+    absolute
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn load(content: &str) -> io::Result<MruList> {
+        use std::sync::atomic::{AtomicUsize, Ordering};
+        static LOADED: AtomicUsize = AtomicUsize::new(0);
+        let number = LOADED.fetch_add(1, Ordering::Relaxed);
+        let file = env::temp_dir().join(format!("sortfs-mru-{}-{}", std::process::id(), number));
+        fs::write(&file, content).unwrap();
+        let list = MruList::load(&file, Path::new("/root"));
+        fs::remove_file(&file).unwrap();
+        list
+    }
+
+    #[test]
+    fn reads_times_and_paths() {
+        let list = load("# comment\n\n1706702400\t/a\n1706702400.5\trel/b\n/c\n0\t/a\n").unwrap();
+        assert_eq!(list.last_used(Path::new("/a")), Some(UNIX_EPOCH + Duration::from_secs(1_706_702_400)));
+        assert_eq!(list.last_used(Path::new("/root/rel/b")), Some(UNIX_EPOCH + Duration::from_millis(1_706_702_400_500)));
+        assert_eq!(list.last_used(Path::new("/c")), None);
+        assert!(list.times.contains_key(Path::new("/c")));
+    }
+
+    #[test]
+    fn rejects_bad_times() {
+        for line in ["-1\t/a", "1e300\t/a", "inf\t/a", "NaN\t/a", "yesterday\t/a", "\t/a"] {
+            let error = load(line).err().unwrap_or_else(|| panic!("{}", line));
+            assert_eq!(error.kind(), io::ErrorKind::InvalidData, "{}", line);
+        }
+    }
+}
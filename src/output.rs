@@ -0,0 +1,117 @@
+//! `--output` formats for the listing.
+
+use std::borrow::Cow;
+use std::io::{self, Write};
+
+#[derive(Clone, Copy, PartialEq)]
+pub enum Format {
+    // Paths, optionally colored and followed by the --detect-text/--annotate columns
+    Plain,
+    // See --porcelain
+    Porcelain,
+    // One array of objects with path, mtime, size, type and depth, plus content and
+    // label with --detect-text and --annotate
+    Json,
+    // The same objects, one per line
+    Jsonl,
+    // The --columns of each entry, tab or comma separated
+    Tsv,
+    Csv,
+}
+
+impl Format {
+    pub const NAMES: &'static [&'static str] = &["plain", "porcelain", "json", "jsonl", "tsv", "csv"];
+
+    pub fn from_name(name: &str) -> Option<Format> {
+        match name {
+            "plain" => Some(Format::Plain),
+            "porcelain" => Some(Format::Porcelain),
+            "json" => Some(Format::Json),
+            "jsonl" => Some(Format::Jsonl),
+            "tsv" => Some(Format::Tsv),
+            "csv" => Some(Format::Csv),
+            _ => None,
+        }
+    }
+}
+
+/// Ends an entry's line: with a newline, or with a NUL for `--print0`, so that
+/// names holding newlines survive xargs -0 and fzf --read0.
+pub fn end_entry(handle: &mut dyn Write, print0: bool) -> io::Result<()> {
+    handle.write_all(if print0 { b"\0" } else { b"\n" })
+}
+
+/// `bytes` the way ls -h shows them: `512`, `1.4K`, `23M`, rounded up so a size
+/// is never understated.
+pub fn human_size(bytes: u64) -> String {
+    const UNITS: [&str; 7] = ["", "K", "M", "G", "T", "P", "E"];
+    let mut value = bytes as f64;
+    let mut unit = 0;
+    while value >= 1024.0 && unit + 1 < UNITS.len() {
+        value /= 1024.0;
+        unit += 1;
+    }
+    if unit == 0 {
+        return bytes.to_string();
+    }
+    let tenths = (value * 10.0).ceil() / 10.0;
+    if tenths < 10.0 {
+        return format!("{:.1}{}", tenths, UNITS[unit]);
+    }
+    let whole = value.ceil();
+    if whole >= 1024.0 && unit + 1 < UNITS.len() {
+        return format!("1.0{}", UNITS[unit + 1]);
+    }
+    format!("{}{}", whole, UNITS[unit])
+}
+
+/// A field of `--output tsv` and `--output csv` lines, see --columns.
+#[derive(Clone, Copy, PartialEq)]
+pub enum Column {
+    Path,
+    // Seconds since the epoch, and the same as YYYY-MM-DDTHH:MM:SSZ
+    Mtime,
+    MtimeIso,
+    Size,
+    Type,
+    // Components below the entry's root
+    Depth,
+    // Text or binary, see --detect-text
+    Content,
+    // See --annotate
+    Label,
+}
+
+impl Column {
+    pub const NAMES: &'static [&'static str] = &["path", "mtime", "mtime_iso", "size", "type", "depth", "content", "label"];
+    pub const DEFAULT: &'static str = "path,mtime,size";
+
+    /// Columns from a comma-separated list of their names.
+    pub fn parse_list(list: &str) -> Result<Vec<Column>, String> {
+        list.split(',')
+            .map(|name| match name.trim() {
+                "path" => Ok(Column::Path),
+                "mtime" => Ok(Column::Mtime),
+                "mtime_iso" => Ok(Column::MtimeIso),
+                "size" => Ok(Column::Size),
+                "type" => Ok(Column::Type),
+                "depth" => Ok(Column::Depth),
+                "content" => Ok(Column::Content),
+                "label" => Ok(Column::Label),
+                name => Err(format!("unknown column '{}' (expected one of {})", name, Column::NAMES.join(", "))),
+            })
+            .collect()
+    }
+}
+
+/// `field` escaped for a delimited line: backslash escapes for tabs, newlines and
+/// backslashes in TSV, and RFC 4180 quoting in CSV.
+pub fn escape_field(format: Format, field: &str) -> Cow<'_, str> {
+    match format {
+        Format::Tsv if field.contains(['\\', '\t', '\n', '\r']) => {
+            Cow::Owned(field.replace('\\', "\\\\").replace('\t', "\\t").replace('\n', "\\n").replace('\r', "\\r"))
+        }
+        Format::Csv if field.contains([',', '"', '\n', '\r']) => Cow::Owned(format!("\"{}\"", field.replace('"', "\"\""))),
+        _ => Cow::Borrowed(field),
+    }
+}
@@ -0,0 +1,26 @@
+//! Lowering this process's claim on the disk, for walks that shouldn't compete
+//! with interactive work.
+
+use std::io;
+
+/// Moves the calling thread, and every thread it starts afterwards, to the idle
+/// I/O scheduling class: its requests are only served when the disk is otherwise idle.
+#[cfg(target_os = "linux")]
+pub fn idle_io() -> io::Result<()> {
+    const IOPRIO_WHO_PROCESS: libc::c_int = 1;
+    const IOPRIO_CLASS_IDLE: libc::c_int = 3;
+    const IOPRIO_CLASS_SHIFT: libc::c_int = 13;
+    // SAFETY: ioprio_set only reads its integer arguments; 0 means the calling thread
+    let result = unsafe {
+        libc::syscall(libc::SYS_ioprio_set, IOPRIO_WHO_PROCESS, 0, IOPRIO_CLASS_IDLE << IOPRIO_CLASS_SHIFT)
+    };
+    if result == -1 {
+        return Err(io::Error::last_os_error());
+    }
+    Ok(())
+}
+
+#[cfg(not(target_os = "linux"))]
+pub fn idle_io() -> io::Result<()> {
+    Err(io::Error::new(io::ErrorKind::Unsupported, "I/O priorities aren't available on this platform"))
+}
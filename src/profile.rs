@@ -0,0 +1,117 @@
+use std::collections::HashMap;
+use std::io::{self, Write};
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+/// Number of directories listed in the `--profile-walk` report.
+const REPORT_LIMIT: usize = 20;
+
+/// Time spent on the entries of a single directory.
+#[derive(Default, Clone, Copy)]
+struct DirTimings {
+    enumerate: Duration,
+    stat: Duration,
+    filter: Duration,
+    entries: usize,
+}
+
+impl DirTimings {
+    fn total(&self) -> Duration {
+        self.enumerate + self.stat + self.filter
+    }
+
+    fn add(&mut self, other: &DirTimings) {
+        self.enumerate += other.enumerate;
+        self.stat += other.stat;
+        self.filter += other.filter;
+        self.entries += other.entries;
+    }
+}
+
+/// Per-directory timings collected for `--profile-walk`.
+pub struct WalkProfile {
+    started: Instant,
+    dirs: Mutex<HashMap<PathBuf, DirTimings>>,
+}
+
+impl WalkProfile {
+    pub fn new() -> WalkProfile {
+        WalkProfile {
+            started: Instant::now(),
+            dirs: Mutex::new(HashMap::new()),
+        }
+    }
+
+    /// Creates a recorder for one walker thread; its timings are merged back on drop.
+    pub fn recorder(&self) -> ProfileRecorder<'_> {
+        ProfileRecorder {
+            profile: self,
+            dirs: HashMap::new(),
+            last_visit: Instant::now(),
+            current: DirTimings::default(),
+        }
+    }
+
+    /// Writes the slowest directories, most expensive first.
+    pub fn report(&self, handle: &mut dyn Write) -> io::Result<()> {
+        let dirs = self.dirs.lock().unwrap();
+        let mut sorted: Vec<(&PathBuf, &DirTimings)> = dirs.iter().collect();
+        sorted.sort_by_key(|(_, t)| std::cmp::Reverse(t.total()));
+
+        let entries: usize = dirs.values().map(|t| t.entries).sum();
+        writeln!(handle, "profile: {} entries in {} directories, {:.1?} wall time",
+                 entries, dirs.len(), self.started.elapsed())?;
+        writeln!(handle, "{:>10} {:>10} {:>10} {:>10} {:>8}  directory",
+                 "total", "enumerate", "stat", "filter", "entries")?;
+        for (dir, t) in sorted.iter().take(REPORT_LIMIT) {
+            writeln!(handle, "{:>10.1?} {:>10.1?} {:>10.1?} {:>10.1?} {:>8}  {}",
+                     t.total(), t.enumerate, t.stat, t.filter, t.entries, dir.display())?;
+        }
+        Ok(())
+    }
+}
+
+/// Thread-local side of a [`WalkProfile`].
+///
+/// The walker gives no hook around reading a directory, so the time between two
+/// visits on the same thread is booked as enumeration of the visited entry's parent.
+pub struct ProfileRecorder<'a> {
+    profile: &'a WalkProfile,
+    dirs: HashMap<PathBuf, DirTimings>,
+    last_visit: Instant,
+    current: DirTimings,
+}
+
+impl ProfileRecorder<'_> {
+    pub fn start_entry(&mut self) {
+        self.current = DirTimings {
+            enumerate: self.last_visit.elapsed(),
+            entries: 1,
+            ..DirTimings::default()
+        };
+    }
+
+    pub fn stat_done(&mut self, started: Instant) {
+        self.current.stat += started.elapsed();
+    }
+
+    pub fn filter_done(&mut self, started: Instant) {
+        self.current.filter += started.elapsed();
+    }
+
+    pub fn finish_entry(&mut self, path: &Path) {
+        let dir = path.parent().unwrap_or(path).to_path_buf();
+        self.dirs.entry(dir).or_default().add(&self.current);
+        self.last_visit = Instant::now();
+    }
+}
+
+impl Drop for ProfileRecorder<'_> {
+    fn drop(&mut self) {
+        let mut dirs = self.profile.dirs.lock().unwrap();
+        for (dir, timings) in self.dirs.drain() {
+            dirs.entry(dir).or_default().add(&timings);
+        }
+    }
+}
@@ -0,0 +1,80 @@
+//! `--recency`: where the time that entries are ranked by comes from.
+
+use std::path::Path;
+use std::time::SystemTime;
+
+use crate::entry::Entry;
+use crate::git::CommitTimes;
+use crate::mru::MruList;
+
+/// Something that can tell when an entry was last changed or used.
+pub trait RecencySource: Sync {
+    /// The entry's time according to this source, or `None` if it doesn't know the entry.
+    fn time(&self, entry: &Entry) -> Option<SystemTime>;
+}
+
+/// The modification time found by the walk.
+struct Mtime;
+
+impl RecencySource for Mtime {
+    fn time(&self, entry: &Entry) -> Option<SystemTime> {
+        Some(entry.modified)
+    }
+}
+
+/// The inode change time, which moves on renames and permission changes too.
+struct Ctime;
+
+impl RecencySource for Ctime {
+    fn time(&self, entry: &Entry) -> Option<SystemTime> {
+        entry.changed()
+    }
+}
+
+impl RecencySource for CommitTimes {
+    fn time(&self, entry: &Entry) -> Option<SystemTime> {
+        self.get(entry.path())
+    }
+}
+
+impl RecencySource for MruList {
+    fn time(&self, entry: &Entry) -> Option<SystemTime> {
+        self.last_used(entry.path())
+    }
+}
+
+/// Sources in order of precedence: an entry gets its time from the first one that knows it.
+pub struct Recency {
+    sources: Vec<Box<dyn RecencySource>>,
+}
+
+impl Recency {
+    /// Parses `SOURCE[,SOURCE]`, where a source is `mtime`, `ctime`, `git` (the last
+    /// commit touching the path) or `mru:FILE` (a list as read by `--merge-mru`).
+    pub fn from_string(s: &str, root: &Path) -> Result<Recency, String> {
+        let mut sources: Vec<Box<dyn RecencySource>> = Vec::new();
+        for name in s.split(',') {
+            let source: Box<dyn RecencySource> = match name {
+                "mtime" => Box::new(Mtime),
+                "ctime" => Box::new(Ctime),
+                "git" => Box::new(CommitTimes::load(root)?),
+                _ => match name.strip_prefix("mru:") {
+                    Some(file) => Box::new(MruList::load(Path::new(file), root).map_err(|e| format!("{}: {}", file, e))?),
+                    None => return Err(format!("unknown recency source '{}' (expected mtime, ctime, git or mru:FILE)", name)),
+                },
+            };
+            sources.push(source);
+        }
+        Ok(Recency { sources })
+    }
+
+    /// Replaces each entry's time with the one from the first source that knows it.
+    /// Entries no source knows keep the time the walk found.
+    pub fn apply(&self, entries: &mut [Entry]) {
+        for entry in entries.iter_mut() {
+            if let Some(time) = self.sources.iter().find_map(|source| source.time(entry)) {
+                entry.modified = time;
+            }
+        }
+    }
+}
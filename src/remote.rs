@@ -0,0 +1,166 @@
+//! Network filesystem detection, so stat() calls there can be bounded by `--stat-timeout`.
+
+use std::env;
+use std::fs::{self, Metadata};
+use std::path::{Component, Path, PathBuf};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::mpsc;
+use std::thread;
+use std::time::Duration;
+
+/// Filesystem types whose stat() may block on a server.
+const REMOTE_TYPES: &[&str] = &[
+    "nfs", "nfs4", "cifs", "smb3", "smbfs", "afs", "ceph", "9p", "glusterfs", "lustre",
+    "davfs", "fuse.sshfs", "fuse.rclone", "fuse.s3fs", "fuse.gcsfuse",
+];
+
+/// Mount points of remote filesystems.
+pub struct RemoteMounts {
+    cwd: PathBuf,
+    mount_points: Vec<PathBuf>,
+    // Per mount point: whether a stat() there has timed out
+    unresponsive: Vec<AtomicBool>,
+}
+
+impl RemoteMounts {
+    #[cfg(target_os = "linux")]
+    pub fn detect() -> RemoteMounts {
+        // Fields: device, mount point (with octal escapes), type, options, ...
+        let mounts = fs::read_to_string("/proc/self/mounts").unwrap_or_default();
+        let mount_points = mounts.lines()
+            .filter_map(|line| {
+                let mut fields = line.split(' ');
+                let mount_point = fields.nth(1)?;
+                let fs_type = fields.next()?;
+                REMOTE_TYPES.contains(&fs_type).then(|| PathBuf::from(unescape(mount_point)))
+            })
+            .collect();
+        RemoteMounts::new(mount_points)
+    }
+
+    #[cfg(not(target_os = "linux"))]
+    pub fn detect() -> RemoteMounts {
+        RemoteMounts::new(Vec::new())
+    }
+
+    fn new(mount_points: Vec<PathBuf>) -> RemoteMounts {
+        RemoteMounts {
+            cwd: env::current_dir().unwrap_or_default(),
+            unresponsive: mount_points.iter().map(|_| AtomicBool::new(false)).collect(),
+            mount_points,
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.mount_points.is_empty()
+    }
+
+    /// The innermost remote mount holding `path`, as an index into `mount_points`.
+    fn mount_of(&self, path: &Path) -> Option<usize> {
+        if self.mount_points.is_empty() {
+            return None;
+        }
+        let mut absolute = self.cwd.clone();
+        for component in path.components() {
+            match component {
+                Component::CurDir => {}
+                Component::ParentDir => {
+                    absolute.pop();
+                }
+                component => absolute.push(component),
+            }
+        }
+        (0..self.mount_points.len())
+            .filter(|&mount| absolute.starts_with(&self.mount_points[mount]))
+            .max_by_key(|&mount| self.mount_points[mount].components().count())
+    }
+
+    /// stat()s `path` if it's on a remote mount, on a helper thread that is waited
+    /// for up to `timeout`; `None` for a local path. A stat() that hangs keeps its
+    /// thread until the server answers, so after the first timeout on a mount the
+    /// rest of it is skipped rather than leaking a thread per entry.
+    pub fn metadata(&self, path: &Path, timeout: Duration) -> Option<Stat<'_>> {
+        let mount = self.mount_of(path)?;
+        if self.unresponsive[mount].load(Ordering::Relaxed) {
+            return Some(Stat::Skipped);
+        }
+        let (sender, receiver) = mpsc::channel();
+        let owned = path.to_path_buf();
+        let spawned = thread::Builder::new().spawn(move || {
+            let _ = sender.send(fs::metadata(owned).ok());
+        });
+        let meta = spawned.ok().and_then(|_| receiver.recv_timeout(timeout).ok());
+        Some(match meta {
+            Some(meta) => Stat::Done(meta),
+            // Only the first to notice reports it
+            None if self.unresponsive[mount].swap(true, Ordering::Relaxed) => Stat::Skipped,
+            None => Stat::TimedOut(&self.mount_points[mount]),
+        })
+    }
+}
+
+/// Decodes the `\040`-style escapes the kernel uses for spaces and the like.
+#[cfg(target_os = "linux")]
+pub fn unescape(field: &str) -> String {
+    let bytes = field.as_bytes();
+    let mut decoded = Vec::with_capacity(bytes.len());
+    let mut i = 0;
+    while i < bytes.len() {
+        let octal = bytes.get(i + 1..i + 4).and_then(|digits| {
+            u8::from_str_radix(std::str::from_utf8(digits).ok()?, 8).ok()
+        });
+        match (bytes[i], octal) {
+            (b'\\', Some(byte)) => {
+                decoded.push(byte);
+                i += 4;
+            }
+            (byte, _) => {
+                decoded.push(byte);
+                i += 1;
+            }
+        }
+    }
+    String::from_utf8_lossy(&decoded).into_owned()
+}
+
+/// Outcome of a stat() bounded by a timeout.
+pub enum Stat<'a> {
+    Done(Option<Metadata>),
+    // The first stat() to time out on the mount point
+    TimedOut(&'a Path),
+    // On a mount point that has stopped answering
+    Skipped,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn innermost_mount_holds_a_path() {
+        let mounts = RemoteMounts::new(vec![PathBuf::from("/net"), PathBuf::from("/net/home")]);
+        assert_eq!(mounts.mount_of(Path::new("/net/home/a")), Some(1));
+        assert_eq!(mounts.mount_of(Path::new("/net/other")), Some(0));
+        assert_eq!(mounts.mount_of(Path::new("/network")), None);
+        assert_eq!(mounts.mount_of(Path::new("/net/home/../x")), Some(0));
+    }
+
+    #[test]
+    fn unresponsive_mounts_are_skipped() {
+        let dir = env::temp_dir();
+        let mounts = RemoteMounts::new(vec![dir.clone()]);
+        assert!(mounts.metadata(Path::new("/"), Duration::from_secs(5)).is_none());
+        assert!(matches!(mounts.metadata(&dir, Duration::from_secs(5)), Some(Stat::Done(Some(_)))));
+        mounts.unresponsive[0].store(true, Ordering::Relaxed);
+        assert!(matches!(mounts.metadata(&dir, Duration::from_secs(5)), Some(Stat::Skipped)));
+    }
+
+    #[cfg(target_os = "linux")]
+    #[test]
+    fn unescapes_mount_points() {
+        assert_eq!(unescape(r"/mnt/my\040share"), "/mnt/my share");
+        assert_eq!(unescape(r"/mnt/tab\011x\134y"), "/mnt/tab\tx\\y");
+        assert_eq!(unescape(r"/mnt/odd\04"), r"/mnt/odd\04");
+        assert_eq!(unescape(r"/mnt/not\999"), r"/mnt/not\999");
+    }
+}
@@ -0,0 +1,302 @@
+//! `.sortfsignore` files: gitignore-style globs that may carry time/size conditions.
+//!
+//! They're read here rather than by the walker, which would take `*.log older-than 30d`
+//! for a glob with spaces in it. Plain lines are applied as gitignore rules; lines
+//! such as `*.log older-than 30d` or `build/ larger-than 1G` only ignore entries whose
+//! metadata meets every condition. Ignored directories are pruned either way.
+
+use std::collections::HashMap;
+use std::env;
+use std::fs::{self, Metadata};
+use std::path::{Component, Path, PathBuf};
+use std::sync::{Arc, RwLock};
+use std::time::SystemTime;
+
+use globset::{GlobBuilder, GlobMatcher};
+use ignore::gitignore::{Gitignore, GitignoreBuilder};
+
+use crate::filter::SizeFilter;
+use crate::time;
+
+pub const IGNORE_FILENAME: &str = ".sortfsignore";
+
+const CONDITION_KEYWORDS: [&str; 4] = ["older-than", "newer-than", "larger-than", "smaller-than"];
+
+enum Condition {
+    ModifiedBefore(SystemTime),
+    ModifiedAfter(SystemTime),
+    SizeAbove(u64),
+    SizeBelow(u64),
+}
+
+impl Condition {
+    fn holds(&self, meta: &Metadata) -> bool {
+        let modified = meta.modified().unwrap_or(SystemTime::UNIX_EPOCH);
+        match *self {
+            Condition::ModifiedBefore(limit) => modified < limit,
+            Condition::ModifiedAfter(limit) => modified > limit,
+            Condition::SizeAbove(size) => meta.is_file() && meta.len() > size,
+            Condition::SizeBelow(size) => meta.is_file() && meta.len() < size,
+        }
+    }
+}
+
+struct Rule {
+    glob: GlobMatcher,
+    // Patterns without a slash match the basename at any depth, like gitignore
+    basename_only: bool,
+    dirs_only: bool,
+    conditions: Vec<Condition>,
+}
+
+impl Rule {
+    /// Parses a line whose pattern is followed by conditions; `None` for any other
+    /// line, which is plain gitignore syntax.
+    fn parse(line: &str, now: SystemTime) -> Result<Option<Rule>, String> {
+        let mut tokens = line.split_whitespace().peekable();
+        let pattern = match tokens.next() {
+            Some(pattern) if !pattern.starts_with('#') => pattern,
+            _ => return Ok(None),
+        };
+        if !tokens.peek().is_some_and(|keyword| CONDITION_KEYWORDS.contains(keyword)) {
+            return Ok(None);
+        }
+        if pattern.starts_with('!') {
+            return Err("a pattern with conditions can't be negated".to_string());
+        }
+        let mut conditions = Vec::new();
+        while let Some(keyword) = tokens.next() {
+            let value = tokens.next().ok_or_else(|| format!("'{}' needs a value", keyword))?;
+            let condition = match keyword {
+                "older-than" => Condition::ModifiedBefore(duration_bound(value, now)?),
+                "newer-than" => Condition::ModifiedAfter(duration_bound(value, now)?),
+                "larger-than" => Condition::SizeAbove(size_bound(value)?),
+                "smaller-than" => Condition::SizeBelow(size_bound(value)?),
+                _ => return Err(format!("unknown condition '{}'", keyword)),
+            };
+            conditions.push(condition);
+        }
+
+        let dirs_only = pattern.ends_with('/');
+        let pattern = pattern.trim_end_matches('/');
+        let basename_only = !pattern.contains('/');
+        let glob = GlobBuilder::new(pattern.trim_start_matches('/'))
+            .literal_separator(true)
+            .build()
+            .map_err(|e| e.to_string())?
+            .compile_matcher();
+        Ok(Some(Rule { glob, basename_only, dirs_only, conditions }))
+    }
+
+    fn applies_to(&self, relative: &Path, is_dir: bool) -> bool {
+        if self.dirs_only && !is_dir {
+            return false;
+        }
+        if self.basename_only {
+            relative.file_name().is_some_and(|name| self.glob.is_match(name))
+        } else {
+            self.glob.is_match(relative)
+        }
+    }
+}
+
+fn duration_bound(value: &str, now: SystemTime) -> Result<SystemTime, String> {
+    time::parse_duration(value)
+        .and_then(|duration| now.checked_sub(duration))
+        .filter(|time| *time >= SystemTime::UNIX_EPOCH)
+        .ok_or_else(|| format!("'{}' is not a duration", value))
+}
+
+fn size_bound(value: &str) -> Result<u64, String> {
+    match SizeFilter::from_string(value)? {
+        SizeFilter::Equals(size) => Ok(size),
+        _ => Err(format!("'{}' must be a plain size", value)),
+    }
+}
+
+/// One `.sortfsignore`, its plain lines compiled as a gitignore file.
+struct IgnoreFile {
+    // Absolute, as the paths matched against it
+    dir: PathBuf,
+    plain: Gitignore,
+    rules: Vec<Rule>,
+}
+
+impl IgnoreFile {
+    /// Reads the `.sortfsignore` in `dir`, if any. Invalid lines are reported and skipped.
+    fn load(dir: &Path, now: SystemTime) -> Option<Arc<IgnoreFile>> {
+        let file = dir.join(IGNORE_FILENAME);
+        let content = fs::read_to_string(&file).ok()?;
+        let mut plain = GitignoreBuilder::new(dir);
+        let mut rules = Vec::new();
+        for (number, line) in content.lines().enumerate() {
+            let result = match Rule::parse(line, now) {
+                Ok(Some(rule)) => {
+                    rules.push(rule);
+                    Ok(())
+                }
+                Ok(None) => plain.add_line(Some(file.clone()), line).map(|_| ()).map_err(|e| e.to_string()),
+                Err(e) => Err(e),
+            };
+            if let Err(e) = result {
+                eprintln!("sortfs: {}:{}: {}", file.display(), number + 1, e);
+            }
+        }
+        let plain = plain.build().unwrap_or_else(|_| Gitignore::empty());
+        Some(Arc::new(IgnoreFile { dir: dir.to_path_buf(), plain, rules }))
+    }
+}
+
+/// The `.sortfsignore` files that apply below the walk roots, resolved once per
+/// walked directory and checked from the walker's filter.
+pub struct SortfsIgnore {
+    roots: Vec<PathBuf>,
+    cwd: PathBuf,
+    now: SystemTime,
+    // Whether the files above a walk root apply too
+    parents: bool,
+    // Per directory as walked: the files that apply in it, outermost first
+    walked: RwLock<HashMap<PathBuf, Arc<Vec<Arc<IgnoreFile>>>>>,
+}
+
+impl SortfsIgnore {
+    pub fn new(roots: &[PathBuf], now: SystemTime, parents: bool) -> SortfsIgnore {
+        SortfsIgnore {
+            roots: roots.to_vec(),
+            cwd: env::current_dir().unwrap_or_default(),
+            now,
+            parents,
+            walked: RwLock::new(HashMap::new()),
+        }
+    }
+
+    /// Whether a walked path is ignored. `meta` is only called when a conditional
+    /// rule applies to the path, and at most once.
+    pub fn is_ignored(&self, path: &Path, is_dir: bool, meta: impl Fn() -> Option<Metadata>) -> bool {
+        let dir = match path.parent() {
+            Some(dir) if !self.roots.iter().any(|root| root == path) => dir,
+            _ => return false,
+        };
+        let files = self.files_in(dir);
+        if files.is_empty() {
+            return false;
+        }
+        let path = self.absolute(path);
+        // As with gitignore, the deepest file with a matching pattern decides
+        let plain = files.iter().rev().map(|file| file.plain.matched(&path, is_dir)).find(|matched| !matched.is_none());
+        if plain.is_some_and(|matched| matched.is_ignore()) {
+            return true;
+        }
+        let mut fetched = None;
+        for file in files.iter() {
+            let relative = path.strip_prefix(&file.dir).unwrap_or(&path);
+            for rule in file.rules.iter().filter(|rule| rule.applies_to(relative, is_dir)) {
+                let meta = fetched.get_or_insert_with(&meta);
+                if meta.as_ref().is_some_and(|meta| rule.conditions.iter().all(|condition| condition.holds(meta))) {
+                    return true;
+                }
+            }
+        }
+        false
+    }
+
+    fn files_in(&self, dir: &Path) -> Arc<Vec<Arc<IgnoreFile>>> {
+        if let Some(files) = self.walked.read().unwrap().get(dir) {
+            return Arc::clone(files);
+        }
+        let absolute = self.absolute(dir);
+        let inherited = if self.roots.iter().any(|root| root == dir) {
+            let mut above: Vec<&Path> = absolute.ancestors().skip(1).filter(|_| self.parents).collect();
+            above.reverse();
+            Arc::new(above.into_iter().filter_map(|dir| IgnoreFile::load(dir, self.now)).collect())
+        } else {
+            match dir.parent() {
+                Some(parent) if self.roots.iter().any(|root| dir.starts_with(root)) => self.files_in(parent),
+                _ => return Arc::new(Vec::new()),
+            }
+        };
+        let files = match IgnoreFile::load(&absolute, self.now) {
+            Some(file) => Arc::new(inherited.iter().cloned().chain(Some(file)).collect()),
+            None => inherited,
+        };
+        self.walked.write().unwrap().insert(dir.to_path_buf(), Arc::clone(&files));
+        files
+    }
+
+    /// Resolves `.` and `..` without following symlinks, as the walker's paths do.
+    fn absolute(&self, path: &Path) -> PathBuf {
+        let mut absolute = self.cwd.clone();
+        for component in path.components() {
+            match component {
+                Component::CurDir => {}
+                Component::ParentDir => {
+                    absolute.pop();
+                }
+                component => absolute.push(component),
+            }
+        }
+        absolute
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::{Duration, UNIX_EPOCH};
+
+    const NOW: u64 = 1_706_702_400;
+
+    fn parse(line: &str) -> Result<Option<Rule>, String> {
+        Rule::parse(line, UNIX_EPOCH + Duration::from_secs(NOW))
+    }
+
+    #[test]
+    fn conditional_lines() {
+        let rule = parse("*.log older-than 30d").unwrap().unwrap();
+        assert!(matches!(rule.conditions[..], [Condition::ModifiedBefore(limit)]
+            if limit == UNIX_EPOCH + Duration::from_secs(NOW - 30 * 24 * 60 * 60)));
+        assert!(rule.basename_only && !rule.dirs_only);
+
+        let rule = parse("/build/ larger-than 1G smaller-than 2G").unwrap().unwrap();
+        assert!(matches!(rule.conditions[..], [Condition::SizeAbove(1_000_000_000), Condition::SizeBelow(2_000_000_000)]));
+        assert!(rule.dirs_only);
+        assert!(rule.applies_to(Path::new("build"), true));
+        assert!(!rule.applies_to(Path::new("build"), false));
+        assert!(!rule.applies_to(Path::new("src/build"), true));
+    }
+
+    #[test]
+    fn plain_lines_are_left_to_gitignore() {
+        for line in ["", "   ", "# older-than 1d", "*.log", "!keep.log", "odd older", "name with spaces"] {
+            assert!(parse(line).unwrap().is_none(), "{}", line);
+        }
+    }
+
+    #[test]
+    fn malformed_conditions() {
+        for line in ["*.log older-than", "*.log older-than soon", "*.log larger-than +1G",
+                     "*.log older-than 1d bigger 1G", "!*.log older-than 1d", "*.log newer-than 1000000y"] {
+            assert!(parse(line).is_err(), "{}", line);
+        }
+    }
+
+    #[test]
+    fn plain_and_conditional_rules_apply_below_their_directory() {
+        let root = env::temp_dir().join(format!("sortfs-rules-{}", std::process::id()));
+        fs::create_dir_all(root.join("sub")).unwrap();
+        fs::write(root.join(IGNORE_FILENAME), "*.tmp\n!keep.tmp\n*.log larger-than 1\n").unwrap();
+        fs::write(root.join("sub").join(IGNORE_FILENAME), "*.txt\n").unwrap();
+        let ignore = SortfsIgnore::new(std::slice::from_ref(&root), SystemTime::now(), false);
+
+        assert!(ignore.is_ignored(&root.join("a.tmp"), false, || None));
+        assert!(!ignore.is_ignored(&root.join("keep.tmp"), false, || None));
+        assert!(ignore.is_ignored(&root.join("sub/b.tmp"), false, || None));
+        assert!(ignore.is_ignored(&root.join("sub/b.txt"), false, || None));
+        assert!(!ignore.is_ignored(&root.join("b.txt"), false, || None));
+        assert!(ignore.is_ignored(&root.join("big.log"), false, || fs::metadata(root.join(IGNORE_FILENAME)).ok()));
+        // Conditions can't hold without metadata
+        assert!(!ignore.is_ignored(&root.join("gone.log"), false, || None));
+        assert!(!ignore.is_ignored(&root, true, || None));
+        fs::remove_dir_all(&root).unwrap();
+    }
+}
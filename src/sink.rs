@@ -0,0 +1,27 @@
+//! `--sink`: a copy of the results streamed to another process, as the same NDJSON
+//! records `--output jsonl` writes.
+
+use std::fs::OpenOptions;
+use std::io::{self, BufWriter, Write};
+
+/// Opens `unix:PATH` (a listening Unix socket) or `fifo:PATH`/`PATH` (a named pipe).
+pub fn open(target: &str) -> io::Result<Box<dyn Write>> {
+    if let Some(path) = target.strip_prefix("unix:") {
+        return connect_unix(path);
+    }
+    let path = target.strip_prefix("fifo:").unwrap_or(target);
+    let file = OpenOptions::new().write(true).open(path)?;
+    Ok(Box::new(BufWriter::new(file)))
+}
+
+#[cfg(unix)]
+fn connect_unix(path: &str) -> io::Result<Box<dyn Write>> {
+    let stream = std::os::unix::net::UnixStream::connect(path)?;
+    Ok(Box::new(BufWriter::new(stream)))
+}
+
+#[cfg(not(unix))]
+fn connect_unix(_path: &str) -> io::Result<Box<dyn Write>> {
+    Err(io::Error::new(io::ErrorKind::Unsupported, "unix sockets aren't available on this platform"))
+}
+
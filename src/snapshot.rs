@@ -0,0 +1,370 @@
+//! Saved listings (`--save-snapshot`) and comparisons against them (`--sort churn`).
+//!
+//! # Format
+//!
+//! Snapshots are UTF-8 NDJSON, independent of locale and platform. The first line
+//! describes the snapshot:
+//!
+//! ```text
+//! {"format":"sortfs-snapshot","version":1,"hostname":"box","root":{"device":64769,"inode":2},"options":"..."}
+//! ```
+//!
+//! and every further line one entry, its path relative to the walk root:
+//!
+//! ```text
+//! {"path":"src/main.rs","mtime":1706702400,"size":5120,"inode":393718}
+//! ```
+//!
+//! `mtime` is in seconds since the Unix epoch and `size` is 0 for anything but regular
+//! files. Paths that aren't valid UTF-8 are written as `"path_bytes":[...]` instead.
+//! Any key other than `format`, `version` and an entry's path and `mtime` may be missing.
+//!
+//! Compatibility: readers ignore keys they don't know, so new information is added
+//! without a version change. `version` only goes up when the meaning of an existing
+//! key changes; snapshots of a version newer than sortfs knows are refused rather than
+//! misread.
+
+use std::collections::HashMap;
+use std::convert::TryFrom;
+use std::env;
+use std::fs::{self, File};
+use std::io::{self, BufWriter, Write};
+use std::path::{Path, PathBuf};
+use std::time::{Duration, UNIX_EPOCH};
+
+use crate::entry::Entry;
+use crate::json::{self, Value};
+use crate::time;
+
+const FORMAT: &str = "sortfs-snapshot";
+const VERSION: u64 = 1;
+const HEADER_KEYS: [&str; 5] = ["format", "version", "hostname", "root", "options"];
+
+/// State of one path when the snapshot was taken.
+pub struct SnapshotEntry {
+    pub modified: u64,
+    pub size: u64,
+    pub inode: Option<u64>,
+}
+
+/// How an entry differs from the snapshot, as marked by `--since-last`.
+#[derive(Clone, Copy)]
+pub enum Change {
+    New,
+    Modified,
+}
+
+impl Change {
+    pub fn marker(self) -> char {
+        match self {
+            Change::New => '+',
+            Change::Modified => '~',
+        }
+    }
+}
+
+/// The snapshot `--since-last` keeps of the previous listing of `root`:
+/// `$XDG_STATE_HOME/sortfs/` (or `~/.local/state/sortfs/`) with the root's
+/// absolute path as file name, its separators replaced by `%`.
+pub fn state_file(root: &Path) -> Option<PathBuf> {
+    let state_home = env::var_os("XDG_STATE_HOME")
+        .map(PathBuf::from)
+        .filter(|dir| dir.is_absolute())
+        .or_else(|| env::var_os("HOME").map(|home| Path::new(&home).join(".local/state")))?;
+    let root = fs::canonicalize(root).ok()?;
+    let name = root.to_string_lossy().replace(std::path::MAIN_SEPARATOR, "%");
+    Some(state_home.join("sortfs").join(name))
+}
+
+/// Where and how a snapshot was taken, to catch comparisons that would mislead.
+pub struct Environment {
+    pub hostname: Option<String>,
+    // Device and inode of the walk root
+    pub root: Option<(u64, u64)>,
+    // Walk options that change which entries are listed
+    pub options: Option<String>,
+}
+
+impl Environment {
+    pub fn current(root: &Path, options: String) -> Environment {
+        Environment { hostname: hostname(), root: root_identity(root), options: Some(options) }
+    }
+
+    /// Problems comparing a walk in `current` against a snapshot taken in `self`:
+    /// fatal ones first, then ones only worth a warning.
+    pub fn incompatibilities(&self, current: &Environment) -> (Vec<String>, Vec<String>) {
+        let mut fatal = Vec::new();
+        let mut warnings = Vec::new();
+        if let (Some(saved), Some(now)) = (self.root, current.root) {
+            if saved != now {
+                fatal.push("it was taken of a different root directory".to_string());
+            }
+        }
+        if let (Some(saved), Some(now)) = (&self.hostname, &current.hostname) {
+            if saved != now {
+                warnings.push(format!("it was taken on host {}", saved));
+            }
+        }
+        if let (Some(saved), Some(now)) = (&self.options, &current.options) {
+            if saved != now {
+                warnings.push(format!("it was taken with different walk options ({})", saved));
+            }
+        }
+        (fatal, warnings)
+    }
+}
+
+#[cfg(unix)]
+fn hostname() -> Option<String> {
+    let mut buffer = [0u8; 256];
+    // SAFETY: the buffer outlives the call and its length is passed along
+    if unsafe { libc::gethostname(buffer.as_mut_ptr().cast(), buffer.len()) } != 0 {
+        return None;
+    }
+    let len = buffer.iter().position(|&byte| byte == 0).unwrap_or(buffer.len());
+    Some(String::from_utf8_lossy(&buffer[..len]).into_owned())
+}
+
+#[cfg(not(unix))]
+fn hostname() -> Option<String> {
+    std::env::var("COMPUTERNAME").ok()
+}
+
+#[cfg(unix)]
+fn root_identity(root: &Path) -> Option<(u64, u64)> {
+    use std::os::unix::fs::MetadataExt;
+    fs::metadata(root).ok().map(|meta| (meta.dev(), meta.ino()))
+}
+
+#[cfg(not(unix))]
+fn root_identity(_root: &Path) -> Option<(u64, u64)> {
+    None
+}
+
+/// Entries of a previous walk, keyed by path relative to the walk root.
+pub struct Snapshot {
+    pub version: u64,
+    pub entries: HashMap<PathBuf, SnapshotEntry>,
+    pub environment: Environment,
+    // Header keys written by a later sortfs, as JSON text
+    pub unknown: Vec<(String, String)>,
+}
+
+fn epoch_seconds(entry: &Entry) -> u64 {
+    entry.modified.duration_since(UNIX_EPOCH).map_or(0, |d| d.as_secs())
+}
+
+fn size(entry: &Entry) -> u64 {
+    match &entry.metadata {
+        Some(meta) if meta.is_file() => meta.len(),
+        _ => 0,
+    }
+}
+
+fn relative<'a>(path: &'a Path, root: &Path) -> &'a Path {
+    path.strip_prefix(root).unwrap_or(path)
+}
+
+pub fn write_path(writer: &mut impl Write, path: &Path) -> io::Result<()> {
+    match path.to_str() {
+        Some(path) => write!(writer, "\"path\":{}", json::string(path)),
+        None => write!(writer, "\"path_bytes\":{:?}", path_bytes(path)),
+    }
+}
+
+pub fn read_path(record: &Value) -> Option<PathBuf> {
+    if let Some(path) = record.get("path") {
+        return path.as_str().map(PathBuf::from);
+    }
+    let bytes = record.get("path_bytes")?.as_array()?.iter()
+        .map(|byte| byte.as_u64().and_then(|byte| u8::try_from(byte).ok()))
+        .collect::<Option<Vec<u8>>>()?;
+    path_from_bytes(bytes)
+}
+
+#[cfg(unix)]
+fn path_bytes(path: &Path) -> Vec<u8> {
+    use std::os::unix::ffi::OsStrExt;
+    path.as_os_str().as_bytes().to_vec()
+}
+
+#[cfg(not(unix))]
+fn path_bytes(path: &Path) -> Vec<u8> {
+    path.to_string_lossy().into_owned().into_bytes()
+}
+
+#[cfg(unix)]
+fn path_from_bytes(bytes: Vec<u8>) -> Option<PathBuf> {
+    use std::os::unix::ffi::OsStringExt;
+    Some(PathBuf::from(std::ffi::OsString::from_vec(bytes)))
+}
+
+#[cfg(not(unix))]
+fn path_from_bytes(bytes: Vec<u8>) -> Option<PathBuf> {
+    String::from_utf8(bytes).ok().map(PathBuf::from)
+}
+
+impl Snapshot {
+    pub fn save(file: &Path, entries: &[Entry], root: &Path, environment: &Environment) -> io::Result<()> {
+        let mut writer = BufWriter::new(File::create(file)?);
+        write!(writer, "{{\"format\":\"{}\",\"version\":{}", FORMAT, VERSION)?;
+        if let Some(hostname) = &environment.hostname {
+            write!(writer, ",\"hostname\":{}", json::string(hostname))?;
+        }
+        if let Some((device, inode)) = environment.root {
+            write!(writer, ",\"root\":{{\"device\":{},\"inode\":{}}}", device, inode)?;
+        }
+        if let Some(options) = &environment.options {
+            write!(writer, ",\"options\":{}", json::string(options))?;
+        }
+        writeln!(writer, "}}")?;
+        for entry in entries {
+            let path = relative(entry.path(), root);
+            if path.as_os_str().is_empty() {
+                continue;
+            }
+            write!(writer, "{{")?;
+            write_path(&mut writer, path)?;
+            write!(writer, ",\"mtime\":{},\"size\":{}", epoch_seconds(entry), size(entry))?;
+            if let Some(inode) = entry.inode() {
+                write!(writer, ",\"inode\":{}", inode)?;
+            }
+            writeln!(writer, "}}")?;
+        }
+        writer.flush()
+    }
+
+    pub fn load(file: &Path) -> io::Result<Snapshot> {
+        let content = fs::read_to_string(file)?;
+        let invalid = |message: String| io::Error::new(io::ErrorKind::InvalidData, message);
+        let mut lines = content.lines();
+        let header = json::parse(lines.next().unwrap_or("")).ok()
+            .filter(|header| header.get("format").and_then(Value::as_str) == Some(FORMAT))
+            .ok_or_else(|| invalid(format!("{} is not a sortfs snapshot", file.display())))?;
+        let version = match header.get("version").and_then(Value::as_u64) {
+            Some(version) if (1..=VERSION).contains(&version) => version,
+            Some(version) if version > VERSION => {
+                return Err(invalid(format!("{} is a version {} snapshot, newer than this sortfs reads", file.display(), version)));
+            }
+            _ => return Err(invalid(format!("{} has no valid snapshot version", file.display()))),
+        };
+        let environment = Environment {
+            hostname: header.get("hostname").and_then(Value::as_str).map(String::from),
+            root: header.get("root").and_then(|root| {
+                Some((root.get("device")?.as_u64()?, root.get("inode")?.as_u64()?))
+            }),
+            options: header.get("options").and_then(Value::as_str).map(String::from),
+        };
+        let unknown = match &header {
+            Value::Object(members) => members.iter()
+                .filter(|(key, _)| !HEADER_KEYS.contains(&key.as_str()))
+                .map(|(key, value)| (key.clone(), value.encode()))
+                .collect(),
+            _ => Vec::new(),
+        };
+
+        let mut entries = HashMap::new();
+        for (number, line) in lines.enumerate().filter(|(_, line)| !line.trim().is_empty()) {
+            let parsed = json::parse(line).ok().and_then(|record| {
+                let path = read_path(&record)?;
+                let modified = record.get("mtime")?.as_u64()?;
+                let size = record.get("size").and_then(Value::as_u64).unwrap_or(0);
+                let inode = record.get("inode").and_then(Value::as_u64);
+                Some((path, SnapshotEntry { modified, size, inode }))
+            });
+            match parsed {
+                Some((path, entry)) => {
+                    entries.insert(path, entry);
+                }
+                None => return Err(invalid(format!("{}:{}: malformed entry", file.display(), number + 2))),
+            }
+        }
+        Ok(Snapshot { version, entries, environment, unknown })
+    }
+
+    /// `sortfs snapshot inspect`: what a snapshot records, without walking anything.
+    pub fn describe(&self, handle: &mut dyn Write) -> io::Result<()> {
+        writeln!(handle, "version: {}", self.version)?;
+        let unknown = "(not recorded)".to_string();
+        writeln!(handle, "hostname: {}", self.environment.hostname.as_ref().unwrap_or(&unknown))?;
+        let root = self.environment.root.map(|(device, inode)| format!("device {}, inode {}", device, inode));
+        writeln!(handle, "root: {}", root.as_ref().unwrap_or(&unknown))?;
+        writeln!(handle, "options: {}", self.environment.options.as_ref().unwrap_or(&unknown))?;
+        for (key, value) in &self.unknown {
+            writeln!(handle, "{}: {} (unknown to this sortfs)", key, value)?;
+        }
+        writeln!(handle, "entries: {}", self.entries.len())?;
+        let times = self.entries.values().map(|entry| entry.modified);
+        if let (Some(oldest), Some(newest)) = (times.clone().min(), times.max()) {
+            let format = |secs| time::format_local(UNIX_EPOCH + Duration::from_secs(secs));
+            writeln!(handle, "oldest: {}", format(oldest))?;
+            writeln!(handle, "newest: {}", format(newest))?;
+        }
+        writeln!(handle, "total size: {} bytes", self.entries.values().map(|entry| entry.size).sum::<u64>())
+    }
+
+    /// Scores how much each entry changed since the snapshot: the size delta for
+    /// files, and the number of added, removed or modified descendants for directories.
+    pub fn churn(&self, entries: &[Entry], root: &Path) -> Vec<u64> {
+        let mut changes: HashMap<PathBuf, u64> = HashMap::new();
+        let mut count_change = |path: &Path| {
+            for ancestor in path.ancestors().skip(1) {
+                *changes.entry(ancestor.to_path_buf()).or_default() += 1;
+            }
+        };
+
+        let mut current = HashMap::new();
+        for entry in entries {
+            let path = relative(entry.path(), root);
+            current.insert(path, entry);
+            match self.entries.get(path) {
+                Some(old) if old.modified == epoch_seconds(entry) && old.size == size(entry) => {}
+                _ => count_change(path),
+            }
+        }
+        for path in self.entries.keys() {
+            if !current.contains_key(path.as_path()) {
+                count_change(path);
+            }
+        }
+
+        entries.iter()
+            .map(|entry| {
+                let path = relative(entry.path(), root);
+                if entry.is_dir() {
+                    changes.get(path).copied().unwrap_or(0)
+                } else {
+                    let old_size = self.entries.get(path).map_or(0, |old| old.size);
+                    size(entry).abs_diff(old_size)
+                }
+            })
+            .collect()
+    }
+
+    /// Whether an entry is new since the snapshot, or modified (by mtime or size).
+    pub fn change(&self, entry: &Entry, root: &Path) -> Option<Change> {
+        match self.entries.get(relative(entry.path(), root)) {
+            None => Some(Change::New),
+            Some(old) if old.modified != epoch_seconds(entry) || old.size != size(entry) => Some(Change::Modified),
+            Some(_) => None,
+        }
+    }
+
+    /// For paths that now hold a different inode than in the snapshot (editors that save
+    /// by writing a new file and renaming it over the old one), moves the entry's time up
+    /// to the replacement, which is the inode's change time and can't be back-dated.
+    /// Returns whether any entry was adjusted.
+    pub fn track_replacements(&self, entries: &mut [Entry], root: &Path) -> bool {
+        let mut adjusted = false;
+        for entry in entries.iter_mut() {
+            let old_inode = self.entries.get(relative(entry.path(), root)).and_then(|old| old.inode);
+            if let (Some(old_inode), Some(inode), Some(changed)) = (old_inode, entry.inode(), entry.changed()) {
+                if old_inode != inode && changed > entry.modified {
+                    entry.modified = changed;
+                    adjusted = true;
+                }
+            }
+        }
+        adjusted
+    }
+}
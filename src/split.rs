@@ -0,0 +1,51 @@
+//! `--split-by-age`: results written to one file per age bucket instead of stdout.
+
+use std::fs::{self, File};
+use std::io::{self, BufWriter, Write};
+use std::path::{Path, PathBuf};
+use std::time::{Duration, SystemTime};
+
+use crate::time;
+
+const BUCKETS: [&str; 4] = ["today.txt", "week.txt", "month.txt", "older.txt"];
+
+pub struct AgeSplit {
+    // Oldest modification time that still belongs to today, week and month
+    bounds: [SystemTime; 3],
+    files: Vec<(PathBuf, BufWriter<File>)>,
+}
+
+impl AgeSplit {
+    /// Creates (or truncates) every bucket file in `dir`, so that a bucket left
+    /// empty by this walk doesn't keep the results of a previous one.
+    pub fn create(dir: &Path, now: SystemTime) -> io::Result<AgeSplit> {
+        fs::create_dir_all(dir)?;
+        let days = |count: u64| now.checked_sub(Duration::from_secs(count * 24 * 60 * 60)).unwrap_or(SystemTime::UNIX_EPOCH);
+        let midnight = time::parse_datetime("today", now).unwrap_or_else(|| days(1));
+        let mut files = Vec::new();
+        for name in BUCKETS {
+            let path = dir.join(name);
+            let file = File::create(&path).map_err(|e| with_path(&path, e))?;
+            files.push((path, BufWriter::new(file)));
+        }
+        Ok(AgeSplit { bounds: [midnight, days(7), days(30)], files })
+    }
+
+    /// Appends a formatted line to the bucket of an entry modified at `modified`.
+    pub fn write(&mut self, modified: SystemTime, line: &[u8]) -> io::Result<()> {
+        let bucket = self.bounds.iter().position(|bound| modified >= *bound).unwrap_or(BUCKETS.len() - 1);
+        let (path, file) = &mut self.files[bucket];
+        file.write_all(line).map_err(|e| with_path(path, e))
+    }
+
+    pub fn finish(self) -> io::Result<()> {
+        for (path, mut file) in self.files {
+            file.flush().map_err(|e| with_path(&path, e))?;
+        }
+        Ok(())
+    }
+}
+
+fn with_path(path: &Path, e: io::Error) -> io::Error {
+    io::Error::new(e.kind(), format!("{}: {}", path.display(), e))
+}
@@ -0,0 +1,97 @@
+//! The `info/exclude` rules of git submodules.
+//!
+//! A submodule's `.git` is a file pointing into the superproject's `.git/modules/`.
+//! The walker follows such files only for linked worktrees, whose git directory
+//! names the shared one in a `commondir` file; a submodule's git directory has
+//! none, so its exclude rules are applied here. Its `.gitignore` files are found
+//! like any other.
+
+use std::collections::HashMap;
+use std::env;
+use std::fs;
+use std::path::{Component, Path, PathBuf};
+use std::sync::{Arc, Mutex, RwLock};
+
+use ignore::gitignore::{Gitignore, GitignoreBuilder};
+
+pub struct SubmoduleExcludes {
+    cwd: PathBuf,
+    // Per directory as walked: the exclude rules of the submodule holding it, if any
+    walked: RwLock<HashMap<PathBuf, Option<Arc<Gitignore>>>>,
+    // The same per absolute directory, including the ones above the walk
+    dirs: Mutex<HashMap<PathBuf, Option<Arc<Gitignore>>>>,
+}
+
+impl SubmoduleExcludes {
+    pub fn new() -> SubmoduleExcludes {
+        SubmoduleExcludes {
+            cwd: env::current_dir().unwrap_or_default(),
+            walked: RwLock::new(HashMap::new()),
+            dirs: Mutex::new(HashMap::new()),
+        }
+    }
+
+    pub fn is_ignored(&self, path: &Path, is_dir: bool) -> bool {
+        let dir = match path.parent() {
+            Some(dir) => dir,
+            None => return false,
+        };
+        let cached = self.walked.read().unwrap().get(dir).cloned();
+        let exclude = cached.unwrap_or_else(|| {
+            let exclude = self.exclude(&self.absolute(dir));
+            self.walked.write().unwrap().insert(dir.to_path_buf(), exclude.clone());
+            exclude
+        });
+        exclude.is_some_and(|exclude| exclude.matched(self.absolute(path), is_dir).is_ignore())
+    }
+
+    /// Resolves `.` and `..` without following symlinks, as the walker's paths do.
+    fn absolute(&self, path: &Path) -> PathBuf {
+        let mut absolute = self.cwd.clone();
+        for component in path.components() {
+            match component {
+                Component::CurDir => {}
+                Component::ParentDir => {
+                    absolute.pop();
+                }
+                component => absolute.push(component),
+            }
+        }
+        absolute
+    }
+
+    fn exclude(&self, dir: &Path) -> Option<Arc<Gitignore>> {
+        if let Some(exclude) = self.dirs.lock().unwrap().get(dir) {
+            return exclude.clone();
+        }
+        let dot_git = dir.join(".git");
+        let exclude = match fs::symlink_metadata(&dot_git) {
+            Ok(meta) if meta.is_file() => submodule_git_dir(dir, &dot_git).and_then(|git_dir| load(dir, &git_dir)),
+            // The top of a repository or worktree, whose rules the walker has
+            Ok(_) => None,
+            Err(_) => dir.parent().and_then(|parent| self.exclude(parent)),
+        };
+        self.dirs.lock().unwrap().insert(dir.to_path_buf(), exclude.clone());
+        exclude
+    }
+}
+
+/// The git directory a `.git` file (`gitdir: PATH`) points to, unless it belongs
+/// to a linked worktree.
+fn submodule_git_dir(work_tree: &Path, dot_git: &Path) -> Option<PathBuf> {
+    let content = fs::read_to_string(dot_git).ok()?;
+    let git_dir = work_tree.join(content.lines().next()?.strip_prefix("gitdir: ")?.trim_end());
+    (!git_dir.join("commondir").exists()).then_some(git_dir)
+}
+
+fn load(work_tree: &Path, git_dir: &Path) -> Option<Arc<Gitignore>> {
+    let file = git_dir.join("info/exclude");
+    if !file.is_file() {
+        return None;
+    }
+    let mut builder = GitignoreBuilder::new(work_tree);
+    if let Some(e) = builder.add(&file) {
+        eprintln!("sortfs: {}: {}", file.display(), e);
+    }
+    builder.build().ok().filter(|exclude| !exclude.is_empty()).map(Arc::new)
+}
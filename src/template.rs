@@ -0,0 +1,128 @@
+//! `--format` templates: text with `{placeholder}`s filled in for each entry.
+
+use std::io::{self, Write};
+use std::path::Path;
+use std::time::UNIX_EPOCH;
+
+use crate::entry::Entry;
+use crate::time;
+
+/// How `{mtime}` is shown without a format of its own.
+const DEFAULT_TIME_FORMAT: &str = "%Y-%m-%d %H:%M:%S";
+
+#[derive(Clone, Copy)]
+enum Field {
+    Path,
+    Basename,
+    Parent,
+    Mtime,
+    Epoch,
+    Size,
+    Type,
+}
+
+enum Piece {
+    Text(String),
+    // A field, with the strftime format of `{mtime:FORMAT}`
+    Field(Field, Option<String>),
+}
+
+pub struct Template {
+    pieces: Vec<Piece>,
+}
+
+impl Template {
+    /// Parses a template. `{{` and `}}` stand for braces, and `\t`, `\n`, `\0` and `\\`
+    /// for a tab, a newline, a NUL and a backslash.
+    pub fn parse(template: &str) -> Result<Template, String> {
+        let mut pieces = Vec::new();
+        let mut text = String::new();
+        let mut chars = template.chars();
+        while let Some(c) = chars.next() {
+            match c {
+                '\\' => match chars.next() {
+                    Some('t') => text.push('\t'),
+                    Some('n') => text.push('\n'),
+                    Some('0') => text.push('\0'),
+                    Some('\\') => text.push('\\'),
+                    Some(other) => {
+                        text.push('\\');
+                        text.push(other);
+                    }
+                    None => text.push('\\'),
+                },
+                '{' if chars.as_str().starts_with('{') => {
+                    chars.next();
+                    text.push('{');
+                }
+                '}' if chars.as_str().starts_with('}') => {
+                    chars.next();
+                    text.push('}');
+                }
+                '{' => {
+                    let rest = chars.as_str();
+                    let end = rest.find('}').ok_or_else(|| format!("unclosed '{{' in '{}'", template))?;
+                    let (name, format) = match rest[..end].split_once(':') {
+                        Some((name, format)) => (name, Some(format.to_string())),
+                        None => (&rest[..end], None),
+                    };
+                    let field = match name {
+                        "path" => Field::Path,
+                        "basename" => Field::Basename,
+                        "parent" => Field::Parent,
+                        "mtime" => Field::Mtime,
+                        "epoch" => Field::Epoch,
+                        "size" => Field::Size,
+                        "type" => Field::Type,
+                        _ => return Err(format!("unknown placeholder '{{{}}}' (expected path, basename, parent, mtime, epoch, size or type)", name)),
+                    };
+                    if format.is_some() && !matches!(field, Field::Mtime) {
+                        return Err(format!("only {{mtime}} takes a format, not {{{}}}", name));
+                    }
+                    if !text.is_empty() {
+                        pieces.push(Piece::Text(std::mem::take(&mut text)));
+                    }
+                    pieces.push(Piece::Field(field, format));
+                    chars = rest[end + 1..].chars();
+                }
+                '}' => return Err(format!("unmatched '}}' in '{}' (write }}}} for a brace)", template)),
+                c => text.push(c),
+            }
+        }
+        if !text.is_empty() {
+            pieces.push(Piece::Text(text));
+        }
+        Ok(Template { pieces })
+    }
+
+    /// Writes the template for `entry`, listed as `path`; an unknown size is left empty.
+    pub fn render(&self, handle: &mut dyn Write, path: &str, entry: &Entry) -> io::Result<()> {
+        for piece in &self.pieces {
+            match piece {
+                Piece::Text(text) => write!(handle, "{}", text)?,
+                Piece::Field(Field::Path, _) => write!(handle, "{}", path)?,
+                Piece::Field(Field::Basename, _) => {
+                    let name = Path::new(path).file_name().map_or(path.into(), |name| name.to_string_lossy());
+                    write!(handle, "{}", name)?
+                }
+                Piece::Field(Field::Parent, _) => {
+                    let parent = Path::new(path).parent().map(Path::display).map(|parent| parent.to_string());
+                    write!(handle, "{}", parent.filter(|parent| !parent.is_empty()).unwrap_or_else(|| ".".to_string()))?
+                }
+                Piece::Field(Field::Mtime, format) => {
+                    write!(handle, "{}", time::strftime(entry.modified, format.as_deref().unwrap_or(DEFAULT_TIME_FORMAT)))?
+                }
+                Piece::Field(Field::Epoch, _) => {
+                    write!(handle, "{}", entry.modified.duration_since(UNIX_EPOCH).map_or(0, |d| d.as_secs()))?
+                }
+                Piece::Field(Field::Size, _) => {
+                    if let Some(size) = entry.size() {
+                        write!(handle, "{}", size)?;
+                    }
+                }
+                Piece::Field(Field::Type, _) => write!(handle, "{}", entry.kind())?,
+            }
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,374 @@
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+/// Parses a duration such as `2d`, `1w`, `90min`, `1h30m` or `500ms`.
+pub fn parse_duration(s: &str) -> Option<Duration> {
+    let mut total_ms: u64 = 0;
+    let mut rest = s.trim();
+    if rest.is_empty() {
+        return None;
+    }
+    while !rest.is_empty() {
+        let digits = rest.find(|c: char| !c.is_ascii_digit()).unwrap_or(rest.len());
+        let quantity: u64 = rest[..digits].parse().ok()?;
+        rest = rest[digits..].trim_start();
+        let unit_len = rest.find(|c: char| !c.is_ascii_alphabetic()).unwrap_or(rest.len());
+        let millis: u64 = match &rest[..unit_len] {
+            "ms" | "msec" | "msecs" | "millisecond" | "milliseconds" => 1,
+            "s" | "sec" | "secs" | "second" | "seconds" => 1000,
+            "m" | "min" | "mins" | "minute" | "minutes" => 60 * 1000,
+            "h" | "hr" | "hrs" | "hour" | "hours" => 60 * 60 * 1000,
+            "d" | "day" | "days" => 24 * 60 * 60 * 1000,
+            "w" | "week" | "weeks" => 7 * 24 * 60 * 60 * 1000,
+            "M" | "month" | "months" => 30 * 24 * 60 * 60 * 1000,
+            "y" | "year" | "years" => 365 * 24 * 60 * 60 * 1000,
+            _ => return None,
+        };
+        total_ms = total_ms.checked_add(quantity.checked_mul(millis)?)?;
+        rest = rest[unit_len..].trim_start();
+    }
+    Some(Duration::from_millis(total_ms))
+}
+
+/// Parses an absolute local time: `YYYY-MM-DD`, `YYYY-MM-DD HH:MM[:SS]`
+/// (a `T` separator is accepted too), `YYYY-MM` for the first of the month,
+/// `today`, `yesterday` (relative to `now`) or `@EPOCH`. Dates may also be written
+/// with `/` or `.`.
+pub fn parse_datetime(s: &str, now: SystemTime) -> Option<SystemTime> {
+    let s = s.trim();
+    if let Some(epoch) = s.strip_prefix('@') {
+        return UNIX_EPOCH.checked_add(Duration::from_secs(epoch.parse().ok()?));
+    }
+    match s {
+        "today" | "yesterday" => {
+            let (year, month, day, ..) = local_fields(now);
+            let midnight = local_to_system_time(year, month, day, 0, 0, 0)?;
+            // Shifting the midnight stays correct across a DST change as long as it isn't at midnight
+            return if s == "today" { Some(midnight) } else { midnight.checked_sub(Duration::from_secs(86400)) };
+        }
+        _ => {}
+    }
+    let (date, time) = match s.find([' ', 'T']) {
+        Some(i) => (&s[..i], Some(&s[i + 1..])),
+        None => (s, None),
+    };
+
+    let mut date_fields = date.split(['-', '/', '.']);
+    let year: i64 = date_fields.next()?.parse().ok()?;
+    let month: u32 = date_fields.next()?.parse().ok()?;
+    let day: u32 = match date_fields.next() {
+        Some(field) => field.parse().ok()?,
+        None if time.is_none() => 1,
+        None => return None,
+    };
+    if date_fields.next().is_some() || !(0..=9999).contains(&year) || !(1..=12).contains(&month)
+        || day == 0 || day > days_in_month(year, month) {
+        return None;
+    }
+
+    let (mut hour, mut minute, mut second) = (0, 0, 0);
+    if let Some(time) = time {
+        let mut time_fields = time.split(':');
+        hour = time_fields.next()?.parse().ok()?;
+        minute = time_fields.next()?.parse().ok()?;
+        second = match time_fields.next() {
+            Some(field) => field.parse().ok()?,
+            None => 0,
+        };
+        if time_fields.next().is_some() || hour > 23 || minute > 59 || second > 60 {
+            return None;
+        }
+    }
+    local_to_system_time(year, month, day, hour, minute, second)
+}
+
+fn days_in_month(year: i64, month: u32) -> u32 {
+    match month {
+        2 if year % 4 == 0 && (year % 100 != 0 || year % 400 == 0) => 29,
+        2 => 28,
+        4 | 6 | 9 | 11 => 30,
+        _ => 31,
+    }
+}
+
+/// Resolves a `--changed-within`/`--changed-before` style argument: either a
+/// duration counted back from `now`, or an absolute date.
+pub fn parse_time_bound(s: &str, now: SystemTime) -> Result<SystemTime, String> {
+    if let Some(duration) = parse_duration(s) {
+        return now.checked_sub(duration)
+            .filter(|time| *time >= UNIX_EPOCH)
+            .ok_or_else(|| format!("'{}' reaches back before the epoch", s));
+    }
+    parse_datetime(s, now).ok_or_else(|| format!("'{}' is neither a duration (e.g. 2d) nor a date (e.g. 2024-01-31)", s))
+}
+
+#[cfg(unix)]
+pub fn local_to_system_time(year: i64, month: u32, day: u32, hour: u32, minute: u32, second: u32) -> Option<SystemTime> {
+    // SAFETY: tm is plain data and mktime() only reads/normalizes it
+    let epoch = unsafe {
+        let mut tm: libc::tm = std::mem::zeroed();
+        tm.tm_year = (year - 1900) as libc::c_int;
+        tm.tm_mon = month as libc::c_int - 1;
+        tm.tm_mday = day as libc::c_int;
+        tm.tm_hour = hour as libc::c_int;
+        tm.tm_min = minute as libc::c_int;
+        tm.tm_sec = second as libc::c_int;
+        tm.tm_isdst = -1;
+        libc::mktime(&mut tm)
+    };
+    from_epoch(epoch as i64)
+}
+
+#[cfg(not(unix))]
+pub fn local_to_system_time(year: i64, month: u32, day: u32, hour: u32, minute: u32, second: u32) -> Option<SystemTime> {
+    let days = days_from_civil(year, month, day);
+    from_epoch(days * 86400 + (hour * 3600 + minute * 60 + second) as i64)
+}
+
+/// Broken-down local time of `time`: year, month, day, hour, minute, second.
+#[cfg(unix)]
+pub fn local_fields(time: SystemTime) -> (i64, u32, u32, u32, u32, u32) {
+    let epoch = time.duration_since(UNIX_EPOCH).map_or(0, |d| d.as_secs()) as libc::time_t;
+    // SAFETY: localtime_r() only writes the tm we own
+    let tm = unsafe {
+        let mut tm: libc::tm = std::mem::zeroed();
+        libc::localtime_r(&epoch, &mut tm);
+        tm
+    };
+    (tm.tm_year as i64 + 1900, tm.tm_mon as u32 + 1, tm.tm_mday as u32,
+     tm.tm_hour as u32, tm.tm_min as u32, tm.tm_sec as u32)
+}
+
+#[cfg(not(unix))]
+pub fn local_fields(time: SystemTime) -> (i64, u32, u32, u32, u32, u32) {
+    let secs = time.duration_since(UNIX_EPOCH).map_or(0, |d| d.as_secs()) as i64;
+    let (year, month, day) = civil_from_days(secs.div_euclid(86400));
+    let rem = secs.rem_euclid(86400) as u32;
+    (year, month, day, rem / 3600, rem / 60 % 60, rem % 60)
+}
+
+/// `YYYY-MM-DDTHH:MM:SS` in local time.
+pub fn format_local(time: SystemTime) -> String {
+    let (year, month, day, hour, minute, second) = local_fields(time);
+    format!("{:04}-{:02}-{:02}T{:02}:{:02}:{:02}", year, month, day, hour, minute, second)
+}
+
+/// `time` in local time as laid out by a strftime(3) `format`: %Y %y %m %d %e %H
+/// %M %S %b %B %F %T %R %s and %%. Other sequences are kept as written.
+pub fn strftime(time: SystemTime, format: &str) -> String {
+    const MONTHS: [&str; 12] = ["January", "February", "March", "April", "May", "June", "July",
+                                "August", "September", "October", "November", "December"];
+    let (year, month, day, hour, minute, second) = local_fields(time);
+    let month_name = MONTHS[(month as usize).clamp(1, 12) - 1];
+    let mut formatted = String::new();
+    let mut chars = format.chars();
+    while let Some(c) = chars.next() {
+        if c != '%' {
+            formatted.push(c);
+            continue;
+        }
+        match chars.next() {
+            Some('Y') => formatted += &year.to_string(),
+            Some('y') => formatted += &format!("{:02}", year.rem_euclid(100)),
+            Some('m') => formatted += &format!("{:02}", month),
+            Some('d') => formatted += &format!("{:02}", day),
+            Some('e') => formatted += &format!("{:2}", day),
+            Some('H') => formatted += &format!("{:02}", hour),
+            Some('M') => formatted += &format!("{:02}", minute),
+            Some('S') => formatted += &format!("{:02}", second),
+            Some('b') => formatted += &month_name[..3],
+            Some('B') => formatted += month_name,
+            Some('F') => formatted += &format!("{:04}-{:02}-{:02}", year, month, day),
+            Some('T') => formatted += &format!("{:02}:{:02}:{:02}", hour, minute, second),
+            Some('R') => formatted += &format!("{:02}:{:02}", hour, minute),
+            Some('s') => formatted += &time.duration_since(UNIX_EPOCH).map_or(0, |d| d.as_secs()).to_string(),
+            Some('%') => formatted.push('%'),
+            Some(other) => {
+                formatted.push('%');
+                formatted.push(other);
+            }
+            None => formatted.push('%'),
+        }
+    }
+    formatted
+}
+
+/// How listed modification times are shown, see --time-style.
+pub enum TimeStyle {
+    Strftime(String),
+    // "5 minutes ago", relative to when the listing is printed
+    Relative(SystemTime),
+    // The same as a compact age: "3m", "2h", "5d"
+    Age(SystemTime),
+}
+
+impl TimeStyle {
+    /// `iso`, `long-iso`, `full-iso`, `relative`, `age` or `+FORMAT` for a strftime format.
+    pub fn parse(style: &str, now: SystemTime) -> Result<TimeStyle, String> {
+        let format = match style {
+            "iso" => "%Y-%m-%d",
+            "long-iso" => "%Y-%m-%d %H:%M",
+            "full-iso" => "%Y-%m-%d %H:%M:%S",
+            "relative" => return Ok(TimeStyle::Relative(now)),
+            "age" => return Ok(TimeStyle::Age(now)),
+            style => match style.strip_prefix('+') {
+                Some(format) => format,
+                None => return Err(format!("'{}' is not a time style (iso, long-iso, full-iso, relative, age or +FORMAT)", style)),
+            },
+        };
+        Ok(TimeStyle::Strftime(format.to_string()))
+    }
+
+    pub fn format(&self, time: SystemTime) -> String {
+        match self {
+            TimeStyle::Strftime(format) => strftime(time, format),
+            TimeStyle::Relative(now) => {
+                let secs = match now.duration_since(time) {
+                    Ok(age) => age.as_secs(),
+                    Err(_) => return "in the future".to_string(),
+                };
+                let (count, unit) = match secs {
+                    0..=59 => return "just now".to_string(),
+                    60..=3599 => (secs / 60, "minute"),
+                    3600..=86399 => (secs / 3600, "hour"),
+                    86400..=604799 => (secs / 86400, "day"),
+                    604800..=2591999 => (secs / 604800, "week"),
+                    2592000..=31535999 => (secs / 2592000, "month"),
+                    _ => (secs / 31536000, "year"),
+                };
+                format!("{} {}{} ago", count, unit, if count == 1 { "" } else { "s" })
+            }
+            TimeStyle::Age(now) => {
+                // Entries from the future (clock skew) count as just written
+                let secs = now.duration_since(time).map_or(0, |age| age.as_secs());
+                match secs {
+                    0..=59 => format!("{}s", secs),
+                    60..=3599 => format!("{}m", secs / 60),
+                    3600..=86399 => format!("{}h", secs / 3600),
+                    86400..=604799 => format!("{}d", secs / 86400),
+                    604800..=31535999 => format!("{}w", secs / 604800),
+                    _ => format!("{}y", secs / 31536000),
+                }
+            }
+        }
+    }
+
+    /// Width to pad to, so that the paths after the column line up.
+    pub fn width(&self) -> usize {
+        match self {
+            TimeStyle::Strftime(_) => 0,
+            TimeStyle::Relative(_) => "11 months ago".len(),
+            TimeStyle::Age(_) => "59m".len(),
+        }
+    }
+}
+
+/// `YYYY-MM-DDTHH:MM:SSZ` in UTC.
+pub fn format_utc(time: SystemTime) -> String {
+    let secs = time.duration_since(UNIX_EPOCH).map_or(0, |d| d.as_secs()) as i64;
+    let (year, month, day) = civil_from_days(secs.div_euclid(86400));
+    let rem = secs.rem_euclid(86400);
+    format!("{:04}-{:02}-{:02}T{:02}:{:02}:{:02}Z", year, month, day, rem / 3600, rem / 60 % 60, rem % 60)
+}
+
+fn from_epoch(epoch: i64) -> Option<SystemTime> {
+    if epoch < 0 {
+        return None;
+    }
+    Some(UNIX_EPOCH + Duration::from_secs(epoch as u64))
+}
+
+/// Days since 1970-01-01 for a proleptic Gregorian date (Howard Hinnant's algorithm).
+#[cfg(not(unix))]
+fn days_from_civil(year: i64, month: u32, day: u32) -> i64 {
+    let year = if month <= 2 { year - 1 } else { year };
+    let era = if year >= 0 { year } else { year - 399 } / 400;
+    let yoe = year - era * 400;
+    let mp = (month as i64 + 9) % 12;
+    let doy = (153 * mp + 2) / 5 + day as i64 - 1;
+    let doe = yoe * 365 + yoe / 4 - yoe / 100 + doy;
+    era * 146097 + doe - 719468
+}
+
+/// Inverse of `days_from_civil`.
+fn civil_from_days(days: i64) -> (i64, u32, u32) {
+    let z = days + 719468;
+    let era = if z >= 0 { z } else { z - 146096 } / 146097;
+    let doe = z - era * 146097;
+    let yoe = (doe - doe / 1460 + doe / 36524 - doe / 146096) / 365;
+    let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+    let mp = (5 * doy + 2) / 153;
+    let day = (doy - (153 * mp + 2) / 5 + 1) as u32;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 } as u32;
+    let year = yoe + era * 400 + if month <= 2 { 1 } else { 0 };
+    (year, month, day)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const MINUTE: u64 = 60;
+    const DAY: u64 = 24 * 60 * MINUTE;
+
+    #[test]
+    fn durations() {
+        assert_eq!(parse_duration("2d"), Some(Duration::from_secs(2 * DAY)));
+        assert_eq!(parse_duration("1h30m"), Some(Duration::from_secs(90 * MINUTE)));
+        assert_eq!(parse_duration("1h 30min"), Some(Duration::from_secs(90 * MINUTE)));
+        assert_eq!(parse_duration("500ms"), Some(Duration::from_millis(500)));
+        assert_eq!(parse_duration("1M"), Some(Duration::from_secs(30 * DAY)));
+        assert_eq!(parse_duration("1m"), Some(Duration::from_secs(MINUTE)));
+        assert_eq!(parse_duration("0s"), Some(Duration::ZERO));
+    }
+
+    #[test]
+    fn malformed_durations() {
+        for s in ["", " ", "10", "d", "2x", "-1d", "1.5h", "2d!", "99999999999999999y", "18446744073709551616s"] {
+            assert_eq!(parse_duration(s), None, "{}", s);
+        }
+    }
+
+    #[test]
+    fn datetimes() {
+        let now = UNIX_EPOCH + Duration::from_secs(1_706_702_400);
+        let local = |year, month, day, hour, minute, second| local_to_system_time(year, month, day, hour, minute, second);
+        assert_eq!(parse_datetime("2024-01-31", now), local(2024, 1, 31, 0, 0, 0));
+        assert_eq!(parse_datetime("2024/01/31 12:30", now), local(2024, 1, 31, 12, 30, 0));
+        assert_eq!(parse_datetime("2024.01.31T12:30:15", now), local(2024, 1, 31, 12, 30, 15));
+        assert_eq!(parse_datetime("2024-02", now), local(2024, 2, 1, 0, 0, 0));
+        assert_eq!(parse_datetime("2024-02-29", now), local(2024, 2, 29, 0, 0, 0));
+        assert_eq!(parse_datetime("@0", now), Some(UNIX_EPOCH));
+        assert_eq!(parse_datetime("@1706702400", now), Some(now));
+        let (year, month, day, ..) = local_fields(now);
+        let today = local(year, month, day, 0, 0, 0);
+        assert_eq!(parse_datetime("today", now), today);
+        assert_eq!(parse_datetime("yesterday", now), today.and_then(|today| today.checked_sub(Duration::from_secs(DAY))));
+    }
+
+    #[test]
+    fn malformed_datetimes() {
+        let now = UNIX_EPOCH + Duration::from_secs(1_706_702_400);
+        for s in [
+            "", "2024", "2024-13-01", "2024-00-10", "2024-01-00", "2024-01-32", "2023-02-29", "2024-04-31",
+            "2024-02 12:00", "2024-01-31 24:00", "2024-01-31 12:60", "2024-01-31 12", "2024-01-31-01",
+            "99999999999-01-01", "@-1", "@x", "@18446744073709551615", "tomorrow",
+        ] {
+            assert_eq!(parse_datetime(s, now), None, "{}", s);
+        }
+    }
+
+    #[test]
+    fn time_bounds() {
+        let now = UNIX_EPOCH + Duration::from_secs(10 * DAY);
+        assert_eq!(parse_time_bound("2d", now), Ok(UNIX_EPOCH + Duration::from_secs(8 * DAY)));
+        assert!(parse_time_bound("11d", now).is_err());
+        assert!(parse_time_bound("soon", now).is_err());
+    }
+
+    #[test]
+    fn civil_days_round_trip() {
+        assert_eq!(civil_from_days(0), (1970, 1, 1));
+        assert_eq!(civil_from_days(19_753), (2024, 1, 31));
+        assert_eq!(civil_from_days(11_016), (2000, 2, 29));
+    }
+}
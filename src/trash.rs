@@ -0,0 +1,117 @@
+//! Moving files to the desktop trash for `--action trash`.
+//!
+//! Follows the freedesktop.org trash specification on Unix desktops and uses `~/.Trash`
+//! on macOS.
+
+use std::io;
+use std::path::Path;
+#[cfg(unix)]
+use std::{env, fs};
+#[cfg(all(unix, not(target_os = "macos")))]
+use std::{fs::OpenOptions, io::Write, path::PathBuf};
+
+#[cfg(all(unix, not(target_os = "macos")))]
+pub fn trash(path: &Path) -> io::Result<()> {
+    use std::os::unix::fs::MetadataExt;
+
+    let path = crate::absolute_path(path)?;
+    let home = home_trash()?;
+    let device = fs::symlink_metadata(&path)?.dev();
+    // The home trash may not exist yet; the nearest existing ancestor tells its filesystem
+    let home_device = home.ancestors().find_map(|dir| fs::metadata(dir).ok()).map(|meta| meta.dev());
+    let trash_dir = match home_device {
+        Some(home_device) if home_device == device => home,
+        // Renaming can't cross filesystems; use the trash at the top of the file's own mount
+        _ => {
+            let top = mount_point(&path, device);
+            // SAFETY: getuid() has no preconditions
+            top.join(format!(".Trash-{}", unsafe { libc::getuid() }))
+        }
+    };
+    let files = trash_dir.join("files");
+    let info = trash_dir.join("info");
+    fs::create_dir_all(&files)?;
+    fs::create_dir_all(&info)?;
+
+    let name = path.file_name().ok_or_else(|| io::Error::from(io::ErrorKind::InvalidInput))?;
+    // The .trashinfo file is created exclusively first; that reserves the name
+    for attempt in 1.. {
+        let mut trashed = name.to_os_string();
+        if attempt > 1 {
+            trashed.push(format!(".{}", attempt));
+        }
+        let mut info_name = trashed.clone();
+        info_name.push(".trashinfo");
+        let info_path = info.join(&info_name);
+        let mut info_file = match OpenOptions::new().write(true).create_new(true).open(&info_path) {
+            Ok(file) => file,
+            Err(e) if e.kind() == io::ErrorKind::AlreadyExists => continue,
+            Err(e) => return Err(e),
+        };
+        write!(info_file, "[Trash Info]\nPath={}\nDeletionDate={}\n",
+               percent_encode(&path), crate::time::format_local(std::time::SystemTime::now()))?;
+        return fs::rename(&path, files.join(&trashed)).inspect_err(|_| {
+            let _ = fs::remove_file(&info_path);
+        });
+    }
+    unreachable!()
+}
+
+#[cfg(all(unix, not(target_os = "macos")))]
+fn home_trash() -> io::Result<PathBuf> {
+    let data_home = env::var_os("XDG_DATA_HOME")
+        .filter(|dir| !dir.is_empty())
+        .map(PathBuf::from)
+        .or_else(|| env::var_os("HOME").map(|home| Path::new(&home).join(".local/share")))
+        .ok_or_else(|| io::Error::new(io::ErrorKind::NotFound, "neither XDG_DATA_HOME nor HOME is set"))?;
+    Ok(data_home.join("Trash"))
+}
+
+/// Topmost ancestor of `path` still on `device`.
+#[cfg(all(unix, not(target_os = "macos")))]
+fn mount_point(path: &Path, device: u64) -> PathBuf {
+    use std::os::unix::fs::MetadataExt;
+    path.ancestors()
+        .skip(1)
+        .take_while(|dir| fs::metadata(dir).is_ok_and(|meta| meta.dev() == device))
+        .last()
+        .unwrap_or(Path::new("/"))
+        .to_path_buf()
+}
+
+#[cfg(all(unix, not(target_os = "macos")))]
+fn percent_encode(path: &Path) -> String {
+    use std::fmt::Write;
+    use std::os::unix::ffi::OsStrExt;
+    let mut encoded = String::new();
+    for &byte in path.as_os_str().as_bytes() {
+        if byte.is_ascii_alphanumeric() || b"/-_.~".contains(&byte) {
+            encoded.push(byte as char);
+        } else {
+            let _ = write!(encoded, "%{:02X}", byte);
+        }
+    }
+    encoded
+}
+
+#[cfg(target_os = "macos")]
+pub fn trash(path: &Path) -> io::Result<()> {
+    let home = env::var_os("HOME").ok_or_else(|| io::Error::new(io::ErrorKind::NotFound, "HOME is not set"))?;
+    let trash_dir = Path::new(&home).join(".Trash");
+    let name = path.file_name().ok_or_else(|| io::Error::from(io::ErrorKind::InvalidInput))?;
+    let mut target = trash_dir.join(name);
+    for attempt in 2.. {
+        if fs::symlink_metadata(&target).is_err() {
+            break;
+        }
+        let mut trashed = name.to_os_string();
+        trashed.push(format!(" {}", attempt));
+        target = trash_dir.join(trashed);
+    }
+    fs::rename(path, target)
+}
+
+#[cfg(not(unix))]
+pub fn trash(_path: &Path) -> io::Result<()> {
+    Err(io::Error::new(io::ErrorKind::Unsupported, "the Recycle Bin isn't supported yet"))
+}
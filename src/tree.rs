@@ -0,0 +1,67 @@
+//! `--tree`: the listing drawn as an indented tree.
+
+use std::collections::HashMap;
+use std::path::Path;
+
+struct Node {
+    parent: Option<usize>,
+    children: Vec<usize>,
+    // Position of the newest entry in the node's subtree
+    rank: usize,
+}
+
+/// Rearranges `listed` (entry index and shown path, newest first) into tree order.
+/// Each entry is shown under its nearest listed ancestor, relative to it, and
+/// siblings keep the order of their newest entry. Also returns the connectors
+/// (`├── `, `│   └── `) to print before each entry.
+pub fn arrange(listed: Vec<(usize, String)>) -> (Vec<(usize, String)>, HashMap<usize, String>) {
+    let positions: HashMap<&Path, usize> = listed.iter().enumerate().map(|(position, (_, disp))| (Path::new(disp.as_str()), position)).collect();
+    let mut nodes: Vec<Node> = listed.iter().enumerate()
+        .map(|(position, (_, disp))| Node {
+            parent: Path::new(disp.as_str()).ancestors().skip(1).find_map(|ancestor| positions.get(ancestor).copied()),
+            children: Vec::new(),
+            rank: position,
+        })
+        .collect();
+    for position in 0..nodes.len() {
+        let mut parent = nodes[position].parent;
+        while let Some(ancestor) = parent {
+            nodes[ancestor].rank = nodes[ancestor].rank.min(position);
+            parent = nodes[ancestor].parent;
+        }
+    }
+    let mut tops = Vec::new();
+    for position in 0..nodes.len() {
+        match nodes[position].parent {
+            Some(parent) => nodes[parent].children.push(position),
+            None => tops.push(position),
+        }
+    }
+    for position in 0..nodes.len() {
+        let mut children = std::mem::take(&mut nodes[position].children);
+        children.sort_by_key(|child| nodes[*child].rank);
+        nodes[position].children = children;
+    }
+    tops.sort_by_key(|top| nodes[*top].rank);
+
+    let mut arranged = Vec::with_capacity(listed.len());
+    let mut connectors = HashMap::new();
+    // Depth-first, with the indentation built up for the children of each node
+    let mut stack: Vec<(usize, String, String)> = tops.iter().rev().map(|top| (*top, String::new(), String::new())).collect();
+    while let Some((position, connector, indent)) = stack.pop() {
+        let (index, disp) = &listed[position];
+        let label = nodes[position].parent
+            .and_then(|parent| Path::new(disp.as_str()).strip_prefix(&listed[parent].1).ok())
+            .map_or_else(|| disp.clone(), |relative| relative.display().to_string());
+        arranged.push((*index, label));
+        connectors.insert(*index, connector);
+        let children = &nodes[position].children;
+        for (i, child) in children.iter().enumerate().rev() {
+            let last = i + 1 == children.len();
+            let connector = format!("{}{}", indent, if last { "└── " } else { "├── " });
+            let child_indent = format!("{}{}", indent, if last { "    " } else { "│   " });
+            stack.push((*child, connector, child_indent));
+        }
+    }
+    (arranged, connectors)
+}
@@ -0,0 +1,58 @@
+//! Name <-> id lookups for users and groups.
+
+#[cfg(unix)]
+use std::ffi::{CStr, CString};
+
+#[cfg(unix)]
+pub fn uid_by_name(name: &str) -> Option<u32> {
+    let name = CString::new(name).ok()?;
+    // SAFETY: the returned record is only read before any other passwd call
+    unsafe {
+        let passwd = libc::getpwnam(name.as_ptr());
+        if passwd.is_null() { None } else { Some((*passwd).pw_uid) }
+    }
+}
+
+#[cfg(unix)]
+pub fn gid_by_name(name: &str) -> Option<u32> {
+    let name = CString::new(name).ok()?;
+    // SAFETY: the returned record is only read before any other group call
+    unsafe {
+        let group = libc::getgrnam(name.as_ptr());
+        if group.is_null() { None } else { Some((*group).gr_gid) }
+    }
+}
+
+/// Name of the user with `uid`, or the number itself if there is none.
+#[cfg(unix)]
+pub fn user_name(uid: u32) -> String {
+    let mut buffer = vec![0 as libc::c_char; 4096];
+    let mut passwd: libc::passwd = unsafe { std::mem::zeroed() };
+    let mut result = std::ptr::null_mut();
+    // SAFETY: all pointers refer to live buffers of the advertised sizes
+    let found = unsafe {
+        libc::getpwuid_r(uid, &mut passwd, buffer.as_mut_ptr(), buffer.len(), &mut result) == 0
+            && !result.is_null()
+    };
+    if found {
+        // SAFETY: getpwuid_r() succeeded, so pw_name points into buffer
+        unsafe { CStr::from_ptr(passwd.pw_name) }.to_string_lossy().into_owned()
+    } else {
+        uid.to_string()
+    }
+}
+
+#[cfg(not(unix))]
+pub fn uid_by_name(_name: &str) -> Option<u32> {
+    None
+}
+
+#[cfg(not(unix))]
+pub fn gid_by_name(_name: &str) -> Option<u32> {
+    None
+}
+
+#[cfg(not(unix))]
+pub fn user_name(uid: u32) -> String {
+    uid.to_string()
+}
@@ -0,0 +1,30 @@
+//! `sortfs warm`: a stat() of everything a query would visit, ahead of time.
+
+use std::io;
+use std::path::Path;
+use std::process;
+use std::time::Instant;
+
+use crate::filter::Filters;
+use crate::{build_entries, priority, WalkOptions};
+
+/// Walks `root` with the default query settings, stat()ing every entry so the
+/// kernel's dentry and inode caches hold the tree for the next interactive run.
+pub fn run(root: &Path) -> io::Result<()> {
+    if !root.is_dir() {
+        eprintln!("Error: {} is not a directory", root.display());
+        process::exit(1);
+    }
+    if let Err(e) = priority::idle_io() {
+        eprintln!("sortfs: warning: running at normal I/O priority: {}", e);
+    }
+    let started = Instant::now();
+    let options = WalkOptions {
+        sort: false,
+        metadata: true,
+        ..WalkOptions::default()
+    };
+    let touched = build_entries(&Filters::default(), &options, &root.to_path_buf(), String::new(), None).len();
+    eprintln!("sortfs: warmed {} entries under {} in {:.1?}", touched, root.display(), started.elapsed());
+    Ok(())
+}
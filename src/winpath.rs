@@ -0,0 +1,80 @@
+//! Windows path forms: `\\?\` extended-length paths, as canonicalize() returns them,
+//! and `\\server\share` UNC roots, which must keep their trailing separator.
+
+use std::path::PathBuf;
+
+/// `path` without its `\\?\` prefix where the plain form names the same file:
+/// `\\?\C:\dir` becomes `C:\dir` and `\\?\UNC\server\share` becomes `\\server\share`.
+/// The standard library adds the prefix back for paths too long without it.
+#[cfg(windows)]
+pub fn simplify(path: PathBuf) -> PathBuf {
+    use std::path::{Component, Prefix};
+
+    let plain = match path.components().next() {
+        Some(Component::Prefix(prefix)) => match prefix.kind() {
+            Prefix::VerbatimDisk(letter) => format!("{}:", letter as char),
+            Prefix::VerbatimUNC(server, share) => {
+                format!(r"\\{}\{}", server.to_string_lossy(), share.to_string_lossy())
+            }
+            _ => return path,
+        },
+        _ => return path,
+    };
+    // Names only the verbatim form can reach, like `con` or `name.`
+    let verbatim_only = path.components().skip(1).any(|component| match component {
+        Component::Normal(name) => {
+            let name = name.to_string_lossy();
+            let stem = name.split('.').next().unwrap_or_default().trim_end().to_ascii_uppercase();
+            name.ends_with('.') || name.ends_with(' ') || name.contains('/') || is_reserved(&stem)
+        }
+        _ => false,
+    });
+    if verbatim_only {
+        return path;
+    }
+    let mut simplified = PathBuf::from(plain);
+    for component in path.components().skip(1) {
+        simplified.push(component);
+    }
+    simplified
+}
+
+#[cfg(not(windows))]
+pub fn simplify(path: PathBuf) -> PathBuf {
+    path
+}
+
+#[cfg(windows)]
+fn is_reserved(stem: &str) -> bool {
+    match stem {
+        "CON" | "PRN" | "AUX" | "NUL" | "CONIN$" | "CONOUT$" => true,
+        _ => {
+            let (name, digit) = stem.split_at(stem.len().saturating_sub(1));
+            matches!(name, "COM" | "LPT") && matches!(digit.as_bytes(), [b'1'..=b'9'])
+        }
+    }
+}
+
+/// `path` without trailing separators, except those of a root such as `C:\` or
+/// `\\server\share\`, which would otherwise name another directory.
+#[cfg(windows)]
+pub fn trim_separators(path: &str) -> &str {
+    if std::path::Path::new(path).parent().is_none() {
+        return path;
+    }
+    path.trim_end_matches(['/', '\\'])
+}
+
+#[cfg(not(windows))]
+pub fn trim_separators(path: &str) -> &str {
+    path.trim_end_matches('/')
+}
+
+/// Bytes to skip in a path under `leading` to reach the part relative to it.
+pub fn relative_start(leading: &str) -> usize {
+    if leading.ends_with(std::path::is_separator) {
+        leading.len()
+    } else {
+        leading.len() + 1
+    }
+}
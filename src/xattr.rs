@@ -0,0 +1,55 @@
+//! Extended attribute lookups for `--has-xattr`.
+
+use std::io;
+use std::path::Path;
+
+/// Value of attribute `name` on `path` (following symlinks), `None` if it isn't set.
+#[cfg(any(target_os = "linux", target_os = "macos"))]
+pub fn get(path: &Path, name: &str) -> io::Result<Option<Vec<u8>>> {
+    use std::ffi::CString;
+    use std::os::unix::ffi::OsStrExt;
+    let invalid = |_| io::Error::from(io::ErrorKind::InvalidInput);
+    let path = CString::new(path.as_os_str().as_bytes()).map_err(invalid)?;
+    let name = CString::new(name).map_err(invalid)?;
+    let mut value = Vec::new();
+    loop {
+        // SAFETY: both strings are NUL-terminated and value has room for value.len() bytes
+        let len = unsafe { getxattr(&path, &name, &mut value) };
+        if len >= 0 {
+            // A size query (empty buffer) only reports the length; fetch the value itself
+            if value.is_empty() && len > 0 {
+                value.resize(len as usize, 0);
+                continue;
+            }
+            value.truncate(len as usize);
+            return Ok(Some(value));
+        }
+        let err = io::Error::last_os_error();
+        match err.raw_os_error() {
+            Some(libc::ERANGE) => value.clear(),
+            Some(code) if code == NO_ATTRIBUTE => return Ok(None),
+            _ => return Err(err),
+        }
+    }
+}
+
+#[cfg(target_os = "linux")]
+const NO_ATTRIBUTE: libc::c_int = libc::ENODATA;
+
+#[cfg(target_os = "macos")]
+const NO_ATTRIBUTE: libc::c_int = libc::ENOATTR;
+
+#[cfg(target_os = "linux")]
+unsafe fn getxattr(path: &std::ffi::CStr, name: &std::ffi::CStr, value: &mut [u8]) -> isize {
+    libc::getxattr(path.as_ptr(), name.as_ptr(), value.as_mut_ptr().cast(), value.len())
+}
+
+#[cfg(target_os = "macos")]
+unsafe fn getxattr(path: &std::ffi::CStr, name: &std::ffi::CStr, value: &mut [u8]) -> isize {
+    libc::getxattr(path.as_ptr(), name.as_ptr(), value.as_mut_ptr().cast(), value.len(), 0, 0)
+}
+
+#[cfg(not(any(target_os = "linux", target_os = "macos")))]
+pub fn get(_path: &Path, _name: &str) -> io::Result<Option<Vec<u8>>> {
+    Ok(None)
+}
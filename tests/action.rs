@@ -0,0 +1,155 @@
+use std::fs::{self, File};
+use std::io::Write;
+use std::path::PathBuf;
+use std::process::{Command, Stdio};
+use std::time::{Duration, SystemTime};
+
+fn scratch_dir(name: &str) -> PathBuf {
+    let dir = std::env::temp_dir().join(format!("sortfs-{}-{}", name, std::process::id()));
+    let _ = fs::remove_dir_all(&dir);
+    fs::create_dir_all(&dir).unwrap();
+    dir
+}
+
+fn age(path: &PathBuf, days: u64) {
+    let then = SystemTime::now() - Duration::from_secs(days * 24 * 60 * 60);
+    File::open(path).unwrap().set_modified(then).unwrap();
+}
+
+#[test]
+fn delete_leaves_unlisted_children() {
+    let root = scratch_dir("delete");
+    let old_dir = root.join("old");
+    fs::create_dir(&old_dir).unwrap();
+    fs::write(old_dir.join("old.txt"), "old").unwrap();
+    fs::write(old_dir.join("new.txt"), "new").unwrap();
+    age(&old_dir.join("old.txt"), 800);
+    age(&old_dir, 800);
+
+    let output = Command::new(env!("CARGO_BIN_EXE_sortfs"))
+        .arg(&root)
+        .args(["--changed-before", "1y", "--action", "delete", "--force", "--yes"])
+        .output()
+        .unwrap();
+
+    assert!(output.status.success());
+    assert!(!old_dir.join("old.txt").exists());
+    assert!(old_dir.join("new.txt").exists());
+    assert!(old_dir.is_dir());
+    fs::remove_dir_all(&root).unwrap();
+}
+
+#[test]
+fn delete_removes_directories_it_empties() {
+    let root = scratch_dir("delete-emptied");
+    let old_dir = root.join("old");
+    fs::create_dir_all(old_dir.join("older")).unwrap();
+    fs::write(old_dir.join("older").join("old.txt"), "old").unwrap();
+    age(&old_dir.join("older").join("old.txt"), 800);
+    age(&old_dir.join("older"), 800);
+    age(&old_dir, 800);
+
+    let output = Command::new(env!("CARGO_BIN_EXE_sortfs"))
+        .arg(&root)
+        .args(["--changed-before", "1y", "--action", "delete", "--force", "--yes"])
+        .output()
+        .unwrap();
+
+    assert!(output.status.success());
+    assert!(!old_dir.exists());
+    fs::remove_dir_all(&root).unwrap();
+}
+
+#[test]
+fn copy_keeps_entries_of_other_roots_below_their_root() {
+    let scratch = scratch_dir("copy-root");
+    let (main, other, dest) = (scratch.join("main"), scratch.join("other"), scratch.join("dest"));
+    fs::create_dir_all(&main).unwrap();
+    fs::create_dir_all(&other).unwrap();
+    fs::write(other.join("keep.txt"), "keep").unwrap();
+
+    let output = Command::new(env!("CARGO_BIN_EXE_sortfs"))
+        .current_dir(&main)
+        .arg("--root").arg(&other)
+        .arg("--action").arg(format!("copy:{}", dest.display()))
+        .args(["--yes", "."])
+        .output()
+        .unwrap();
+
+    assert!(output.status.success());
+    assert_eq!(fs::read_to_string(other.join("keep.txt")).unwrap(), "keep");
+    assert_eq!(fs::read_to_string(dest.join("keep.txt")).unwrap(), "keep");
+    fs::remove_dir_all(&scratch).unwrap();
+}
+
+#[test]
+fn copy_refuses_stdin_paths_outside_the_root() {
+    let scratch = scratch_dir("copy-stdin");
+    let (main, dest) = (scratch.join("main"), scratch.join("dest"));
+    fs::create_dir_all(&main).unwrap();
+    let file = scratch.join("f");
+    fs::write(&file, "f").unwrap();
+
+    let mut child = Command::new(env!("CARGO_BIN_EXE_sortfs"))
+        .current_dir(&main)
+        .arg("--stdin")
+        .arg("--action").arg(format!("copy:{}", dest.display()))
+        .arg("--yes")
+        .stdin(Stdio::piped())
+        .stdout(Stdio::null())
+        .stderr(Stdio::null())
+        .spawn()
+        .unwrap();
+    writeln!(child.stdin.take().unwrap(), "{}", file.display()).unwrap();
+    let status = child.wait().unwrap();
+
+    assert!(!status.success());
+    assert_eq!(fs::read_to_string(&file).unwrap(), "f");
+    assert!(!dest.exists());
+    fs::remove_dir_all(&scratch).unwrap();
+}
+
+#[test]
+fn copy_refuses_its_own_source() {
+    let root = scratch_dir("copy-self");
+    fs::write(root.join("f"), "f").unwrap();
+
+    let output = Command::new(env!("CARGO_BIN_EXE_sortfs"))
+        .arg(&root)
+        .arg("--action").arg(format!("copy:{}", root.display()))
+        .arg("--yes")
+        .output()
+        .unwrap();
+
+    assert!(!output.status.success());
+    assert_eq!(fs::read_to_string(root.join("f")).unwrap(), "f");
+    fs::remove_dir_all(&root).unwrap();
+}
+
+#[cfg(all(unix, not(target_os = "macos")))]
+#[test]
+fn trash_keeps_emptied_directories_restorable() {
+    let scratch = scratch_dir("trash");
+    let (root, data) = (scratch.join("root"), scratch.join("data"));
+    let old_dir = root.join("old");
+    fs::create_dir_all(&old_dir).unwrap();
+    fs::create_dir_all(&data).unwrap();
+    fs::write(old_dir.join("old.txt"), "old").unwrap();
+    age(&old_dir.join("old.txt"), 800);
+    age(&old_dir, 800);
+
+    let output = Command::new(env!("CARGO_BIN_EXE_sortfs"))
+        .env("XDG_DATA_HOME", &data)
+        .arg(&root)
+        .args(["--changed-before", "1y", "--action", "trash", "--yes"])
+        .output()
+        .unwrap();
+
+    assert!(output.status.success());
+    assert!(!old_dir.exists());
+    let trashed = data.join("Trash/files");
+    assert!(trashed.join("old.txt").is_file());
+    assert!(trashed.join("old").is_dir());
+    assert!(data.join("Trash/info/old.trashinfo").is_file());
+    fs::remove_dir_all(&scratch).unwrap();
+}
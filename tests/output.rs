@@ -0,0 +1,52 @@
+use std::fs;
+use std::path::PathBuf;
+use std::process::{Command, Output};
+
+fn scratch_dir(name: &str) -> PathBuf {
+    let dir = std::env::temp_dir().join(format!("sortfs-{}-{}", name, std::process::id()));
+    let _ = fs::remove_dir_all(&dir);
+    fs::create_dir_all(&dir).unwrap();
+    dir
+}
+
+fn sortfs(root: &PathBuf, args: &[&str]) -> Output {
+    Command::new(env!("CARGO_BIN_EXE_sortfs"))
+        .current_dir(root)
+        .args(args)
+        .output()
+        .unwrap()
+}
+
+#[test]
+fn labels_and_content_reach_every_format() {
+    let root = scratch_dir("output-labels");
+    fs::create_dir(root.join("tree")).unwrap();
+    fs::write(root.join("tree/notes.txt"), "notes\n").unwrap();
+    fs::write(root.join("labels.tsv"), "notes.txt\tkeep, please\n").unwrap();
+    let labels = root.join("labels.tsv");
+    let labels = labels.to_str().unwrap();
+
+    let output = sortfs(&root.join("tree"), &["--annotate", labels, "--detect-text", "--output", "jsonl"]);
+    assert!(output.status.success());
+    let line = String::from_utf8(output.stdout).unwrap();
+    assert!(line.contains(r#""content":"text","label":"keep, please"}"#), "{}", line);
+
+    let output = sortfs(&root.join("tree"), &["--annotate", labels, "--detect-text", "--output", "csv", "--columns", "path,label,content"]);
+    assert_eq!(String::from_utf8(output.stdout).unwrap(), "notes.txt,\"keep, please\",text\n");
+
+    let output = sortfs(&root.join("tree"), &["--annotate", labels, "--output", "tsv", "--columns", "path"]);
+    assert_eq!(String::from_utf8(output.stdout).unwrap(), "notes.txt\n");
+    fs::remove_dir_all(&root).unwrap();
+}
+
+#[test]
+fn columns_and_formats_that_cant_show_labels_are_refused() {
+    let root = scratch_dir("output-refused");
+    fs::write(root.join("labels.tsv"), "a\tb\n").unwrap();
+
+    assert!(!sortfs(&root, &["--output", "tsv", "--columns", "path,label"]).status.success());
+    assert!(!sortfs(&root, &["--output", "csv", "--columns", "content"]).status.success());
+    assert!(!sortfs(&root, &["--annotate", "labels.tsv", "--output", "porcelain"]).status.success());
+    assert!(!sortfs(&root, &["--detect-text", "--output", "porcelain"]).status.success());
+    fs::remove_dir_all(&root).unwrap();
+}
@@ -0,0 +1,32 @@
+use std::fs::{self, File};
+use std::path::PathBuf;
+use std::process::Command;
+use std::time::{Duration, UNIX_EPOCH};
+
+// 2024-01-01 12:00 UTC
+const MODIFIED: u64 = 1_704_110_400;
+
+fn scratch_dir(name: &str) -> PathBuf {
+    let dir = std::env::temp_dir().join(format!("sortfs-{}-{}", name, std::process::id()));
+    let _ = fs::remove_dir_all(&dir);
+    fs::create_dir_all(&dir).unwrap();
+    dir
+}
+
+#[test]
+fn age_counts_from_now_option() {
+    let root = scratch_dir("age-now");
+    let file = root.join("file");
+    fs::write(&file, "").unwrap();
+    File::open(&file).unwrap().set_modified(UNIX_EPOCH + Duration::from_secs(MODIFIED)).unwrap();
+
+    let output = Command::new(env!("CARGO_BIN_EXE_sortfs"))
+        .arg(&root)
+        .args(["--age", "--now", &format!("@{}", MODIFIED + 2 * 24 * 60 * 60)])
+        .output()
+        .unwrap();
+
+    assert!(output.status.success());
+    assert_eq!(String::from_utf8_lossy(&output.stdout).trim(), "2d file");
+    fs::remove_dir_all(&root).unwrap();
+}